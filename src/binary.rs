@@ -13,15 +13,28 @@
 pub(crate) mod errors;
 mod header;
 mod header_fields;
+mod kdb;
 mod kdbx;
+#[cfg(all(unix, feature = "mmap"))]
+mod mmap;
 mod read;
 mod variant_dict;
 
-pub use header::{InnerHeaderId, KdbxHeader, KdbxInnerHeader, OuterHeaderId};
+pub use header::{InnerBinary, InnerHeaderId, KdbxHeader, KdbxInnerHeader, OuterHeaderId};
 pub use header_fields::{
     Cipher, CompressionType, InnerStreamCipherAlgorithm, KdfAlgorithm, KdfParams,
 };
-pub(crate) use header_fields::{KDBX_MAGIC_NUMBER, KEEPASS_MAGIC_NUMBER};
-pub use kdbx::{FailedUnlock, Kdbx, Locked, Unlocked};
-pub use read::{from_reader, open};
+pub(crate) use header_fields::{KDBX_MAGIC_NUMBER, KDB_MAGIC_NUMBER, KEEPASS_MAGIC_NUMBER};
+pub use kdb::{Kdb, KdbHeader};
+pub use kdbx::{
+    FailedUnlock, FieldLengthPolicy, Kdbx, Locked, SaveOptions, UnlockOptions, Unlocked,
+};
+#[cfg(feature = "tokio")]
+pub use read::from_async_reader;
+#[cfg(all(unix, feature = "mmap"))]
+pub use read::open_mmap;
+pub use read::{
+    any_from_reader, detect_format, detect_format_slice, from_bytes, from_reader, open, open_any,
+    AnyDatabase, Format, OpenWarning,
+};
 pub use variant_dict::{Value as VariantDictValue, VariantDict, VariantParseError};
@@ -2,6 +2,7 @@ use super::header;
 use super::header_fields;
 use crate::crypto;
 use crate::stream::random::InnerStreamError;
+use crate::stream::{StageError, WriteStage};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -48,6 +49,18 @@ pub enum UnlockError {
     /// The inner header is invalid
     #[error("Corrupt database. XML data is invald - {0}")]
     InvalidXml(#[from] crate::errors::XmlReadError),
+    /// The decompressed database is larger than the limit set when unlocking
+    #[error("Decompressed database is larger than the limit of {0} bytes")]
+    DecompressedTooLarge(usize),
+    /// The hash of the decrypted KDB content did not match. This indicates an invalid password or corrupt DB
+    #[error("Content hash validation failed - wrong password or corrupt database")]
+    ContentHashInvalid,
+    /// A group or entry record in a KDB file is malformed
+    #[error("Corrupt database. KDB record is invalid - {0}")]
+    InvalidKdbRecord(String),
+    /// The key file could not be read
+    #[error("Could not read key file - {0}")]
+    KeyFile(std::io::Error),
 }
 
 #[derive(Debug, Error)]
@@ -74,8 +87,38 @@ pub enum HeaderError {
     /// The database cipher is not supported by this library.
     #[error("Incompatible database - Unknown cipher {0:?}")]
     UnknownCipher(uuid::Uuid),
+    /// The KDB encryption flags do not name a cipher supported by this library.
+    #[error("Incompatible database - Unsupported KDB encryption flags {0:#x}")]
+    UnsupportedKdbFlags(u32),
+    /// A header field length runs past the end of the data
+    #[error("Corrupt database - Header field length exceeds the available data")]
+    FieldOverrun,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Header settings that cannot be stored in a KDBX 3.1 database
+pub struct Incompatibility {
+    /// The outer cipher, if KDBX 3.1 does not support it
+    pub cipher: Option<header_fields::Cipher>,
+    /// The key derivation function, if KDBX 3.1 does not support it
+    pub kdf: Option<header_fields::KdfAlgorithm>,
+}
+
+impl std::fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut settings = Vec::new();
+        if let Some(cipher) = &self.cipher {
+            settings.push(format!("cipher {:?}", cipher));
+        }
+        if let Some(kdf) = &self.kdf {
+            settings.push(format!("KDF {:?}", kdf));
+        }
+        write!(f, "KDBX 3.1 does not support {}", settings.join(" or "))
+    }
+}
+
+impl std::error::Error for Incompatibility {}
+
 #[derive(Debug, Error)]
 /// Errors encountered writing a database
 pub enum WriteError {
@@ -91,4 +134,50 @@ pub enum WriteError {
     /// The inner stream is invalid
     #[error("Cannot create inner stream to write protected values - {0}")]
     InnerStream(#[from] InnerStreamError),
+    /// Compressing the database failed
+    #[error("Error compressing database - {0}")]
+    Compression(std::io::Error),
+    /// Encrypting the database failed
+    #[error("Error encrypting database - {0}")]
+    Cipher(std::io::Error),
+    /// Writing the HMAC verified data blocks failed
+    #[error("Error writing HMAC data blocks - {0}")]
+    Hmac(std::io::Error),
+    /// The header cannot be represented in the database's format version
+    #[error("Error writing database header - {0}")]
+    InvalidHeader(#[from] HeaderError),
+    /// The cipher or KDF cannot be used with the database's format version
+    #[error("Incompatible database settings - {0}")]
+    Incompatible(#[from] Incompatibility),
+    /// A field value is longer than the maximum set with `Kdbx::set_max_field_length`
+    #[error("Field {key} of entry {entry_uuid} is {len} bytes, more than the limit of {max_len}")]
+    FieldTooLong {
+        /// Identifier of the entry containing the field
+        entry_uuid: uuid::Uuid,
+        /// Name of the field
+        key: String,
+        /// Length of the field value in bytes
+        len: usize,
+        /// Maximum allowed length in bytes
+        max_len: usize,
+    },
+}
+
+impl WriteError {
+    /// Convert an error from the encryption pipeline into the variant for the stage it failed in
+    pub(crate) fn from_pipeline(e: std::io::Error) -> WriteError {
+        if !e
+            .get_ref()
+            .map(|inner| inner.is::<StageError>())
+            .unwrap_or(false)
+        {
+            return WriteError::Io(e);
+        }
+        let stage_error = *e.into_inner().unwrap().downcast::<StageError>().unwrap();
+        match stage_error.stage {
+            WriteStage::Compression => WriteError::Compression(stage_error.source),
+            WriteStage::Cipher => WriteError::Cipher(stage_error.source),
+            WriteStage::Hmac => WriteError::Hmac(stage_error.source),
+        }
+    }
 }
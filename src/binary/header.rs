@@ -1,7 +1,9 @@
 use super::errors::HeaderError as Error;
+use super::errors::Incompatibility;
 use super::header_fields;
 use super::variant_dict;
 use crate::crypto;
+use crate::database::{Binary, BinaryPool};
 use crate::utils;
 use rand::{rngs::OsRng, RngCore};
 use sha2::{Digest, Sha256};
@@ -142,6 +144,15 @@ impl HeaderId for InnerHeaderId {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A binary stored in the KDBX4 inner header, such as an attachment's contents
+pub struct InnerBinary {
+    /// Whether the binary should be kept protected in memory
+    pub protected: bool,
+    /// Contents of the binary
+    pub data: Vec<u8>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HeaderField<T> {
     ty: T,
@@ -205,6 +216,39 @@ where
     }
 }
 
+/// Find the major version whose header field length width fits the data
+///
+/// The declared version is preferred. Returns `None` if reading the fields
+/// with either width would overrun the data before the end of the header.
+pub(crate) fn fitting_field_version(data: &[u8], major_version: u16) -> Option<u16> {
+    let other_version = if major_version >= 4 { 3 } else { 4 };
+    [major_version, other_version]
+        .iter()
+        .copied()
+        .find(|version| fields_fit(data, *version))
+}
+
+fn fields_fit(mut data: &[u8], major_version: u16) -> bool {
+    let len_width = if major_version >= 4 { 4 } else { 2 };
+    loop {
+        if data.len() < 1 + len_width {
+            return false;
+        }
+        let is_final = OuterHeaderId::from(data[0]).is_final();
+        let mut len_buffer = [0u8; 4];
+        len_buffer[..len_width].copy_from_slice(&data[1..1 + len_width]);
+        let len = u32::from_le_bytes(len_buffer) as usize;
+        data = &data[1 + len_width..];
+        if len > data.len() {
+            return false;
+        }
+        data = &data[len..];
+        if is_final {
+            return true;
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct KdbxHeaderBuilder {
     pub cipher: Option<header_fields::Cipher>,
@@ -339,6 +383,25 @@ pub struct KdbxHeader {
 }
 
 impl KdbxHeader {
+    /// Plugin data stored unencrypted in the header, if present and valid
+    pub fn public_custom_data(&self) -> Option<variant_dict::VariantDict> {
+        let field = self
+            .other_headers
+            .iter()
+            .find(|h| h.ty == OuterHeaderId::PublicCustomData)?;
+        variant_dict::parse_variant_dict(&*field.data).ok()
+    }
+
+    /// Replace the plugin data stored unencrypted in the header
+    pub fn set_public_custom_data(&mut self, data: &variant_dict::VariantDict) {
+        let mut buf = Vec::new();
+        variant_dict::write_variant_dict(&mut buf, data).unwrap();
+        self.other_headers
+            .retain(|h| h.ty != OuterHeaderId::PublicCustomData);
+        self.other_headers
+            .push(HeaderField::new(OuterHeaderId::PublicCustomData, buf));
+    }
+
     /// Create a new header to encrypt a database with keys from the OS Secure RNG.
     ///
     /// Under the hood this uses the [`rand`] crate to access the [`OsRng`],
@@ -352,14 +415,15 @@ impl KdbxHeader {
     /// [`rand`]: https://docs.rs/rand/
     /// [`OsRng`]: https://docs.rs/rand/0.7/rand/rngs/struct.OsRng.html
     pub fn from_os_random() -> KdbxHeader {
+        let cipher = header_fields::Cipher::Aes256;
         let mut master_seed = vec![0u8; 32];
-        let mut encryption_iv = vec![0u8; 16];
+        let mut encryption_iv = vec![0u8; cipher.iv_len().unwrap_or(16)];
         let mut cipher_salt = vec![0u8; 32];
         OsRng.fill_bytes(&mut master_seed);
         OsRng.fill_bytes(&mut encryption_iv);
         OsRng.fill_bytes(&mut cipher_salt);
         KdbxHeader {
-            cipher: header_fields::Cipher::Aes256,
+            cipher,
             kdf_params: header_fields::KdfParams::Argon2 {
                 variant: argon2::Variant::Argon2d,
                 iterations: 10,
@@ -379,9 +443,10 @@ impl KdbxHeader {
     pub(crate) fn read<R: Read>(
         mut caching_reader: utils::CachingReader<R>,
         major_version: u16,
+        field_version: u16,
     ) -> Result<(KdbxHeader, Vec<u8>)> {
         let mut header_builder = KdbxHeaderBuilder::default();
-        let headers = HeaderParser::new(&mut caching_reader).read_all_headers(major_version)?;
+        let headers = HeaderParser::new(&mut caching_reader).read_all_headers(field_version)?;
         for header in headers {
             header_builder.add_header(header)?;
         }
@@ -431,6 +496,118 @@ impl KdbxHeader {
         }
         Ok(())
     }
+
+    /// Check the cipher and KDF can be stored in a KDBX 3.1 database
+    ///
+    /// KDBX 3.1 only supports the AES KDF and the AES and TwoFish ciphers.
+    pub fn is_v3_compatible(&self) -> std::result::Result<(), Incompatibility> {
+        let mut incompatibility = Incompatibility::default();
+        if !matches!(
+            self.cipher,
+            header_fields::Cipher::Aes128
+                | header_fields::Cipher::Aes256
+                | header_fields::Cipher::TwoFish
+        ) {
+            incompatibility.cipher = Some(self.cipher);
+        }
+        if !matches!(self.kdf_params, header_fields::KdfParams::Aes { .. }) {
+            incompatibility.kdf = Some(self.kdf_params.algorithm());
+        }
+        if incompatibility == Incompatibility::default() {
+            Ok(())
+        } else {
+            Err(incompatibility)
+        }
+    }
+
+    /// Write the header in the KDBX 3.1 layout
+    ///
+    /// The KDF settings and inner stream settings are stored in the legacy
+    /// header fields, and field lengths are 16 bits rather than 32.
+    pub(crate) fn write_legacy<W: Write>(
+        &self,
+        mut writer: W,
+        inner_header: &KdbxInnerHeader,
+    ) -> Result<()> {
+        use std::iter::once;
+        let (rounds, salt) = match &self.kdf_params {
+            header_fields::KdfParams::Aes { rounds, salt } => (*rounds, salt.clone()),
+            _ => {
+                return Err(Error::MalformedField(
+                    OuterHeaderId::KdfParameters,
+                    "KDBX3 databases require the AES KDF".into(),
+                ))
+            }
+        };
+        let stream_start_bytes = self
+            .stream_start_bytes
+            .clone()
+            .ok_or(Error::MissingRequiredField(OuterHeaderId::StreamStartBytes))?;
+        let headers = self
+            .other_headers
+            .iter()
+            .filter(|h| {
+                !matches!(
+                    h.ty,
+                    OuterHeaderId::LegacyTransformSeed
+                        | OuterHeaderId::LegacyTransformRounds
+                        | OuterHeaderId::ProtectedStreamKey
+                        | OuterHeaderId::StreamStartBytes
+                        | OuterHeaderId::InnerRandomStreamId
+                        | OuterHeaderId::KdfParameters
+                )
+            })
+            .cloned()
+            .chain(once(self.cipher.into()))
+            .chain(once(self.compression_type.into()))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::MasterSeed,
+                self.master_seed.clone(),
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::LegacyTransformSeed,
+                salt,
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::LegacyTransformRounds,
+                rounds.to_le_bytes().to_vec(),
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::EncryptionIv,
+                self.encryption_iv.clone(),
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::ProtectedStreamKey,
+                inner_header.inner_stream_key.clone(),
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::StreamStartBytes,
+                stream_start_bytes,
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::InnerRandomStreamId,
+                u32::from(inner_header.inner_stream_cipher)
+                    .to_le_bytes()
+                    .to_vec(),
+            )))
+            .chain(once(HeaderField::new(
+                OuterHeaderId::EndOfHeader,
+                Vec::new(),
+            )));
+
+        for header in headers {
+            if header.data.len() > u16::MAX as usize {
+                return Err(Error::MalformedField(
+                    header.ty,
+                    "Field too large for a KDBX3 header".into(),
+                ));
+            }
+            writer.write_all(&[header.ty.into()])?;
+            writer.write_all(&(header.data.len() as u16).to_le_bytes())?;
+            writer.write_all(&header.data)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Default)]
@@ -472,7 +649,7 @@ impl KdbxInnerHeaderBuilder {
 }
 
 /// Encrypted database information and custom data
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct KdbxInnerHeader {
     /// Cipher identifier for data encrypted in memory
     pub inner_stream_cipher: header_fields::InnerStreamCipherAlgorithm,
@@ -534,6 +711,61 @@ impl KdbxInnerHeader {
         }
     }
 
+    /// Attachment binaries stored in the other headers, in order
+    ///
+    /// Each binary header starts with a flags byte, where bit 0 marks it as protected.
+    pub fn binaries(&self) -> Vec<InnerBinary> {
+        self.other_headers
+            .iter()
+            .filter(|h| h.ty == InnerHeaderId::Binary)
+            .map(|header| InnerBinary {
+                protected: header.data.first().map_or(false, |flags| flags & 1 != 0),
+                data: header.data.get(1..).unwrap_or_default().to_vec(),
+            })
+            .collect()
+    }
+
+    /// Replace the attachment binaries stored in the other headers
+    pub fn set_binaries(&mut self, binaries: Vec<InnerBinary>) {
+        self.other_headers.retain(|h| h.ty != InnerHeaderId::Binary);
+        self.other_headers
+            .extend(binaries.into_iter().map(|binary| {
+                let mut header_data = Vec::with_capacity(binary.data.len() + 1);
+                header_data.push(u8::from(binary.protected));
+                header_data.extend(binary.data);
+                HeaderField::new(InnerHeaderId::Binary, header_data)
+            }));
+    }
+
+    /// Remove the attachment binaries from the other headers and decode them
+    pub(crate) fn take_binaries(&mut self) -> Vec<Binary> {
+        let binaries = self.binaries();
+        self.set_binaries(Vec::new());
+        binaries
+            .into_iter()
+            .enumerate()
+            .map(|(id, binary)| Binary {
+                id,
+                data: binary.data,
+                protected: binary.protected,
+            })
+            .collect()
+    }
+
+    /// Copy of this header with its attachment binaries replaced by those in `pool`
+    pub(crate) fn with_binaries(&self, pool: &BinaryPool) -> KdbxInnerHeader {
+        let mut header = self.clone();
+        header.set_binaries(
+            pool.iter()
+                .map(|(data, protected)| InnerBinary {
+                    data: data.to_vec(),
+                    protected,
+                })
+                .collect(),
+        );
+        header
+    }
+
     pub(crate) fn read<R: Read>(reader: &mut R, major_version: u16) -> Result<KdbxInnerHeader> {
         let mut header_builder = KdbxInnerHeaderBuilder::default();
         let headers = HeaderParser::new(reader).read_all_headers(major_version)?;
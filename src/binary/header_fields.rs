@@ -7,6 +7,7 @@ use uuid::Uuid;
 
 pub const KEEPASS_MAGIC_NUMBER: u32 = 0x9AA2_D903;
 pub const KDBX_MAGIC_NUMBER: u32 = 0xB54B_FB67;
+pub const KDB_MAGIC_NUMBER: u32 = 0xB54B_FB65;
 
 const AES128_UUID: &str = "61ab05a1-9464-41c3-8d74-3a563df8dd35";
 const AES256_UUID: &str = "31c1f2e6-bf71-4350-be58-05216afc5aff";
@@ -41,6 +42,19 @@ const CIPHER_TABLE: [(&str, Cipher); 4] = [
     (CHACHA20_UUID, Cipher::ChaCha20),
 ];
 
+impl Cipher {
+    /// Length in bytes of the encryption IV used by this cipher
+    ///
+    /// Returns `None` for ciphers unknown to this library.
+    pub fn iv_len(self) -> Option<usize> {
+        match self {
+            Cipher::Aes128 | Cipher::Aes256 | Cipher::TwoFish => Some(16),
+            Cipher::ChaCha20 => Some(12),
+            Cipher::Unknown(_) => None,
+        }
+    }
+}
+
 impl From<uuid::Uuid> for Cipher {
     fn from(uuid: uuid::Uuid) -> Cipher {
         utils::value_from_uuid_table(&CIPHER_TABLE, uuid).unwrap_or(Cipher::Unknown(uuid))
@@ -179,6 +193,100 @@ pub enum KdfParams {
     },
 }
 
+impl KdfParams {
+    /// The key derivation function these parameters are for
+    pub fn algorithm(&self) -> KdfAlgorithm {
+        match self {
+            KdfParams::Argon2 { variant, .. } => match variant {
+                argon2::Variant::Argon2id => KdfAlgorithm::Argon2id,
+                _ => KdfAlgorithm::Argon2d,
+            },
+            KdfParams::Aes { .. } => KdfAlgorithm::Aes256_Kdbx4,
+            KdfParams::Unknown { uuid, .. } => KdfAlgorithm::Unknown(*uuid),
+        }
+    }
+
+    /// Salt of the key derivation function, empty for unknown functions
+    pub(crate) fn seed(&self) -> &[u8] {
+        match self {
+            KdfParams::Argon2 { salt, .. } | KdfParams::Aes { salt, .. } => salt,
+            KdfParams::Unknown { .. } => &[],
+        }
+    }
+
+    /// Upper bound on iterations chosen by [`KdfParams::benchmark_argon2`]
+    pub const MAX_BENCHMARK_ITERATIONS: u64 = 1000;
+    /// Rounds used by [`Kdbx::set_major_version`][crate::Kdbx::set_major_version] when switching to AES
+    pub const DEFAULT_AES_ROUNDS: u64 = 60_000;
+
+    /// AES KDF parameters with the given number of rounds and a fresh random salt
+    ///
+    /// Uses OS randomness provided by the `rand` crate to generate the salt.
+    pub fn aes(rounds: u64) -> KdfParams {
+        use rand::{rngs::OsRng, RngCore};
+        let mut salt = vec![0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams::Aes { rounds, salt }
+    }
+
+    /// Argon2 parameters with the given work factors and a fresh random salt
+    ///
+    /// Uses OS randomness provided by the `rand` crate to generate the salt.
+    pub fn argon2(
+        memory_mib: u64,
+        iterations: u64,
+        lanes: u32,
+        variant: argon2::Variant,
+    ) -> KdfParams {
+        use rand::{rngs::OsRng, RngCore};
+        let mut salt = vec![0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams::Argon2 {
+            variant,
+            memory_bytes: memory_mib * 1024 * 1024,
+            version: 19,
+            salt,
+            lanes,
+            iterations,
+        }
+    }
+
+    /// Argon2 parameters tuned so key derivation takes roughly `target` on this machine
+    ///
+    /// Iterations are increased until a trial derivation with the given memory
+    /// and lanes takes at least `target`, up to a maximum of
+    /// [`KdfParams::MAX_BENCHMARK_ITERATIONS`]. If a trial derivation fails, for example
+    /// because the parameters are invalid, the iterations tried so far are used
+    /// and the error will be reported when setting a key.
+    pub fn benchmark_argon2(
+        memory_mib: u32,
+        lanes: u32,
+        target: std::time::Duration,
+        variant: argon2::Variant,
+    ) -> KdfParams {
+        let fallback = KdfParams::argon2(memory_mib.into(), 1, lanes, variant);
+        let key = match crate::CompositeKey::from_password("").composed(&[], &fallback, 4) {
+            Ok(key) => key,
+            Err(_) => return fallback,
+        };
+        let mut iterations = 1;
+        loop {
+            let params = KdfParams::argon2(memory_mib.into(), iterations, lanes, variant);
+            let start = std::time::Instant::now();
+            if key.master_key(&params).is_err() {
+                return params;
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= target || iterations >= Self::MAX_BENCHMARK_ITERATIONS {
+                return params;
+            }
+            let scale = target.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+            let estimate = (iterations as f64 * scale).ceil() as u64;
+            iterations = estimate.clamp(iterations + 1, Self::MAX_BENCHMARK_ITERATIONS);
+        }
+    }
+}
+
 impl TryFrom<VariantDict> for KdfParams {
     type Error = HeaderError;
     fn try_from(mut vdict: VariantDict) -> Result<Self, HeaderError> {
@@ -0,0 +1,373 @@
+//! Reading KeePass 1 (.kdb) databases
+//!
+//! KDB files are read only - an unlocked KDB file is converted into a
+//! [`Database`] that can then be saved as a KDBX file.
+
+use super::errors::{HeaderError, OpenError, UnlockError};
+use super::{header_fields, KdfParams};
+use crate::crypto;
+use crate::database::{Database, Entry, Field, Group, Times};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use uuid::Uuid;
+
+const KDB_HEADER_SIZE: usize = 124;
+/// Size of a record holding only its end marker field
+const MIN_RECORD_SIZE: usize = 6;
+const KDB_VERSION: u32 = 0x0003_0004;
+const KDB_VERSION_MASK: u32 = 0xFFFF_FF00;
+
+const FLAG_RIJNDAEL: u32 = 2;
+const FLAG_TWOFISH: u32 = 8;
+
+const FIELD_END: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Unencrypted header of a KeePass 1 database
+pub struct KdbHeader {
+    /// Flags identifying the cipher used
+    pub flags: u32,
+    /// KDB file format version
+    pub version: u32,
+    /// Seed combined with the transformed key to create the cipher key
+    pub master_seed: Vec<u8>,
+    /// IV for the database cipher
+    pub encryption_iv: Vec<u8>,
+    /// Number of group records in the encrypted data
+    pub group_count: u32,
+    /// Number of entry records in the encrypted data
+    pub entry_count: u32,
+    /// SHA-256 hash of the decrypted data
+    pub contents_hash: Vec<u8>,
+    /// AES key used to transform the composite key
+    pub transform_seed: Vec<u8>,
+    /// Number of AES rounds used to transform the composite key
+    pub key_rounds: u32,
+}
+
+impl KdbHeader {
+    /// Cipher used to encrypt the database contents
+    pub fn cipher(&self) -> Option<header_fields::Cipher> {
+        if self.flags & FLAG_RIJNDAEL != 0 {
+            Some(header_fields::Cipher::Aes256)
+        } else if self.flags & FLAG_TWOFISH != 0 {
+            Some(header_fields::Cipher::TwoFish)
+        } else {
+            None
+        }
+    }
+
+    fn read(data: &[u8; KDB_HEADER_SIZE]) -> Result<KdbHeader, OpenError> {
+        let u32_at = |idx: usize| {
+            u32::from_le_bytes([data[idx], data[idx + 1], data[idx + 2], data[idx + 3]])
+        };
+        if u32_at(0) != super::KEEPASS_MAGIC_NUMBER {
+            return Err(OpenError::NonKeepassFormat);
+        }
+        if u32_at(4) != super::KDB_MAGIC_NUMBER {
+            return Err(OpenError::UnsupportedFileFormat);
+        }
+        let version = u32_at(12);
+        if version & KDB_VERSION_MASK != KDB_VERSION & KDB_VERSION_MASK {
+            return Err(OpenError::UnsupportedMajorVersion((version >> 16) as u16));
+        }
+        let header = KdbHeader {
+            flags: u32_at(8),
+            version,
+            master_seed: data[16..32].to_vec(),
+            encryption_iv: data[32..48].to_vec(),
+            group_count: u32_at(48),
+            entry_count: u32_at(52),
+            contents_hash: data[56..88].to_vec(),
+            transform_seed: data[88..120].to_vec(),
+            key_rounds: u32_at(120),
+        };
+        if header.cipher().is_none() {
+            return Err(HeaderError::UnsupportedKdbFlags(header.flags).into());
+        }
+        Ok(header)
+    }
+}
+
+/// A locked KeePass 1 database
+pub struct Kdb {
+    header: KdbHeader,
+    encrypted_data: Vec<u8>,
+}
+
+impl Kdb {
+    /// Read a KDB database from a input stream
+    pub fn from_reader<R: Read>(mut input: R) -> Result<Kdb, OpenError> {
+        let mut header_data = [0u8; KDB_HEADER_SIZE];
+        input.read_exact(&mut header_data)?;
+        let header = KdbHeader::read(&header_data)?;
+        let mut encrypted_data = Vec::new();
+        input.read_to_end(&mut encrypted_data)?;
+        Ok(Kdb {
+            header,
+            encrypted_data,
+        })
+    }
+
+    /// Read a KDB database from a given path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Kdb, OpenError> {
+        let mut file = File::open(path.as_ref())?;
+        Kdb::from_reader(&mut file)
+    }
+
+    /// Unencrypted database configuration
+    pub fn header(&self) -> &KdbHeader {
+        &self.header
+    }
+
+    /// Decrypt the database and convert it to the KDBX database model
+    pub fn unlock(&self, key: &crypto::CompositeKey) -> Result<Database, UnlockError> {
+        let kdf_params = KdfParams::Aes {
+            rounds: self.header.key_rounds.into(),
+            salt: self.header.transform_seed.clone(),
+        };
+        let master_key = key.composed_kdb()?.master_key(&kdf_params)?;
+        let cipher_key = master_key.cipher_key(&self.header.master_seed);
+        // Checked when reading the header
+        let cipher = self.header.cipher().unwrap();
+
+        let mut decrypted = Vec::new();
+        crate::stream::decryption_stream(
+            &*self.encrypted_data,
+            cipher_key,
+            cipher,
+            &self.header.encryption_iv,
+        )?
+        .read_to_end(&mut decrypted)
+        .map_err(|_| UnlockError::ContentHashInvalid)?;
+
+        if !crypto::verify_sha256(&decrypted, &self.header.contents_hash) {
+            return Err(UnlockError::ContentHashInvalid);
+        }
+
+        // The counts are not covered by the contents hash, so capacity is
+        // limited by how many of the smallest records could fit in the data
+        let max_records = decrypted.len() / MIN_RECORD_SIZE;
+        let mut reader = RecordReader::new(&decrypted);
+        let mut groups = Vec::with_capacity(max_records.min(self.header.group_count as usize));
+        for _ in 0..self.header.group_count {
+            groups.push(reader.read_group()?);
+        }
+        let mut entries = Vec::with_capacity(max_records.min(self.header.entry_count as usize));
+        for _ in 0..self.header.entry_count {
+            entries.push(reader.read_entry()?);
+        }
+        Ok(build_database(groups, entries))
+    }
+}
+
+struct KdbGroup {
+    id: u32,
+    level: u16,
+    group: Group,
+}
+
+struct KdbEntry {
+    group_id: u32,
+    binary_desc: String,
+    entry: Entry,
+}
+
+impl KdbEntry {
+    /// Whether this entry stores KeePass 1 client settings rather than user data
+    fn is_meta_stream(&self) -> bool {
+        self.binary_desc == "bin-stream"
+            && self.entry.title() == Some("Meta-Info")
+            && self.entry.username() == Some("SYSTEM")
+            && self.entry.url() == Some("$")
+    }
+}
+
+/// Reader for the type-length-value field records of KDB groups and entries
+struct RecordReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> RecordReader<'a> {
+    fn new(data: &'a [u8]) -> RecordReader<'a> {
+        RecordReader { data }
+    }
+
+    fn next_field(&mut self) -> Result<(u16, &'a [u8]), UnlockError> {
+        if self.data.len() < 6 {
+            return Err(UnlockError::InvalidKdbRecord(
+                "Unexpected end of data".into(),
+            ));
+        }
+        let ty = u16::from_le_bytes([self.data[0], self.data[1]]);
+        let len = u32::from_le_bytes([self.data[2], self.data[3], self.data[4], self.data[5]]);
+        let len = len as usize;
+        if self.data.len() - 6 < len {
+            return Err(UnlockError::InvalidKdbRecord(format!(
+                "Field of type {:#x} is longer than the remaining data",
+                ty
+            )));
+        }
+        let value = &self.data[6..6 + len];
+        self.data = &self.data[6 + len..];
+        Ok((ty, value))
+    }
+
+    fn read_group(&mut self) -> Result<KdbGroup, UnlockError> {
+        let mut id = None;
+        let mut level = 0;
+        let mut group = Group::default();
+        loop {
+            match self.next_field()? {
+                (FIELD_END, _) => break,
+                (0x0001, value) => id = Some(read_u32(value)?),
+                (0x0002, value) => group.set_name(read_string(value)),
+                (0x0003, value) => group.times_mut().creation_time = read_time(value)?,
+                (0x0004, value) => group.times_mut().last_modification_time = read_time(value)?,
+                (0x0005, value) => group.times_mut().last_access_time = read_time(value)?,
+                (0x0006, value) => set_expiry(group.times_mut(), read_time(value)?),
+                (0x0008, value) => level = read_u16(value)?,
+                _ => {}
+            }
+        }
+        let id = id.ok_or_else(|| UnlockError::InvalidKdbRecord("Group has no ID".into()))?;
+        Ok(KdbGroup { id, level, group })
+    }
+
+    fn read_entry(&mut self) -> Result<KdbEntry, UnlockError> {
+        let mut group_id = None;
+        let mut binary_desc = String::new();
+        let mut binary_data = Vec::new();
+        let mut entry = Entry::default();
+        loop {
+            match self.next_field()? {
+                (FIELD_END, _) => break,
+                (0x0001, value) => entry.set_uuid(
+                    Uuid::from_slice(value)
+                        .map_err(|_| UnlockError::InvalidKdbRecord("Invalid entry UUID".into()))?,
+                ),
+                (0x0002, value) => group_id = Some(read_u32(value)?),
+                (0x0004, value) => entry.set_title(read_string(value)),
+                (0x0005, value) => entry.set_url(read_string(value)),
+                (0x0006, value) => entry.set_username(read_string(value)),
+                (0x0007, value) => entry.set_password(read_string(value)),
+                (0x0008, value) => entry.add_field(Field::new("Notes", &read_string(value))),
+                (0x0009, value) => entry.times_mut().creation_time = read_time(value)?,
+                (0x000A, value) => entry.times_mut().last_modification_time = read_time(value)?,
+                (0x000B, value) => entry.times_mut().last_access_time = read_time(value)?,
+                (0x000C, value) => set_expiry(entry.times_mut(), read_time(value)?),
+                (0x000D, value) => binary_desc = read_string(value),
+                (0x000E, value) => binary_data = value.to_vec(),
+                _ => {}
+            }
+        }
+        // KDB entries hold at most one attachment, named by its description
+        if !binary_desc.is_empty() {
+            entry.add_attachment(&binary_desc, binary_data);
+        }
+        let group_id =
+            group_id.ok_or_else(|| UnlockError::InvalidKdbRecord("Entry has no group".into()))?;
+        Ok(KdbEntry {
+            group_id,
+            binary_desc,
+            entry,
+        })
+    }
+}
+
+fn read_u32(value: &[u8]) -> Result<u32, UnlockError> {
+    match value {
+        [a, b, c, d] => Ok(u32::from_le_bytes([*a, *b, *c, *d])),
+        _ => Err(UnlockError::InvalidKdbRecord(
+            "Expected a 4 byte integer".into(),
+        )),
+    }
+}
+
+fn read_u16(value: &[u8]) -> Result<u16, UnlockError> {
+    match value {
+        [a, b] => Ok(u16::from_le_bytes([*a, *b])),
+        _ => Err(UnlockError::InvalidKdbRecord(
+            "Expected a 2 byte integer".into(),
+        )),
+    }
+}
+
+/// Read a KDB timestamp, packed into 5 bytes as year, month, day, hour, minute and second
+fn read_time(value: &[u8]) -> Result<NaiveDateTime, UnlockError> {
+    let invalid = || UnlockError::InvalidKdbRecord("Invalid timestamp".into());
+    let (b0, b1, b2, b3, b4) = match value {
+        [b0, b1, b2, b3, b4] => (
+            u32::from(*b0),
+            u32::from(*b1),
+            u32::from(*b2),
+            u32::from(*b3),
+            u32::from(*b4),
+        ),
+        _ => return Err(invalid()),
+    };
+    let year = (b0 << 6) | (b1 >> 2);
+    let month = ((b1 & 0x03) << 2) | (b2 >> 6);
+    let day = (b2 >> 1) & 0x1F;
+    let hour = ((b2 & 0x01) << 4) | (b3 >> 4);
+    let minute = ((b3 & 0x0F) << 2) | (b4 >> 6);
+    let second = b4 & 0x3F;
+    NaiveDate::from_ymd_opt(year as i32, month, day)
+        .and_then(|date| date.and_hms_opt(hour, minute, second))
+        .ok_or_else(invalid)
+}
+
+/// Apply a KDB expiry time, where a date in 2999 means the item never expires
+fn set_expiry(times: &mut Times, expiry: NaiveDateTime) {
+    times.expires = expiry < never_expires();
+    if times.expires {
+        times.expiry_time = expiry;
+    }
+}
+
+fn never_expires() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2999, 12, 28)
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .unwrap()
+}
+
+fn read_string(value: &[u8]) -> String {
+    let value = value.strip_suffix(&[0]).unwrap_or(value);
+    String::from_utf8_lossy(value).into_owned()
+}
+
+fn build_database(mut groups: Vec<KdbGroup>, entries: Vec<KdbEntry>) -> Database {
+    let mut database = Database::default();
+    for kdb_entry in entries {
+        if kdb_entry.is_meta_stream() {
+            continue;
+        }
+        match groups.iter_mut().find(|g| g.id == kdb_entry.group_id) {
+            Some(kdb_group) => kdb_group.group.add_entry(kdb_entry.entry),
+            // Keep entries whose group is missing rather than losing them
+            None => database.add_entry(kdb_entry.entry),
+        }
+    }
+
+    // Each group is a child of the closest preceding group one level up
+    let mut parents: Vec<KdbGroup> = Vec::new();
+    for kdb_group in groups {
+        close_groups(&mut parents, &mut database, kdb_group.level);
+        parents.push(kdb_group);
+    }
+    close_groups(&mut parents, &mut database, 0);
+    database
+}
+
+/// Attach groups at or below `level` to their parents, as no more children can follow
+fn close_groups(parents: &mut Vec<KdbGroup>, database: &mut Database, level: u16) {
+    while parents.last().map(|g| g.level >= level).unwrap_or(false) {
+        let finished = parents.pop().unwrap();
+        match parents.last_mut() {
+            Some(parent) => parent.group.add_group(finished.group),
+            None => database.add_group(finished.group),
+        }
+    }
+}
@@ -1,7 +1,14 @@
-use super::{errors, header};
-use crate::{crypto, database, stream};
+use super::{errors, header, KdfParams};
+use crate::stream::random::InnerStreamKey;
+use crate::xml::parse::{parse_xml_protected, ProtectedValues};
+use crate::{crypto, database, stream, utils};
+use std::borrow::Cow;
+use std::fs;
 use std::io::{Read, Write};
 use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub trait KdbxState: std::fmt::Debug {
     fn header(&self) -> &header::KdbxHeader;
@@ -56,6 +63,208 @@ impl<T: KdbxState> Kdbx<T> {
         self.state.write(output)?;
         Ok(())
     }
+
+    /// Write this archive into a new buffer
+    pub fn to_bytes(&self) -> Result<Vec<u8>, errors::WriteError> {
+        let mut output = Vec::new();
+        self.write(&mut output)?;
+        Ok(output)
+    }
+
+    /// Write this archive to a file at the given path
+    ///
+    /// The file is created or truncated, then written and flushed to disk.
+    /// See [`Kdbx::save_atomic`] to avoid losing the existing file if writing fails.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), errors::WriteError> {
+        self.save_with_options(
+            path,
+            &SaveOptions {
+                atomic: false,
+                ..SaveOptions::default()
+            },
+        )
+    }
+
+    /// Write this archive to a temporary file, then rename it over the given path
+    ///
+    /// A crash or error part way through leaves any existing file untouched.
+    /// The saved file keeps the permissions of the file it replaces, and a
+    /// new file is only readable by its owner.
+    pub fn save_atomic<P: AsRef<Path>>(&self, path: P) -> Result<(), errors::WriteError> {
+        self.save_with_options(path, &SaveOptions::default())
+    }
+
+    /// Write this archive to a file at the given path using the given options
+    pub fn save_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &SaveOptions,
+    ) -> Result<(), errors::WriteError> {
+        let path = path.as_ref();
+        if !options.atomic {
+            return self.write_file(fs::File::create(path)?, options.fsync);
+        }
+
+        let (file, temp_path) = create_temp_file(path)?;
+        let result = self
+            .write_file(file, options.fsync)
+            .and_then(|_| Ok(fs::rename(&temp_path, path)?));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            return result;
+        }
+
+        #[cfg(unix)]
+        if options.fsync {
+            let dir = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn write_file(&self, file: fs::File, fsync: bool) -> Result<(), errors::WriteError> {
+        let mut file = std::io::BufWriter::new(file);
+        self.write(&mut file)?;
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        if fsync {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Write this archive to the given async output stream
+    ///
+    /// The archive is encrypted into memory first, then written out and flushed.
+    pub async fn write_async<W: tokio::io::AsyncWrite + Unpin>(
+        &self,
+        mut output: W,
+    ) -> Result<(), errors::WriteError> {
+        use tokio::io::AsyncWriteExt;
+        let mut buffer = Vec::new();
+        self.state.write(&mut buffer)?;
+        output.write_all(&buffer).await?;
+        output.flush().await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Options controlling how a database is unlocked
+pub struct UnlockOptions {
+    /// Maximum memory in bytes the key derivation function may use
+    ///
+    /// Databases asking for more fail to unlock with
+    /// [`KeyGenerationError::MemoryLimitExceeded`][crate::errors::KeyGenerationError::MemoryLimitExceeded]
+    /// before any memory is allocated.
+    pub kdf_memory_limit: Option<u64>,
+    /// Maximum size in bytes of the decompressed database XML
+    ///
+    /// Decompression stops with [`UnlockError::DecompressedTooLarge`][crate::errors::UnlockError::DecompressedTooLarge]
+    /// as soon as the limit is passed.
+    pub max_decompressed: Option<usize>,
+    /// Options passed on when parsing the decrypted database XML
+    pub parse_options: crate::xml::ParseOptions,
+}
+
+/// Create a new, uniquely named file next to `path` for an atomic save
+///
+/// The file is only readable by its owner, or has the permissions of the
+/// file at `path` if one exists, so saving never exposes a database more
+/// widely than before. It is never an existing file or symlink.
+fn create_temp_file(path: &Path) -> std::io::Result<(fs::File, std::path::PathBuf)> {
+    let existing_permissions = fs::metadata(path).map(|m| m.permissions()).ok();
+    let mut attempts = 0;
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        temp_name.push(format!(".{:016x}.tmp", rand::random::<u64>()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let mut open_options = fs::OpenOptions::new();
+        open_options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut open_options, 0o600);
+        match open_options.open(&temp_path) {
+            Ok(file) => {
+                if let Some(permissions) = existing_permissions {
+                    if let Err(e) = file.set_permissions(permissions) {
+                        let _ = fs::remove_file(&temp_path);
+                        return Err(e);
+                    }
+                }
+                return Ok((file, temp_path));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < 16 => {
+                attempts += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Options controlling how a database is saved to disk
+pub struct SaveOptions {
+    /// Flush the file (and for atomic saves, its directory) to disk before returning
+    pub fsync: bool,
+    /// Write to a temporary file and rename it over the target, so a
+    /// failed save never leaves a partially written database
+    pub atomic: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> SaveOptions {
+        SaveOptions {
+            fsync: true,
+            atomic: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What to do with field values longer than [`Kdbx::set_max_field_length`] on write
+pub enum FieldLengthPolicy {
+    /// Write the value cut down to the maximum length
+    Truncate,
+    /// Fail the write with [`WriteError::FieldTooLong`][crate::errors::WriteError::FieldTooLong]
+    Error,
+}
+
+impl Default for FieldLengthPolicy {
+    fn default() -> FieldLengthPolicy {
+        FieldLengthPolicy::Truncate
+    }
+}
+
+/// Inner header, the bytes it was read from, and the decrypted XML that follows
+type DecryptedStream<'a> = (header::KdbxInnerHeader, Vec<u8>, Box<dyn Read + 'a>);
+
+/// Read a decrypted stream to the end, failing once it passes `max_len` bytes
+fn read_limited<R: Read>(
+    input_stream: R,
+    max_len: Option<usize>,
+    output_buffer: &mut Vec<u8>,
+) -> Result<(), errors::UnlockError> {
+    output_buffer.clear();
+    match max_len {
+        Some(limit) => {
+            input_stream
+                .take(limit as u64 + 1)
+                .read_to_end(output_buffer)?;
+            if output_buffer.len() > limit {
+                return Err(errors::UnlockError::DecompressedTooLarge(limit));
+            }
+        }
+        None => {
+            let mut input_stream = input_stream;
+            input_stream.read_to_end(output_buffer)?;
+        }
+    }
+    Ok(())
 }
 
 /// Represents a failed attempt at unlocking a database
@@ -82,6 +291,8 @@ pub struct Unlocked {
     pub(crate) header: header::KdbxHeader,
     /// Inner header data that is stored encrypted, not present on kdbx3
     pub(crate) inner_header: header::KdbxInnerHeader,
+    /// Inner header bytes as read on unlock, written back as is while the header is unchanged
+    pub(crate) inner_header_data: Option<Vec<u8>>,
     /// Major version of the database file format
     pub(crate) major_version: u16,
     /// Minor version of the database file format
@@ -92,34 +303,167 @@ pub struct Unlocked {
     pub(crate) master_key: Option<crypto::MasterKey>,
     /// Unencrypted unparsed XML data
     pub(crate) xml_data: Option<Vec<u8>>,
+    /// Time taken by the unlock that produced this database
+    pub(crate) unlock_duration: Option<Duration>,
+    /// Longest field value in bytes written without applying the policy
+    pub(crate) max_field_length: Option<usize>,
+    /// How to handle field values longer than the maximum length
+    pub(crate) field_length_policy: FieldLengthPolicy,
     /// Actual password database data
     pub(crate) database: crate::Database,
 }
 
+/// Parse the decrypted XML, moving attachment binaries out of the inner header
+fn parse_inner_xml(
+    data: &[u8],
+    inner_header: &mut header::KdbxInnerHeader,
+    options: &crate::xml::ParseOptions,
+) -> Result<database::Database, errors::UnlockError> {
+    let binaries = inner_header.take_binaries();
+    let algorithm = inner_header.inner_stream_cipher;
+    let key = inner_header.inner_stream_key.as_ref();
+    let mut stream_cipher = algorithm.stream_cipher(key)?;
+    let mut protected_values = if options.lazy_protected {
+        ProtectedValues::Lazy {
+            stream: Arc::new(InnerStreamKey::new(algorithm, key)),
+            offset: 0,
+        }
+    } else {
+        ProtectedValues::Eager(stream_cipher.as_mut())
+    };
+    Ok(parse_xml_protected(
+        data,
+        &mut protected_values,
+        options,
+        binaries,
+    )?)
+}
+
+fn map_xml_write_error(e: crate::errors::XmlWriteError) -> errors::WriteError {
+    match e {
+        crate::errors::XmlWriteError::Xml(xml::writer::Error::Io(io_error)) => {
+            errors::WriteError::from_pipeline(io_error)
+        }
+        e => errors::WriteError::XmlWrite(e),
+    }
+}
+
 impl Unlocked {
-    fn encrypt_inner(&self, key: &crypto::MasterKey) -> Result<Vec<u8>, super::errors::WriteError> {
-        let mut encrypted_buf = Vec::new();
+    /// Original inner header bytes, if they match the inner header about to be written
+    fn unchanged_inner_header_data(&self, inner_header: &header::KdbxInnerHeader) -> Option<&[u8]> {
+        let data = self.inner_header_data.as_deref()?;
+        let original = header::KdbxInnerHeader::read(&mut &*data, self.major_version).ok()?;
+        if original == *inner_header {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    /// Database as it should be written, after applying the field length limit
+    fn database_to_write(&self) -> Result<Cow<'_, database::Database>, errors::WriteError> {
+        let max_len = match self.max_field_length {
+            Some(max_len) => max_len,
+            None => return Ok(Cow::Borrowed(&self.database)),
+        };
+        let oversized = self.database.oversized_fields(max_len);
+        match (oversized.into_iter().next(), self.field_length_policy) {
+            (None, _) => Ok(Cow::Borrowed(&self.database)),
+            (Some(field), FieldLengthPolicy::Error) => Err(errors::WriteError::FieldTooLong {
+                entry_uuid: field.entry_uuid,
+                key: field.key,
+                len: field.len,
+                max_len,
+            }),
+            (Some(_), FieldLengthPolicy::Truncate) => {
+                let mut database = self.database.clone();
+                database.truncate_fields(max_len);
+                Ok(Cow::Owned(database))
+            }
+        }
+    }
+
+    fn encrypt_inner<W: Write>(
+        &self,
+        key: &crypto::MasterKey,
+        database: &database::Database,
+        output: W,
+    ) -> Result<(), super::errors::WriteError> {
+        use super::errors::WriteError;
         let mut encrypted_stream = crate::stream::kdbx4_write_stream(
-            &mut encrypted_buf,
+            output,
             key.hmac_key(&self.header.master_seed),
             key.cipher_key(&self.header.master_seed),
             self.header.cipher,
             &self.header.encryption_iv,
             self.header.compression_type,
-        )?;
-        self.inner_header.write(&mut encrypted_stream)?;
+        )
+        .map_err(WriteError::from_pipeline)?;
+        let pool = database::BinaryPool::new(database);
+        let inner_header = self.inner_header.with_binaries(&pool);
+        match self.unchanged_inner_header_data(&inner_header) {
+            Some(data) => encrypted_stream.write_all(data),
+            None => inner_header.write(&mut encrypted_stream),
+        }
+        .map_err(WriteError::from_pipeline)?;
         let mut stream_cipher = self
             .inner_header
             .inner_stream_cipher
             .stream_cipher(&self.inner_header.inner_stream_key)?;
-        crate::xml::write_xml(
+        crate::xml::serialize::write_xml_with_binaries(
             &mut encrypted_stream,
-            &self.database,
+            database,
             stream_cipher.as_mut(),
+            &pool,
+            false,
+            self.major_version,
+        )
+        .map_err(map_xml_write_error)?;
+
+        encrypted_stream
+            .finish()
+            .map_err(WriteError::from_pipeline)?;
+        Ok(())
+    }
+
+    fn encrypt_inner_v3<W: Write>(
+        &self,
+        key: &crypto::MasterKey,
+        database: &database::Database,
+        output: W,
+    ) -> Result<(), super::errors::WriteError> {
+        use super::errors::WriteError;
+        let start_bytes = self.header.stream_start_bytes.as_ref().ok_or(
+            errors::HeaderError::MissingRequiredField(header::OuterHeaderId::StreamStartBytes),
         )?;
+        let mut encrypted_stream = crate::stream::kdbx3_write_stream(
+            output,
+            key.cipher_key(&self.header.master_seed),
+            self.header.cipher,
+            &self.header.encryption_iv,
+            self.header.compression_type,
+            start_bytes,
+        )
+        .map_err(WriteError::from_pipeline)?;
+        let mut stream_cipher = self
+            .inner_header
+            .inner_stream_cipher
+            .stream_cipher(&self.inner_header.inner_stream_key)?;
+        let pool = database::BinaryPool::new(database);
+        crate::xml::serialize::write_xml_with_binaries(
+            &mut encrypted_stream,
+            database,
+            stream_cipher.as_mut(),
+            &pool,
+            true,
+            self.major_version,
+        )
+        .map_err(map_xml_write_error)?;
 
-        encrypted_stream.finish()?;
-        Ok(encrypted_buf)
+        encrypted_stream
+            .finish()
+            .map_err(WriteError::from_pipeline)?;
+        Ok(())
     }
 }
 
@@ -145,12 +489,21 @@ impl KdbxState for Unlocked {
             .master_key
             .as_ref()
             .ok_or(errors::WriteError::MissingKeys)?;
+        let database = self.database_to_write()?;
         let mut header_buf = Vec::new();
         let header_writer = &mut header_buf as &mut dyn Write;
         header_writer.write_all(&super::KEEPASS_MAGIC_NUMBER.to_le_bytes())?;
         header_writer.write_all(&super::KDBX_MAGIC_NUMBER.to_le_bytes())?;
         header_writer.write_all(&self.minor_version.to_le_bytes())?;
         header_writer.write_all(&self.major_version.to_le_bytes())?;
+        if self.major_version < 4 {
+            self.header.is_v3_compatible()?;
+            self.header
+                .write_legacy(&mut header_buf, &self.inner_header)?;
+            output.write_all(&header_buf)?;
+            self.encrypt_inner_v3(master_key, &database, output)?;
+            return Ok(());
+        }
         self.header.write(&mut header_buf)?;
         output.write_all(&header_buf)?;
         output.write_all(&crypto::sha256(&header_buf))?;
@@ -160,8 +513,7 @@ impl KdbxState for Unlocked {
             .calculate_header_hmac(&header_buf)
             .map_err(|_| errors::WriteError::MissingKeys)?;
         output.write_all(&hmac.into_bytes())?;
-        let encrypted_xml = self.encrypt_inner(master_key)?;
-        output.write_all(&encrypted_xml)?;
+        self.encrypt_inner(master_key, &database, output)?;
         Ok(())
     }
 }
@@ -182,16 +534,235 @@ impl Kdbx<Unlocked> {
         &mut self,
         key: crypto::CompositeKey,
     ) -> Result<(), crate::errors::KeyGenerationError> {
-        self.state.composed_key = Some(key.composed());
+        self.state.composed_key = Some(key.composed(
+            &self.header().master_seed,
+            &self.header().kdf_params,
+            self.major_version(),
+        )?);
         let composed_key = self.state.composed_key.as_ref().unwrap();
         self.state.master_key = Some(composed_key.master_key(&self.header().kdf_params)?);
 
         Ok(())
     }
 
+    /// Change the credentials used to encrypt the database
+    ///
+    /// Generates a new master seed, KDF salt and encryption IV using OS
+    /// randomness, so no key material is shared with the previous credentials.
+    /// A key with a precomputed challenge response instead keeps the seed it
+    /// was computed for, see [`CompositeKey::with_precomputed_response`][crate::CompositeKey::with_precomputed_response].
+    /// The time the key changed is recorded in the database meta.
+    ///
+    /// If deriving the new key fails, the database keeps its previous seeds
+    /// and credentials.
+    pub fn change_key(
+        &mut self,
+        new_key: crypto::CompositeKey,
+    ) -> Result<(), crate::errors::KeyGenerationError> {
+        use rand::{rngs::OsRng, RngCore};
+        let major_version = self.major_version();
+        let mut master_seed = vec![0u8; self.header().master_seed.len()];
+        OsRng.fill_bytes(&mut master_seed);
+        let mut kdf_params = self.header().kdf_params.clone();
+        if let KdfParams::Argon2 { salt, .. } | KdfParams::Aes { salt, .. } = &mut kdf_params {
+            OsRng.fill_bytes(salt);
+        }
+        match (new_key.challenge_seed(), &mut kdf_params) {
+            (Some(seed), KdfParams::Argon2 { salt, .. } | KdfParams::Aes { salt, .. })
+                if major_version >= 4 =>
+            {
+                *salt = seed.to_vec()
+            }
+            (Some(seed), _) => master_seed = seed.to_vec(),
+            (None, _) => {}
+        }
+        let composed_key = new_key.composed(&master_seed, &kdf_params, major_version)?;
+        let master_key = composed_key.master_key(&kdf_params)?;
+
+        let header = self.header_mut();
+        header.master_seed = master_seed;
+        header.kdf_params = kdf_params;
+        OsRng.fill_bytes(&mut header.encryption_iv);
+        self.state.composed_key = Some(composed_key);
+        self.state.master_key = Some(master_key);
+        self.meta_mut().master_key_changed = Some(database::current_time());
+        Ok(())
+    }
+
+    /// Replace the key derivation parameters used when writing the database
+    ///
+    /// The master key depends on the KDF parameters, so [`Kdbx::set_key`]
+    /// must be called again afterwards before the database can be written.
+    pub fn set_kdf_params(&mut self, params: KdfParams) {
+        self.header_mut().kdf_params = params;
+        self.state.master_key = None;
+    }
+
+    /// Replace all random values used when writing the database
+    ///
+    /// Intended for reproducible test vectors: two databases with the same
+    /// contents, key and seeds are written as identical bytes. Never reuse
+    /// seeds for real databases, as that weakens their encryption. The KDF
+    /// salt is ignored for unknown KDFs, and KDBX 3.1 databases also store
+    /// random [`stream_start_bytes`][header::KdbxHeader::stream_start_bytes]
+    /// which can be set through [`Kdbx::header_mut`].
+    ///
+    /// As the master key depends on the seeds, [`Kdbx::set_key`] must be
+    /// called again afterwards before the database can be written.
+    pub fn set_seeds(
+        &mut self,
+        master_seed: &[u8],
+        encryption_iv: &[u8],
+        kdf_salt: &[u8],
+        inner_stream_key: &[u8],
+    ) {
+        let header = self.header_mut();
+        header.master_seed = master_seed.to_vec();
+        header.encryption_iv = encryption_iv.to_vec();
+        match &mut header.kdf_params {
+            KdfParams::Argon2 { salt, .. } | KdfParams::Aes { salt, .. } => {
+                *salt = kdf_salt.to_vec();
+            }
+            KdfParams::Unknown { .. } => {}
+        }
+        self.inner_header_mut().inner_stream_key = inner_stream_key.to_vec();
+        self.state.master_key = None;
+    }
+
+    /// Change the cipher used to encrypt the database
+    ///
+    /// Generates a new encryption IV of the right length for the cipher
+    /// using OS randomness. The IV of an unknown cipher keeps its current length.
+    pub fn set_cipher(&mut self, cipher: super::Cipher) {
+        use rand::{rngs::OsRng, RngCore};
+        let header = self.header_mut();
+        let iv_len = cipher.iv_len().unwrap_or(header.encryption_iv.len());
+        header.cipher = cipher;
+        header.encryption_iv = vec![0u8; iv_len];
+        OsRng.fill_bytes(&mut header.encryption_iv);
+    }
+
+    /// Switch the file format version used when writing the database
+    ///
+    /// Versions below 4 are written in the KDBX 3.1 format. As KDBX 3.1 only
+    /// supports the AES KDF, any other KDF is replaced by AES with
+    /// [`KdfParams::DEFAULT_AES_ROUNDS`] rounds and the inner stream cipher is
+    /// switched to Salsa20 with a new key. Random stream start bytes are
+    /// generated if not already present. If the KDF changed, [`Kdbx::set_key`]
+    /// must be called again afterwards before the database can be written.
+    pub fn set_major_version(&mut self, major_version: u16) {
+        use rand::{rngs::OsRng, RngCore};
+        self.state.major_version = major_version;
+        if major_version >= 4 {
+            self.state.minor_version = 0;
+            return;
+        }
+
+        self.state.minor_version = 1;
+        if !matches!(self.header().kdf_params, KdfParams::Aes { .. }) {
+            self.set_kdf_params(KdfParams::aes(KdfParams::DEFAULT_AES_ROUNDS));
+        }
+        if self.header().stream_start_bytes.is_none() {
+            let mut start_bytes = vec![0u8; 32];
+            OsRng.fill_bytes(&mut start_bytes);
+            self.header_mut().stream_start_bytes = Some(start_bytes);
+        }
+        let inner_header = self.inner_header_mut();
+        if inner_header.inner_stream_cipher != super::InnerStreamCipherAlgorithm::Salsa20 {
+            inner_header.inner_stream_cipher = super::InnerStreamCipherAlgorithm::Salsa20;
+            inner_header.inner_stream_key = vec![0u8; 32];
+            OsRng.fill_bytes(&mut inner_header.inner_stream_key);
+        }
+    }
+
+    /// Binary data referred to by entry attachments, as it will be stored on write
+    ///
+    /// Binaries are numbered in the order entries first use them. KDBX 4
+    /// stores them in the inner header, earlier versions in the XML meta section.
+    pub fn binaries(&self) -> Vec<database::Binary> {
+        database::BinaryPool::new(&self.state.database)
+            .iter()
+            .enumerate()
+            .map(|(id, (data, protected))| database::Binary {
+                id,
+                data: data.to_vec(),
+                protected,
+            })
+            .collect()
+    }
+
+    /// Limit the length in bytes of field values when writing the database
+    ///
+    /// Some clients fail on very long values. Longer values are handled
+    /// according to [`Kdbx::set_field_length_policy`], and can be listed
+    /// beforehand with [`Kdbx::fields_over_limit`]. The database in memory is
+    /// never changed. Defaults to no limit.
+    pub fn set_max_field_length(&mut self, max_len: Option<usize>) {
+        self.state.max_field_length = max_len;
+    }
+
+    /// Limit on the length of field values when writing the database
+    pub fn max_field_length(&self) -> Option<usize> {
+        self.state.max_field_length
+    }
+
+    /// Choose whether writes truncate or reject values over the maximum field length
+    pub fn set_field_length_policy(&mut self, policy: FieldLengthPolicy) {
+        self.state.field_length_policy = policy;
+    }
+
+    /// Handling of values over the maximum field length
+    pub fn field_length_policy(&self) -> FieldLengthPolicy {
+        self.state.field_length_policy
+    }
+
+    /// Fields the next write will truncate or reject for exceeding the maximum field length
+    pub fn fields_over_limit(&self) -> Vec<database::OversizedField> {
+        self.state
+            .max_field_length
+            .map(|max_len| self.state.database.oversized_fields(max_len))
+            .unwrap_or_default()
+    }
+
+    /// Check the database reads back unchanged after being written
+    ///
+    /// The database is encrypted to memory, then decrypted with the current
+    /// keys and compared with the in-memory copy. This costs about as much as
+    /// a save and an unlock without key derivation, and is a useful safeguard
+    /// before saving over the only copy of a database. Unlike a save, it does
+    /// not start new automatic history snapshots.
+    pub fn roundtrip_check(&self) -> Result<(), crate::Error> {
+        let master_key = self
+            .state
+            .master_key
+            .as_ref()
+            .ok_or(errors::WriteError::MissingKeys)?;
+        let expected = self.state.database_to_write()?;
+        let mut buf = Vec::new();
+        self.state.write(&mut buf)?;
+        let reread = super::from_reader(&*buf)?;
+        let options = crate::xml::ParseOptions {
+            preserve_comments: true,
+            ..Default::default()
+        };
+        let actual = reread.decrypt_database(master_key, &options)?;
+        match expected.content_difference(&actual) {
+            Some(difference) => Err(crate::Error::RoundTripMismatch(difference)),
+            None => Ok(()),
+        }
+    }
+
+    /// Time taken to unlock this database, including key derivation
+    ///
+    /// Only present for databases obtained by unlocking an existing file.
+    pub fn last_unlock_duration(&self) -> Option<Duration> {
+        self.state.unlock_duration
+    }
+
     /// Raw parsed XML data to handle fields not supported by this plugin
     ///
-    /// Only present from databases loaded from existing sources
+    /// Only present from databases loaded from existing sources, and not
+    /// when unlocked with [`Kdbx::unlock_into`]
     pub fn raw_xml(&self) -> Option<&[u8]> {
         self.state.xml_data.as_deref()
     }
@@ -219,17 +790,51 @@ impl Kdbx<Unlocked> {
         let header = header::KdbxHeader::from_os_random();
         let inner_header = header::KdbxInnerHeader::from_os_random();
         let unlocked = Unlocked {
+            inner_header_data: None,
             header,
             inner_header,
             major_version: 4,
             minor_version: 0,
             xml_data: None,
+            unlock_duration: None,
+            max_field_length: None,
+            field_length_policy: FieldLengthPolicy::default(),
             composed_key: None,
             master_key: None,
             database,
         };
         Kdbx { state: unlocked }
     }
+
+    /// Generate a new .kdbx from decrypted database XML
+    ///
+    /// Protected values in the XML must not be encrypted, as in XML written
+    /// with [`write_xml`][crate::xml::write_xml] and a
+    /// [`NullStreamCipher`][crate::utils::NullStreamCipher]. The XML from
+    /// [`Kdbx::raw_xml`] is still encrypted with its database's inner stream,
+    /// use [`Kdbx::rewrap_raw_xml`] for it instead. As with
+    /// [`Kdbx::from_database`], the headers are freshly generated and a key
+    /// must be set with [`Kdbx::set_key`] before writing.
+    pub fn from_xml_bytes(xml: &[u8]) -> Result<Kdbx<Unlocked>, crate::Error> {
+        let database = crate::xml::parse_xml(xml, &mut utils::NullStreamCipher)?;
+        Ok(Kdbx::from_database(database))
+    }
+
+    /// Generate a new .kdbx from XML as returned by [`Kdbx::raw_xml`], possibly edited
+    ///
+    /// Protected values are decrypted with this database's inner stream, so
+    /// they must be kept in the same order and with the same encrypted values.
+    /// As with [`Kdbx::from_xml_bytes`], the headers are freshly generated and
+    /// a key must be set with [`Kdbx::set_key`] before writing.
+    pub fn rewrap_raw_xml(&self, xml: &[u8]) -> Result<Kdbx<Unlocked>, crate::Error> {
+        let inner_header = self.inner_header();
+        let mut stream_cipher = inner_header
+            .inner_stream_cipher
+            .stream_cipher(&inner_header.inner_stream_key)
+            .map_err(errors::UnlockError::from)?;
+        let database = crate::xml::parse_xml(xml, stream_cipher.as_mut())?;
+        Ok(Kdbx::from_database(database))
+    }
 }
 
 impl Deref for Kdbx<Unlocked> {
@@ -246,6 +851,37 @@ impl DerefMut for Kdbx<Unlocked> {
     }
 }
 
+#[derive(Debug)]
+/// Encrypted body of a locked database, either read into memory or mapped from a file
+pub(crate) enum EncryptedData {
+    Owned(Vec<u8>),
+    #[cfg(all(unix, feature = "mmap"))]
+    Mapped {
+        map: super::mmap::Mmap,
+        offset: usize,
+    },
+}
+
+impl Deref for EncryptedData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            EncryptedData::Owned(data) => data,
+            #[cfg(all(unix, feature = "mmap"))]
+            EncryptedData::Mapped { map, offset } => &map[*offset..],
+        }
+    }
+}
+
+impl PartialEq for EncryptedData {
+    fn eq(&self, other: &EncryptedData) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for EncryptedData {}
+
 #[derive(Debug, PartialEq, Eq)]
 /// A locked kdbx file, use unlock(composite_key) to unlock
 pub struct Locked {
@@ -260,7 +896,9 @@ pub struct Locked {
     /// hmac code to verify keys and header integrity
     pub(crate) hmac: Option<Vec<u8>>,
     /// Encrypted vault data
-    pub(crate) encrypted_data: Vec<u8>,
+    pub(crate) encrypted_data: EncryptedData,
+    /// Recoverable problems found while opening the database
+    pub(crate) warnings: Vec<super::OpenWarning>,
 }
 
 impl KdbxState for Locked {
@@ -287,11 +925,15 @@ impl KdbxState for Locked {
         header_writer.write_all(&super::KDBX_MAGIC_NUMBER.to_le_bytes())?;
         header_writer.write_all(&self.minor_version.to_le_bytes())?;
         header_writer.write_all(&self.major_version.to_le_bytes())?;
-        self.header.write(&mut header_buf)?;
-        output.write_all(&header_buf)?;
         if self.major_version >= 4 {
+            self.header.write(&mut header_buf)?;
+            output.write_all(&header_buf)?;
             output.write_all(&crypto::sha256(&header_buf))?;
             output.write_all(self.hmac.as_ref().unwrap())?;
+        } else {
+            let inner_header = header::KdbxInnerHeader::from_legacy_fields(&self.header)?;
+            self.header.write_legacy(&mut header_buf, &inner_header)?;
+            output.write_all(&header_buf)?;
         }
         output.write_all(&self.encrypted_data)?;
         Ok(())
@@ -299,10 +941,15 @@ impl KdbxState for Locked {
 }
 
 impl Kdbx<Locked> {
-    fn decrypt_v4(
+    /// Recoverable problems found while opening the database
+    pub fn warnings(&self) -> &[super::OpenWarning] {
+        &self.state.warnings
+    }
+
+    fn decrypt_stream_v4(
         &self,
         master_key: &crypto::MasterKey,
-    ) -> Result<(header::KdbxInnerHeader, Vec<u8>), errors::UnlockError> {
+    ) -> Result<DecryptedStream<'_>, errors::UnlockError> {
         let hmac_key = master_key.hmac_key(&self.state.header.master_seed);
         let cipher_key = master_key.cipher_key(&self.state.header.master_seed);
         let mut input_stream = stream::kdbx4_read_stream(
@@ -313,31 +960,186 @@ impl Kdbx<Locked> {
             &self.state.header.encryption_iv,
             self.state.header.compression_type,
         )?;
+        let mut caching_reader = utils::CachingReader::new(&mut input_stream);
         let inner_header =
-            header::KdbxInnerHeader::read(&mut input_stream, self.state.major_version)?;
-        let mut output_buffer = Vec::new();
-        input_stream.read_to_end(&mut output_buffer)?;
-        Ok((inner_header, output_buffer))
+            header::KdbxInnerHeader::read(&mut caching_reader, self.state.major_version)?;
+        let (inner_header_data, _) = caching_reader.into_inner();
+        Ok((inner_header, inner_header_data, input_stream))
+    }
+
+    fn decrypt_v4(
+        &self,
+        master_key: &crypto::MasterKey,
+        max_len: Option<usize>,
+        output: &mut Vec<u8>,
+    ) -> Result<(header::KdbxInnerHeader, Vec<u8>), errors::UnlockError> {
+        let (inner_header, inner_header_data, input_stream) = self.decrypt_stream_v4(master_key)?;
+        read_limited(input_stream, max_len, output)?;
+        Ok((inner_header, inner_header_data))
+    }
+
+    /// Decrypt and parse the database with an already derived master key
+    fn decrypt_database(
+        &self,
+        master_key: &crypto::MasterKey,
+        options: &crate::xml::ParseOptions,
+    ) -> Result<database::Database, errors::UnlockError> {
+        let mut data = Vec::new();
+        let mut inner_header = if self.state.major_version >= 4 {
+            let hmac_key = master_key.hmac_key(&self.state.header.master_seed);
+            let hmac = self.state.hmac.as_ref().unwrap();
+            if !hmac_key
+                .block_key(u64::MAX)
+                .verify_header_block(hmac, &self.state.header_data)
+            {
+                return Err(errors::UnlockError::HmacInvalid);
+            }
+            self.decrypt_v4(master_key, None, &mut data)?.0
+        } else {
+            self.decrypt_v3(master_key, None, &mut data)?
+        };
+        parse_inner_xml(&data, &mut inner_header, options)
+    }
+
+    /// Size in bytes of the decrypted XML database, without parsing it
+    ///
+    /// This decrypts and decompresses the database to count its length,
+    /// discarding the data as it goes. It's useful for sizing a progress
+    /// indicator before calling [`Kdbx::unlock`], but does the full KDF
+    /// and decryption work, so it costs about as much as unlocking.
+    pub fn decrypted_len(&self, key: &crypto::CompositeKey) -> Result<usize, errors::UnlockError> {
+        let master_key = key
+            .composed(
+                &self.state.header.master_seed,
+                &self.state.header.kdf_params,
+                self.state.major_version,
+            )?
+            .master_key(&self.header().kdf_params)?;
+        let mut input_stream = if self.state.major_version >= 4 {
+            let hmac_key = master_key.hmac_key(&self.state.header.master_seed);
+            let hmac = self.state.hmac.as_ref().unwrap();
+            if !hmac_key
+                .block_key(u64::MAX)
+                .verify_header_block(hmac, &self.state.header_data)
+            {
+                return Err(errors::UnlockError::HmacInvalid);
+            }
+            self.decrypt_stream_v4(&master_key)?.2
+        } else {
+            self.decrypt_stream_v3(&master_key)?.1
+        };
+        let len = std::io::copy(&mut input_stream, &mut std::io::sink())?;
+        Ok(len as usize)
     }
 
     /// Unlocks the kdbx file
     ///
     /// If unlock fails, returns the locked kdbx file along with the error
+    ///
+    /// KeePass's Windows user account key factor is not supported. KeePass
+    /// does not record its use in the file, so such databases fail to unlock
+    /// as if the credentials were wrong.
     #[allow(clippy::result_large_err)]
     pub fn unlock(self, key: &crypto::CompositeKey) -> Result<Kdbx<Unlocked>, FailedUnlock> {
-        if self.state.major_version >= 4 {
-            self.unlock_v4(key)
+        self.unlock_with_options(key, &UnlockOptions::default())
+    }
+
+    /// Unlocks the kdbx file, applying limits suitable for untrusted input
+    ///
+    /// If unlock fails, returns the locked kdbx file along with the error
+    #[allow(clippy::result_large_err)]
+    pub fn unlock_with_options(
+        self,
+        key: &crypto::CompositeKey,
+        options: &UnlockOptions,
+    ) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        self.unlock_timed(key, options, None)
+    }
+
+    /// Unlocks the kdbx file, decrypting into a caller provided buffer
+    ///
+    /// `scratch` is cleared and reused for the decrypted XML, so one allocation
+    /// can be shared across many unlocks. It still holds the plaintext XML
+    /// afterwards, and [`Kdbx::raw_xml`] is not available on the result.
+    #[allow(clippy::result_large_err)]
+    pub fn unlock_into(
+        self,
+        key: &crypto::CompositeKey,
+        scratch: &mut Vec<u8>,
+    ) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        self.unlock_timed(key, &UnlockOptions::default(), Some(scratch))
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn unlock_timed(
+        self,
+        key: &crypto::CompositeKey,
+        options: &UnlockOptions,
+        scratch: Option<&mut Vec<u8>>,
+    ) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        let start = Instant::now();
+        let result = if self.state.major_version >= 4 {
+            self.unlock_v4(key, options, scratch)
         } else {
-            self.unlock_v3(key)
+            self.unlock_v3(key, options, scratch)
+        };
+        result.map(|mut unlocked| {
+            unlocked.state.unlock_duration = Some(start.elapsed());
+            unlocked
+        })
+    }
+
+    /// Unlocks the kdbx file with just a password
+    ///
+    /// Use [`Kdbx::unlock`] for databases that also need a key file.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), kdbx_rs::Error> {
+    /// # let file_path = "./res/test_input/kdbx4-argon2d.kdbx";
+    /// let unlocked = kdbx_rs::open(file_path)?.unlock_with_password("kdbxrs")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn unlock_with_password(self, pw: &str) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        self.unlock(&crypto::CompositeKey::from_password(pw))
+    }
+
+    /// Unlocks the kdbx file with a key file read from disk, and optionally a password
+    ///
+    /// Fails with [`UnlockError::KeyFile`][crate::errors::UnlockError::KeyFile]
+    /// if the key file can't be read.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), kdbx_rs::Error> {
+    /// # let file_path = "./res/test_input/kdbx4-argon2d-key-file.kdbx";
+    /// # let key_file_path = std::env::temp_dir().join("kdbx-rs-doc-key-file");
+    /// # std::fs::write(&key_file_path, b"key-file").unwrap();
+    /// let unlocked = kdbx_rs::open(file_path)?.unlock_with_keyfile(Some("kdbxrs"), &key_file_path)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn unlock_with_keyfile<P: AsRef<Path>>(
+        self,
+        pw: Option<&str>,
+        keyfile_path: P,
+    ) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        match fs::read(keyfile_path) {
+            Ok(keyfile) => self.unlock(&crypto::CompositeKey::new(
+                pw.map(String::from),
+                Some(keyfile),
+            )),
+            Err(e) => Err(FailedUnlock(self, errors::UnlockError::KeyFile(e))),
         }
     }
 
-    fn decrypt_v3(
+    fn decrypt_stream_v3(
         &self,
         master_key: &crypto::MasterKey,
-    ) -> Result<(header::KdbxInnerHeader, Vec<u8>), errors::UnlockError> {
+    ) -> Result<(header::KdbxInnerHeader, Box<dyn Read + '_>), errors::UnlockError> {
         let cipher_key = master_key.cipher_key(&self.state.header.master_seed);
-        let mut input_stream = stream::kdbx3_read_stream(
+        let input_stream = stream::kdbx3_read_stream(
             &*self.state.encrypted_data,
             cipher_key,
             self.state.header.cipher,
@@ -346,39 +1148,66 @@ impl Kdbx<Locked> {
             self.header().stream_start_bytes.as_ref().unwrap(),
         )?;
         let inner_header = header::KdbxInnerHeader::from_legacy_fields(&self.state.header)?;
-        let mut output_buffer = Vec::new();
-        input_stream.read_to_end(&mut output_buffer)?;
-        Ok((inner_header, output_buffer))
+        Ok((inner_header, input_stream))
+    }
+
+    fn decrypt_v3(
+        &self,
+        master_key: &crypto::MasterKey,
+        max_len: Option<usize>,
+        output: &mut Vec<u8>,
+    ) -> Result<header::KdbxInnerHeader, errors::UnlockError> {
+        let (inner_header, input_stream) = self.decrypt_stream_v3(master_key)?;
+        read_limited(input_stream, max_len, output)?;
+        Ok(inner_header)
     }
 
     #[allow(clippy::result_large_err)]
-    fn unlock_v3(self, key: &crypto::CompositeKey) -> Result<Kdbx<Unlocked>, FailedUnlock> {
-        let composed_key = key.composed();
-        let master_key = match composed_key.master_key(&self.header().kdf_params) {
+    fn unlock_v3(
+        self,
+        key: &crypto::CompositeKey,
+        options: &UnlockOptions,
+        scratch: Option<&mut Vec<u8>>,
+    ) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        let composed_key = match key.composed(
+            &self.state.header.master_seed,
+            &self.state.header.kdf_params,
+            self.state.major_version,
+        ) {
+            Ok(composed_key) => composed_key,
+            Err(e) => return Err(FailedUnlock(self, errors::UnlockError::from(e))),
+        };
+        let master_key = match composed_key
+            .master_key_with_memory_limit(&self.header().kdf_params, options.kdf_memory_limit)
+        {
             Ok(master_key) => master_key,
             Err(e) => return Err(FailedUnlock(self, errors::UnlockError::from(e))),
         };
 
+        let mut owned = Vec::new();
+        let reused = scratch.is_some();
+        let data = scratch.unwrap_or(&mut owned);
         let parsed = self
-            .decrypt_v3(&master_key)
-            .and_then(|(inner_header, data)| {
-                let mut stream_cipher = inner_header
-                    .inner_stream_cipher
-                    .stream_cipher(inner_header.inner_stream_key.as_ref())?;
-                let parsed = crate::xml::parse_xml(data.as_slice(), stream_cipher.as_mut())?;
-                Ok((inner_header, data, parsed))
+            .decrypt_v3(&master_key, options.max_decompressed, data)
+            .and_then(|mut inner_header| {
+                let parsed = parse_inner_xml(data, &mut inner_header, &options.parse_options)?;
+                Ok((inner_header, parsed))
             });
         match parsed {
-            Ok((inner_header, data, db)) => Ok(Kdbx {
+            Ok((inner_header, db)) => Ok(Kdbx {
                 state: Unlocked {
                     inner_header,
+                    inner_header_data: None,
                     header: self.state.header,
                     major_version: self.state.major_version,
                     minor_version: self.state.minor_version,
                     composed_key: Some(composed_key),
                     master_key: Some(master_key),
                     database: db,
-                    xml_data: Some(data),
+                    xml_data: if reused { None } else { Some(owned) },
+                    unlock_duration: None,
+                    max_field_length: None,
+                    field_length_policy: FieldLengthPolicy::default(),
                 },
             }),
             Err(e) => Err(FailedUnlock(self, e)),
@@ -386,9 +1215,23 @@ impl Kdbx<Locked> {
     }
 
     #[allow(clippy::result_large_err)]
-    fn unlock_v4(self, key: &crypto::CompositeKey) -> Result<Kdbx<Unlocked>, FailedUnlock> {
-        let composed_key = key.composed();
-        let master_key = match composed_key.master_key(&self.header().kdf_params) {
+    fn unlock_v4(
+        self,
+        key: &crypto::CompositeKey,
+        options: &UnlockOptions,
+        scratch: Option<&mut Vec<u8>>,
+    ) -> Result<Kdbx<Unlocked>, FailedUnlock> {
+        let composed_key = match key.composed(
+            &self.state.header.master_seed,
+            &self.state.header.kdf_params,
+            self.state.major_version,
+        ) {
+            Ok(composed_key) => composed_key,
+            Err(e) => return Err(FailedUnlock(self, errors::UnlockError::from(e))),
+        };
+        let master_key = match composed_key
+            .master_key_with_memory_limit(&self.header().kdf_params, options.kdf_memory_limit)
+        {
             Ok(master_key) => master_key,
             Err(e) => return Err(FailedUnlock(self, errors::UnlockError::from(e))),
         };
@@ -398,27 +1241,31 @@ impl Kdbx<Locked> {
         let hmac = self.state.hmac.clone().unwrap();
 
         if header_block_key.verify_header_block(hmac.as_ref(), &self.state.header_data) {
+            let mut owned = Vec::new();
+            let reused = scratch.is_some();
+            let data = scratch.unwrap_or(&mut owned);
             let parsed = self
-                .decrypt_v4(&master_key)
-                .and_then(|(inner_header, data)| {
-                    let mut stream_cipher = inner_header
-                        .inner_stream_cipher
-                        .stream_cipher(inner_header.inner_stream_key.as_ref())?;
-                    let parsed = crate::xml::parse_xml(data.as_slice(), stream_cipher.as_mut())?;
-                    Ok((inner_header, data, parsed))
+                .decrypt_v4(&master_key, options.max_decompressed, data)
+                .and_then(|(mut inner_header, inner_header_data)| {
+                    let parsed = parse_inner_xml(data, &mut inner_header, &options.parse_options)?;
+                    Ok((inner_header, inner_header_data, parsed))
                 });
 
             match parsed {
-                Ok((inner_header, data, db)) => Ok(Kdbx {
+                Ok((inner_header, inner_header_data, db)) => Ok(Kdbx {
                     state: Unlocked {
                         inner_header,
+                        inner_header_data: Some(inner_header_data),
                         header: self.state.header,
                         major_version: self.state.major_version,
                         minor_version: self.state.minor_version,
                         composed_key: Some(composed_key),
                         master_key: Some(master_key),
                         database: db,
-                        xml_data: Some(data),
+                        xml_data: if reused { None } else { Some(owned) },
+                        unlock_duration: None,
+                        max_field_length: None,
+                        field_length_policy: FieldLengthPolicy::default(),
                     },
                 }),
                 Err(e) => Err(FailedUnlock(self, e)),
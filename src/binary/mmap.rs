@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+
+/// Read only memory map of an entire file
+pub(crate) struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// SAFETY: the mapping is read only and owned by this struct, so it can be
+// unmapped from and read by any thread
+unsafe impl Send for Mmap {}
+// SAFETY: as above, shared references only ever read the mapping
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Map the whole of `file` into memory
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the map is alive, as
+    /// the mapped bytes are handed out as a `&[u8]`.
+    pub(crate) unsafe fn map(file: &File) -> io::Result<Mmap> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Cannot map an empty file",
+            ));
+        }
+        // SAFETY: a null hint lets the kernel pick the address, and `len` is
+        // the non zero length of the open file
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `ptr` maps `len` readable bytes until drop, and the caller of
+        // `Mmap::map` guarantees the file does not change underneath it
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `Mmap::map` and is unmapped once
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+impl std::fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mmap").field("len", &self.len).finish()
+    }
+}
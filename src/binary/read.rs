@@ -1,17 +1,72 @@
-use super::{errors, header, Kdbx, Locked};
+use super::kdbx::EncryptedData;
+use super::{errors, header, Kdb, Kdbx, Locked};
 use crate::utils;
 use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Recoverable problems found while opening a database
+pub enum OpenWarning {
+    /// Header field lengths used the width of a different major version than declared
+    HeaderFieldWidth {
+        /// Major version declared in the file
+        declared: u16,
+        /// Major version whose field length width was used to read the header
+        used: u16,
+    },
+}
+
 /// Read a database from a input stream
 ///
 /// The database starts locked, use [`KdbxDatabase.unlock`] to unlock
 ///
 /// [`KdbxDatabase.unlock`]: ./struct.KdbxDatabase.html#method.unlock
 pub fn from_reader<R: Read>(mut input: R) -> Result<Kdbx<Locked>, errors::OpenError> {
-    let mut caching_reader = utils::CachingReader::new(&mut input);
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    from_data(data)
+}
+
+/// Read a database already held in memory
+///
+/// The database starts locked, see [`from_reader`].
+pub fn from_bytes(data: &[u8]) -> Result<Kdbx<Locked>, errors::OpenError> {
+    from_data(data.to_vec())
+}
+
+#[cfg(feature = "tokio")]
+/// Read a database from an async input stream
+///
+/// The whole input is read into memory before the header is parsed, so
+/// reading does not block. Unlocking is still done synchronously.
+pub async fn from_async_reader<R: tokio::io::AsyncRead + Unpin>(
+    mut input: R,
+) -> Result<Kdbx<Locked>, errors::OpenError> {
+    use tokio::io::AsyncReadExt;
+    let mut data = Vec::new();
+    input.read_to_end(&mut data).await?;
+    from_data(data)
+}
+
+/// Read a database from the entire contents of a file
+fn from_data(mut data: Vec<u8>) -> Result<Kdbx<Locked>, errors::OpenError> {
+    let mut remaining = &data[..];
+    let mut state = read_header(&mut remaining)?;
+    let header_len = data.len() - remaining.len();
+    data.drain(..header_len);
+    state.encrypted_data = EncryptedData::Owned(data);
+
+    Ok(Kdbx { state })
+}
+
+/// Read everything before the encrypted data, leaving the data empty
+///
+/// The input is advanced past the header.
+fn read_header(input: &mut &[u8]) -> Result<Locked, errors::OpenError> {
+    let data: &[u8] = input;
+    let mut caching_reader = utils::CachingReader::new(&mut *input);
     let mut buffer = [0u8; 4];
     caching_reader.read_exact(&mut buffer)?;
 
@@ -34,7 +89,18 @@ pub fn from_reader<R: Read>(mut input: R) -> Result<Kdbx<Locked>, errors::OpenEr
         return Err(errors::OpenError::UnsupportedMajorVersion(major_version));
     }
 
-    let (header, header_data) = header::KdbxHeader::read(caching_reader, major_version)?;
+    let field_version = header::fitting_field_version(&data[12..], major_version)
+        .ok_or(errors::HeaderError::FieldOverrun)?;
+    let mut warnings = Vec::new();
+    if field_version != major_version {
+        warnings.push(OpenWarning::HeaderFieldWidth {
+            declared: major_version,
+            used: field_version,
+        });
+    }
+
+    let (header, header_data) =
+        header::KdbxHeader::read(caching_reader, major_version, field_version)?;
     let hmac = if major_version >= 4 {
         let mut hmac = utils::buffer(Sha256::output_size());
         input.read_exact(&mut hmac)?;
@@ -42,19 +108,16 @@ pub fn from_reader<R: Read>(mut input: R) -> Result<Kdbx<Locked>, errors::OpenEr
     } else {
         None
     };
-    let mut encrypted_data = Vec::new();
-    input.read_to_end(&mut encrypted_data)?;
 
-    let state = Locked {
+    Ok(Locked {
         header,
         header_data,
         major_version,
         minor_version,
         hmac,
-        encrypted_data,
-    };
-
-    Ok(Kdbx { state })
+        encrypted_data: EncryptedData::Owned(Vec::new()),
+        warnings,
+    })
 }
 
 /// Read a database from a given path
@@ -67,3 +130,115 @@ pub fn open<P: AsRef<Path>>(path: P) -> Result<Kdbx<Locked>, errors::OpenError>
     let mut file = File::open(path)?;
     from_reader(&mut file)
 }
+
+#[cfg(all(unix, feature = "mmap"))]
+/// Read a database from a given path using a read only memory map
+///
+/// Only the header is parsed up front, the encrypted data is read from
+/// the mapped file when unlocking.
+///
+/// # Safety
+///
+/// The file must not be modified or truncated, by this or any other process,
+/// until the returned database and anything unlocked from it are dropped.
+/// Changes to the file are visible through the map, which is undefined
+/// behaviour, and reading past the end of a truncated file raises `SIGBUS`.
+pub unsafe fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Kdbx<Locked>, errors::OpenError> {
+    let file = File::open(path.as_ref())?;
+    // SAFETY: the caller guarantees the file is not changed while the map is alive
+    let map = super::mmap::Mmap::map(&file)?;
+    let mut input: &[u8] = &map;
+    let mut state = read_header(&mut input)?;
+    let offset = map.len() - input.len();
+    state.encrypted_data = EncryptedData::Mapped { map, offset };
+
+    Ok(Kdbx { state })
+}
+
+/// A locked KeePass database of either format
+pub enum AnyDatabase {
+    /// A KeePass 1 database
+    Kdb(Kdb),
+    /// A KeePass 2 database
+    Kdbx(Kdbx<Locked>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// KeePass file format, as identified by [`detect_format`]
+pub enum Format {
+    /// KeePass 1 .kdb file
+    Kdb,
+    /// KeePass 2 .kdbx version 3 file
+    Kdbx3,
+    /// KeePass 2 .kdbx version 4 file
+    Kdbx4 {
+        /// Minor format version
+        minor: u16,
+    },
+}
+
+/// Number of bytes from the start of a file needed to identify its format
+const FORMAT_PROBE_LEN: usize = 12;
+
+fn format_from_probe(probe: &[u8; FORMAT_PROBE_LEN]) -> Result<Format, errors::OpenError> {
+    let u32_at = |idx: usize| {
+        u32::from_le_bytes([probe[idx], probe[idx + 1], probe[idx + 2], probe[idx + 3]])
+    };
+    if u32_at(0) != super::KEEPASS_MAGIC_NUMBER {
+        return Err(errors::OpenError::NonKeepassFormat);
+    }
+    match u32_at(4) {
+        super::KDB_MAGIC_NUMBER => Ok(Format::Kdb),
+        super::KDBX_MAGIC_NUMBER => {
+            let minor = u16::from_le_bytes([probe[8], probe[9]]);
+            let major = u16::from_le_bytes([probe[10], probe[11]]);
+            match major {
+                3 => Ok(Format::Kdbx3),
+                4 => Ok(Format::Kdbx4 { minor }),
+                _ => Err(errors::OpenError::UnsupportedMajorVersion(major)),
+            }
+        }
+        _ => Err(errors::OpenError::UnsupportedFileFormat),
+    }
+}
+
+/// Identify the format of a KeePass database without parsing its header
+///
+/// This consumes the first bytes of the reader, so pass a fresh reader
+/// (or a [`Cursor`][std::io::Cursor] that can be rewound) rather than one
+/// that will be used to open the database afterwards.
+pub fn detect_format<R: Read>(reader: &mut R) -> Result<Format, errors::OpenError> {
+    let mut probe = [0u8; FORMAT_PROBE_LEN];
+    reader.read_exact(&mut probe)?;
+    format_from_probe(&probe)
+}
+
+/// Identify the format of a KeePass database held in memory
+///
+/// Returns the untouched data alongside the format, so it can be passed
+/// on to [`from_reader`] or [`Kdb::from_reader`].
+pub fn detect_format_slice(data: &[u8]) -> Result<(Format, &[u8]), errors::OpenError> {
+    let format = detect_format(&mut &*data)?;
+    Ok((format, data))
+}
+
+/// Read a KeePass 1 or KeePass 2 database from a input stream
+pub fn any_from_reader<R: Read>(mut input: R) -> Result<AnyDatabase, errors::OpenError> {
+    let mut probe = [0u8; FORMAT_PROBE_LEN];
+    input.read_exact(&mut probe)?;
+    let format = format_from_probe(&probe)?;
+
+    let input = (&probe[..]).chain(input);
+    match format {
+        Format::Kdb => Ok(AnyDatabase::Kdb(Kdb::from_reader(input)?)),
+        Format::Kdbx3 | Format::Kdbx4 { .. } => Ok(AnyDatabase::Kdbx(from_reader(input)?)),
+    }
+}
+
+/// Read a KeePass 1 or KeePass 2 database from a given path
+///
+/// The format is detected from the file's magic numbers.
+pub fn open_any<P: AsRef<Path>>(path: P) -> Result<AnyDatabase, errors::OpenError> {
+    let mut file = File::open(path.as_ref())?;
+    any_from_reader(&mut file)
+}
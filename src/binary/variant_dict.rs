@@ -200,7 +200,10 @@ pub(crate) fn write_variant_dict<W: io::Write>(
     vdict: &VariantDict,
 ) -> io::Result<()> {
     output.write_all(&[0u8, 1u8])?;
-    for (name, value) in vdict.iter() {
+    // Sort keys so the same dictionary always produces the same bytes
+    let mut fields: Vec<_> = vdict.iter().collect();
+    fields.sort_by_key(|(name, _)| name.as_str());
+    for (name, value) in fields {
         output.write_all(&[value.tag()])?;
         output.write_all(&(name.len() as i32).to_le_bytes())?;
         output.write_all(name.as_bytes())?;
@@ -1,3 +1,5 @@
+mod keyfile;
+
 use crate::binary;
 
 use aes::Aes256;
@@ -8,6 +10,7 @@ use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256, Sha512};
 use std::string::ToString;
 use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -24,16 +27,39 @@ type HmacSha256 = Hmac<Sha256>;
 /// CompositeKey::from_password("abcdef");
 /// ```
 ///
-/// Otherwise you can use [`CompositeKey::new`] to provide other combinations
+/// Otherwise you can use [`CompositeKey::new`] to provide other combinations,
+/// and [`CompositeKey::with_precomputed_response`] to add a hardware token's
+/// challenge response
+///
+/// Key files may be a KeePass 2.x XML key file (version 1.0 or 2.0), a raw
+/// 32 byte key, a 64 character hex encoded key, or any other file, in which
+/// case its contents are hashed.
+///
+/// Credentials are wiped from memory when the key is dropped.
+#[derive(Zeroize, ZeroizeOnDrop)]
 pub struct CompositeKey {
     pw: Option<String>,
     keyfile: Option<Vec<u8>>,
+    challenge_response: Option<ChallengeResponse>,
+}
+
+/// Response from a hardware token, along with the master seed it answers
+#[derive(Zeroize)]
+struct ChallengeResponse {
+    seed: Vec<u8>,
+    response: Vec<u8>,
 }
 
 impl CompositeKey {
     /// Create a new composite key
+    ///
+    /// Either part may be `None`, such as for a database protected by a key file alone.
     pub fn new(pw: Option<String>, keyfile: Option<Vec<u8>>) -> CompositeKey {
-        CompositeKey { pw, keyfile }
+        CompositeKey {
+            pw,
+            keyfile,
+            challenge_response: None,
+        }
     }
 
     /// Utility method for making a key with just a password
@@ -41,30 +67,156 @@ impl CompositeKey {
         CompositeKey::new(Some(pw.into()), None)
     }
 
-    pub(crate) fn composed(&self) -> ComposedKey {
-        let mut buffer = Vec::new();
-        if let Some(ref pw) = self.pw {
-            buffer.extend(Sha256::digest(pw.as_bytes()))
-            //buffer.extend(pw.as_bytes());
+    /// Add a hardware token's response to a challenge, computed ahead of time
+    ///
+    /// `seed` is the challenge sent to the token and `response` the token's
+    /// answer. As in KeePassXC, the challenge is the KDF salt of a KDBX 4
+    /// database, and the hash of the response is combined with the other
+    /// credentials before key derivation. For KDBX 3.1 databases the challenge
+    /// is the master seed, and the hash of the response is combined with the
+    /// derived key instead. Using the key with a database whose seed differs
+    /// fails with [`KeyGenerationError::ChallengeSeedMismatch`]. The response
+    /// is ignored for KeePass 1 databases.
+    pub fn with_precomputed_response(mut self, seed: &[u8], response: &[u8]) -> CompositeKey {
+        self.challenge_response = Some(ChallengeResponse {
+            seed: seed.to_vec(),
+            response: response.to_vec(),
+        });
+        self
+    }
+
+    /// Seed a precomputed challenge response was made for, if any
+    pub(crate) fn challenge_seed(&self) -> Option<&[u8]> {
+        self.challenge_response
+            .as_ref()
+            .map(|challenge_response| &challenge_response.seed[..])
+    }
+
+    /// Hash of the challenge response, checking it answers the given seed
+    fn challenge_key(&self, seed: &[u8]) -> Result<Vec<u8>, KeyGenerationError> {
+        match self.challenge_response {
+            Some(ref challenge_response) if challenge_response.seed != seed => {
+                Err(KeyGenerationError::ChallengeSeedMismatch)
+            }
+            Some(ref challenge_response) => {
+                Ok(Sha256::digest(&challenge_response.response).to_vec())
+            }
+            None => Ok(Vec::new()),
         }
-        if let Some(ref keyfile) = self.keyfile {
-            buffer.extend(Sha256::digest(keyfile))
+    }
+
+    /// Combine credentials for a KDBX database with the given seeds and version
+    pub(crate) fn composed(
+        &self,
+        master_seed: &[u8],
+        kdf_params: &binary::KdfParams,
+        major_version: u16,
+    ) -> Result<ComposedKey, KeyGenerationError> {
+        let (response_key, challenge_key) = if major_version >= 4 {
+            (
+                Zeroizing::new(self.challenge_key(kdf_params.seed())?),
+                Vec::new(),
+            )
+        } else {
+            (Zeroizing::new(Vec::new()), self.challenge_key(master_seed)?)
+        };
+        let material = match self.keyfile {
+            Some(ref keyfile) => Zeroizing::new(keyfile::key_material(keyfile)?),
+            None => Zeroizing::new(Vec::new()),
+        };
+        // Allocated once at full size, so no unwiped copies are left behind by growth
+        let mut buffer =
+            Zeroizing::new(Vec::with_capacity(32 + material.len() + response_key.len()));
+        if let Some(ref pw) = self.pw {
+            let mut pw_hash = Sha256::digest(pw.as_bytes());
+            buffer.extend_from_slice(&pw_hash);
+            pw_hash.as_mut_slice().zeroize();
         }
+        buffer.extend_from_slice(&material);
+        buffer.extend_from_slice(&response_key);
+
+        Ok(ComposedKey {
+            key: Sha256::digest(&*buffer).to_vec(),
+            challenge_key,
+        })
+    }
 
-        ComposedKey(Sha256::digest(&buffer).iter().cloned().collect())
+    /// Combine credentials the way KeePass 1 does for .kdb files
+    ///
+    /// Unlike KDBX, a lone password or key file is used without hashing
+    /// it a second time.
+    pub(crate) fn composed_kdb(&self) -> Result<ComposedKey, KeyGenerationError> {
+        let pw_hash = self
+            .pw
+            .as_ref()
+            .map(|pw| Zeroizing::new(Sha256::digest(pw.as_bytes()).to_vec()));
+        let keyfile_key = match self.keyfile {
+            Some(ref keyfile) => Some(Zeroizing::new(keyfile::key_material(keyfile)?)),
+            None => None,
+        };
+        let key = match (pw_hash, keyfile_key) {
+            (Some(pw_hash), None) => pw_hash.to_vec(),
+            (None, Some(keyfile_key)) => keyfile_key.to_vec(),
+            (pw_hash, keyfile_key) => {
+                let pw_hash = pw_hash.unwrap_or_default();
+                let keyfile_key = keyfile_key.unwrap_or_default();
+                let mut buffer =
+                    Zeroizing::new(Vec::with_capacity(pw_hash.len() + keyfile_key.len()));
+                buffer.extend_from_slice(&pw_hash);
+                buffer.extend_from_slice(&keyfile_key);
+                Sha256::digest(&*buffer).to_vec()
+            }
+        };
+        Ok(ComposedKey {
+            key,
+            challenge_key: Vec::new(),
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Zeroize, ZeroizeOnDrop)]
 /// Hashed combined input credentials used as KDF input
-pub struct ComposedKey(Vec<u8>);
+pub struct ComposedKey {
+    key: Vec<u8>,
+    /// Hashed challenge response combined with the derived key, for KDBX 3.1
+    challenge_key: Vec<u8>,
+}
 
 impl ComposedKey {
+    /// Wrap the output of the KDF as a master key
+    fn derived(&self, key: Vec<u8>) -> MasterKey {
+        MasterKey {
+            key,
+            challenge_key: self.challenge_key.clone(),
+        }
+    }
+
     /// Generate a master key used to derive all other keys
     pub fn master_key(
         &self,
         kdf_options: &binary::KdfParams,
     ) -> Result<MasterKey, KeyGenerationError> {
+        self.master_key_with_memory_limit(kdf_options, None)
+    }
+
+    /// Generate a master key, refusing KDF options that need more than `memory_limit` bytes
+    ///
+    /// The limit is checked before any memory is allocated for the KDF.
+    pub fn master_key_with_memory_limit(
+        &self,
+        kdf_options: &binary::KdfParams,
+        memory_limit: Option<u64>,
+    ) -> Result<MasterKey, KeyGenerationError> {
+        if let (binary::KdfParams::Argon2 { memory_bytes, .. }, Some(limit)) =
+            (kdf_options, memory_limit)
+        {
+            if *memory_bytes > limit {
+                return Err(KeyGenerationError::MemoryLimitExceeded {
+                    required: *memory_bytes,
+                    limit,
+                });
+            }
+        }
         match kdf_options {
             binary::KdfParams::Argon2 {
                 variant,
@@ -83,16 +235,16 @@ impl ComposedKey {
                     time_cost: *iterations as u32,
                     ..Default::default()
                 };
-                let hash = argon2::hash_raw(&self.0, salt, &config)
+                let hash = argon2::hash_raw(&self.key, salt, &config)
                     .map_err(|e| KeyGenerationError::KeyGeneration(e.to_string()))?;
 
-                Ok(MasterKey(hash))
+                Ok(self.derived(hash))
             }
             binary::KdfParams::Aes { rounds, salt } => {
                 use cipher::KeyInit;
                 let mut cipher = Aes256::new_from_slice(salt).unwrap();
                 let chunked: Vec<GenericArray<u8, _>> = self
-                    .0
+                    .key
                     .chunks_exact(16)
                     .map(|chunk| *GenericArray::from_slice(chunk))
                     .collect();
@@ -105,42 +257,53 @@ impl ComposedKey {
                 transformed_hasher.update(blocks[1]);
                 let transformed = transformed_hasher.finalize().to_vec();
 
-                Ok(MasterKey(transformed))
+                Ok(self.derived(transformed))
             }
-            _ => Ok(MasterKey(Vec::new())),
+            _ => Ok(self.derived(Vec::new())),
         }
     }
 }
 
 /// Master key - this is generated from the user's composite key and is used to generate all other keys
-#[derive(Debug)]
-pub struct MasterKey(Vec<u8>);
+#[derive(Debug, Zeroize, ZeroizeOnDrop)]
+pub struct MasterKey {
+    key: Vec<u8>,
+    challenge_key: Vec<u8>,
+}
 
 impl MasterKey {
     /// Obtain a key to use for data integrity checks
     pub(crate) fn hmac_key(&self, seed: &[u8]) -> HmacKey {
-        let mut data_to_hash = Vec::new();
-        data_to_hash.extend(seed.iter());
-        data_to_hash.extend(self.0.iter());
+        let mut data_to_hash = Zeroizing::new(Vec::with_capacity(seed.len() + self.key.len() + 1));
+        data_to_hash.extend_from_slice(seed);
+        data_to_hash.extend_from_slice(&self.key);
         data_to_hash.push(1);
 
-        HmacKey(Sha512::digest(&data_to_hash).iter().cloned().collect())
+        HmacKey(Sha512::digest(&*data_to_hash).to_vec())
     }
 
     /// Obtain a key to initialise a cipher
+    ///
+    /// For KDBX 3.1 databases the hashed challenge response, if any, is
+    /// combined between the seed and the derived key, as KeePassXC does.
     pub(crate) fn cipher_key(&self, seed: &[u8]) -> CipherKey {
-        let mut data_to_hash = Vec::new();
-        data_to_hash.extend(seed.iter());
-        data_to_hash.extend(self.0.iter());
+        let mut data_to_hash = Zeroizing::new(Vec::with_capacity(
+            seed.len() + self.challenge_key.len() + self.key.len(),
+        ));
+        data_to_hash.extend_from_slice(seed);
+        data_to_hash.extend_from_slice(&self.challenge_key);
+        data_to_hash.extend_from_slice(&self.key);
 
-        CipherKey(Sha256::digest(&data_to_hash).iter().cloned().collect())
+        CipherKey(Sha256::digest(&*data_to_hash).to_vec())
     }
 }
 
 /// Used to initialise the encryption/decryption cipher
+#[derive(Zeroize, ZeroizeOnDrop)]
 pub(crate) struct CipherKey(pub(crate) Vec<u8>);
 
 /// Base key for all HMAC data integrity checks
+#[derive(Zeroize, ZeroizeOnDrop)]
 pub(crate) struct HmacKey(Vec<u8>);
 
 impl HmacKey {
@@ -154,6 +317,7 @@ impl HmacKey {
 }
 
 /// Key to perform data integrity checks on a specific block
+#[derive(Zeroize, ZeroizeOnDrop)]
 pub(crate) struct HmacBlockKey(u64, Vec<u8>);
 
 impl HmacBlockKey {
@@ -214,4 +378,18 @@ pub enum KeyGenerationError {
     /// KDF Options are not supported by this library
     #[error("Generation for KDF Options: {0:?} not implemented")]
     UnimplementedKdfOptions(binary::KdfParams),
+    /// The key file is in the KeePass XML format but is not valid
+    #[error("Invalid key file: {0}")]
+    InvalidKeyFile(String),
+    /// A precomputed challenge response was made for a different master seed
+    #[error("Challenge response was computed for a different master seed")]
+    ChallengeSeedMismatch,
+    /// The KDF needs more memory than the caller allowed
+    #[error("KDF requires {required} bytes of memory, more than the limit of {limit}")]
+    MemoryLimitExceeded {
+        /// Memory the KDF options ask for in bytes
+        required: u64,
+        /// Maximum memory allowed in bytes
+        limit: u64,
+    },
 }
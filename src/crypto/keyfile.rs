@@ -0,0 +1,227 @@
+//! Key file handling
+//!
+//! KeePass key files can be in one of several formats, checked in this order:
+//!
+//! * XML files in the KeePass 2.x `<KeyFile>` format, where the key is stored
+//!   directly in the file.
+//! * Files of exactly 32 bytes, which are used as the key as-is.
+//! * Files of exactly 64 hex characters, which are decoded to a 32 byte key.
+//! * Any other file, which is hashed with SHA-256 to obtain the key.
+
+use super::KeyGenerationError;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+use xml::reader::{EventReader, XmlEvent};
+
+/// Contents of a parsed KeePass XML key file
+struct XmlKeyFile {
+    version: Option<String>,
+    data: Option<String>,
+    hash: Option<String>,
+}
+
+/// Parse a KeePass XML key file, returning `None` if the data is not one
+fn parse_xml_key_file(data: &[u8]) -> Option<XmlKeyFile> {
+    let xml_config = xml::ParserConfig::new()
+        .trim_whitespace(true)
+        .cdata_to_characters(true);
+    let reader = EventReader::new_with_config(data, xml_config);
+    let mut path: Vec<String> = Vec::new();
+    let mut key_file = XmlKeyFile {
+        version: None,
+        data: None,
+        hash: None,
+    };
+
+    for event in reader {
+        match event.ok()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                if path.is_empty() && name.local_name != "KeyFile" {
+                    return None;
+                }
+                path.push(name.local_name);
+                if path == ["KeyFile", "Key", "Data"] {
+                    key_file.hash = attributes
+                        .into_iter()
+                        .find(|attr| attr.name.local_name == "Hash")
+                        .map(|attr| attr.value);
+                }
+            }
+            XmlEvent::Characters(chardata) => {
+                if path == ["KeyFile", "Meta", "Version"] {
+                    key_file.version = Some(chardata);
+                } else if path == ["KeyFile", "Key", "Data"] {
+                    key_file
+                        .data
+                        .get_or_insert_with(String::new)
+                        .push_str(&chardata);
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+
+    Some(key_file)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn xml_key_material(key_file: XmlKeyFile) -> Result<Vec<u8>, KeyGenerationError> {
+    let data = key_file
+        .data
+        .ok_or_else(|| KeyGenerationError::InvalidKeyFile("No key data".into()))?;
+    let version = key_file.version.unwrap_or_default();
+    match version.split('.').next() {
+        Some("1") => BASE64_STANDARD
+            .decode(data.trim())
+            .map_err(|_| KeyGenerationError::InvalidKeyFile("Key data not valid base64".into())),
+        Some("2") => {
+            let hex: String = data.split_whitespace().collect();
+            let key = decode_hex(&hex).ok_or_else(|| {
+                KeyGenerationError::InvalidKeyFile("Key data not valid hex".into())
+            })?;
+            if let Some(expected_hash) = key_file.hash {
+                let hash = decode_hex(expected_hash.trim()).ok_or_else(|| {
+                    KeyGenerationError::InvalidKeyFile("Key hash not valid hex".into())
+                })?;
+                if hash.is_empty() || hash.len() > 32 {
+                    return Err(KeyGenerationError::InvalidKeyFile(
+                        "Key hash must be 1 to 32 bytes".into(),
+                    ));
+                }
+                if Sha256::digest(&key)[..hash.len()] != hash[..] {
+                    return Err(KeyGenerationError::InvalidKeyFile(
+                        "Key data does not match hash".into(),
+                    ));
+                }
+            }
+            Ok(key)
+        }
+        _ => Err(KeyGenerationError::InvalidKeyFile(format!(
+            "Unsupported key file version {:?}",
+            version
+        ))),
+    }
+}
+
+/// Obtain the 32 byte key used in the composite key from the contents of a key file
+pub(crate) fn key_material(keyfile: &[u8]) -> Result<Vec<u8>, KeyGenerationError> {
+    if let Some(key_file) = parse_xml_key_file(keyfile) {
+        return xml_key_material(key_file);
+    }
+    if keyfile.len() == 32 {
+        return Ok(keyfile.to_vec());
+    }
+    if keyfile.len() == 64 {
+        if let Some(key) = std::str::from_utf8(keyfile).ok().and_then(decode_hex) {
+            return Ok(key);
+        }
+    }
+    Ok(Sha256::digest(keyfile).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [
+        0x0a, 0x1b, 0x2c, 0x3d, 0x4e, 0x5f, 0x60, 0x71, 0x82, 0x93, 0xa4, 0xb5, 0xc6, 0xd7, 0xe8,
+        0xf9, 0x01, 0x12, 0x23, 0x34, 0x45, 0x56, 0x67, 0x78, 0x89, 0x9a, 0xab, 0xbc, 0xcd, 0xde,
+        0xef, 0xf0,
+    ];
+
+    fn v2_key_file(hash: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<KeyFile>
+    <Meta>
+        <Version>2.0</Version>
+    </Meta>
+    <Key>
+        <Data Hash="{}">
+            0A1B2C3D 4E5F6071 8293A4B5 C6D7E8F9
+            01122334 45566778 899AABBC CDDEEFF0
+        </Data>
+    </Key>
+</KeyFile>"#,
+            hash
+        )
+    }
+
+    #[test]
+    fn xml_v1_key_file() {
+        let key_file = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<KeyFile>
+    <Meta>
+        <Version>1.00</Version>
+    </Meta>
+    <Key>
+        <Data>{}</Data>
+    </Key>
+</KeyFile>"#,
+            BASE64_STANDARD.encode(KEY)
+        );
+        assert_eq!(key_material(key_file.as_bytes()).unwrap(), KEY);
+    }
+
+    #[test]
+    fn xml_v2_key_file() {
+        let hash: String = Sha256::digest(KEY)[..4]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let key_file = v2_key_file(&hash);
+        assert_eq!(key_material(key_file.as_bytes()).unwrap(), KEY);
+    }
+
+    #[test]
+    fn xml_v2_key_file_bad_hash() {
+        let key_file = v2_key_file("00000000");
+        assert!(matches!(
+            key_material(key_file.as_bytes()),
+            Err(KeyGenerationError::InvalidKeyFile(_))
+        ));
+    }
+
+    #[test]
+    fn raw_32_byte_key_file() {
+        assert_eq!(key_material(&KEY).unwrap(), KEY);
+    }
+
+    #[test]
+    fn hex_64_byte_key_file() {
+        let key_file = b"0A1B2C3D4E5F60718293a4b5c6d7e8f90112233445566778899AABBCCDDEEFF0";
+        assert_eq!(key_material(key_file).unwrap(), KEY);
+    }
+
+    #[test]
+    fn other_key_file_is_hashed() {
+        let not_hex = b"0A1B2C3D4E5F60718293a4b5c6d7e8f90112233445566778899AABBCCDDEEFFZ";
+        assert_eq!(
+            key_material(not_hex).unwrap(),
+            Sha256::digest(not_hex).to_vec()
+        );
+        let short = b"key-file";
+        assert_eq!(key_material(short).unwrap(), Sha256::digest(short).to_vec());
+    }
+
+    #[test]
+    fn other_xml_is_hashed() {
+        let data = b"<?xml version=\"1.0\"?><NotAKeyFile />";
+        assert_eq!(key_material(data).unwrap(), Sha256::digest(data).to_vec());
+    }
+}
@@ -44,26 +44,32 @@
 //!
 //! ### Moving an entry from one folder to another
 //!
-//! [`Group::find_entry_mut()`] gives us a reference, while moving a folder to
-//! another group requires an owned [`Entry`]. So instead we take its UUID
-//! and remove it from the source group first.
+//! Entries are moved by UUID, from wherever they are in the database.
 //!
 //! ```
 //! # let mut database = kdbx_rs::database::doc_sample_db();
-//! let uuid = database.find_entry_mut(|f| f.title() == Some("Foo"))
+//! let uuid = database.find_entry(|f| f.title() == Some("Foo"))
 //!     .unwrap()
 //!     .uuid();
-//! # let mut source_group = database.root_mut();
-//! let entry = source_group.remove_entry(uuid).unwrap();
-//!
-//! let mut target_group = database.find_group_mut(|g| g.name() == "Child Group").unwrap();
-//! target_group.add_entry(entry);
+//! let target = database.find_group(|g| g.name() == "Child Group")
+//!     .unwrap()
+//!     .uuid();
+//! assert!(database.move_entry(uuid, target));
 //! ```
 
+use crate::stream::random::InnerStreamKey;
+use crate::xml::ParseWarning;
 use chrono::{NaiveDateTime, Timelike};
 use std::borrow::Cow;
-use std::ops::{Index, IndexMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+use std::sync::Arc;
+use thiserror::Error;
 use uuid::Uuid;
+use zeroize::Zeroize;
+
+/// Field keys treated as standard by default
+const STANDARD_FIELDS: [&str; 6] = ["Title", "UserName", "Password", "URL", "Notes", "otp"];
 
 #[doc(hidden)]
 pub fn doc_sample_db() -> Database {
@@ -92,7 +98,7 @@ pub fn doc_sample_db() -> Database {
 }
 
 /// A value for a `Field` stored in an `Entry`
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 pub(crate) enum Value {
     /// A value using in-memory encryption
     Protected(String),
@@ -102,6 +108,54 @@ pub(crate) enum Value {
     Empty,
     /// A empty value that should be protected if filled
     ProtectEmpty,
+    /// A protected value still encrypted with the inner stream cipher
+    Lazy(LazyValue),
+}
+
+/// Protected value whose decryption is deferred until it is read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LazyValue {
+    ciphertext: Vec<u8>,
+    offset: u64,
+    stream: Arc<InnerStreamKey>,
+}
+
+impl LazyValue {
+    pub(crate) fn new(ciphertext: Vec<u8>, offset: u64, stream: Arc<InnerStreamKey>) -> LazyValue {
+        LazyValue {
+            ciphertext,
+            offset,
+            stream,
+        }
+    }
+
+    /// Decrypt the value, returning `None` if it is not valid UTF-8
+    pub(crate) fn reveal(&self) -> Option<String> {
+        let mut plain = self.ciphertext.clone();
+        self.stream.decrypt_at(&mut plain, self.offset).ok()?;
+        String::from_utf8(plain)
+            .map_err(|e| e.into_bytes().zeroize())
+            .ok()
+    }
+}
+
+impl Value {
+    /// Length of the value in bytes
+    fn len(&self) -> usize {
+        match self {
+            Value::Protected(s) | Value::Standard(s) => s.len(),
+            Value::Lazy(lazy) => lazy.ciphertext.len(),
+            Value::Empty | Value::ProtectEmpty => 0,
+        }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        if let Value::Protected(ref mut s) = self {
+            s.zeroize();
+        }
+    }
 }
 
 impl Default for Value {
@@ -110,8 +164,73 @@ impl Default for Value {
     }
 }
 
+/// Protected values are hidden so they do not end up in logs
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Protected(_) => f.debug_tuple("Protected").field(&"***").finish(),
+            Value::Standard(s) => f.debug_tuple("Standard").field(s).finish(),
+            Value::Empty => f.write_str("Empty"),
+            Value::ProtectEmpty => f.write_str("ProtectEmpty"),
+            Value::Lazy(lazy) => f.debug_tuple("Lazy").field(lazy).finish(),
+        }
+    }
+}
+
+/// Serialized form of a `Value`, tagged with its kind
+///
+/// Lazily decrypted values are serialized as protected values.
+#[cfg(feature = "serde")]
+#[derive(serde_crate::Serialize, serde_crate::Deserialize)]
+#[serde(
+    crate = "serde_crate",
+    tag = "kind",
+    content = "value",
+    rename_all = "snake_case"
+)]
+enum ValueRepr<'a> {
+    Protected(Cow<'a, str>),
+    Standard(Cow<'a, str>),
+    Empty,
+    ProtectEmpty,
+}
+
+#[cfg(feature = "serde")]
+impl serde_crate::Serialize for Value {
+    fn serialize<S: serde_crate::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match self {
+            Value::Protected(s) => ValueRepr::Protected(Cow::Borrowed(s)),
+            Value::Standard(s) => ValueRepr::Standard(Cow::Borrowed(s)),
+            Value::Empty => ValueRepr::Empty,
+            Value::ProtectEmpty => ValueRepr::ProtectEmpty,
+            Value::Lazy(lazy) => ValueRepr::Protected(Cow::Owned(
+                lazy.reveal()
+                    .ok_or_else(|| serde_crate::ser::Error::custom("invalid protected value"))?,
+            )),
+        };
+        repr.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde_crate::Deserialize<'de> for Value {
+    fn deserialize<D: serde_crate::Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        Ok(match ValueRepr::deserialize(deserializer)? {
+            ValueRepr::Protected(s) => Value::Protected(s.into_owned()),
+            ValueRepr::Standard(s) => Value::Standard(s.into_owned()),
+            ValueRepr::Empty => Value::Empty,
+            ValueRepr::ProtectEmpty => Value::ProtectEmpty,
+        })
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 /// A key value pair
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct Field {
     /// The name of this field
     pub(crate) key: String,
@@ -147,6 +266,10 @@ impl Field {
     }
 
     /// Value for this field
+    ///
+    /// Protected values that were left encrypted by
+    /// [`ParseOptions::lazy_protected`][crate::xml::ParseOptions#structfield.lazy_protected]
+    /// return `None` here, use [`Field::reveal`] to decrypt them.
     pub fn value(&self) -> Option<&str> {
         match self.value {
             Value::Protected(ref s) => Some(s),
@@ -155,6 +278,15 @@ impl Field {
         }
     }
 
+    /// Value for this field, decrypting it first if it was parsed lazily
+    pub fn reveal(&self) -> Option<Cow<'_, str>> {
+        match self.value {
+            Value::Protected(ref s) | Value::Standard(ref s) => Some(Cow::Borrowed(s)),
+            Value::Lazy(ref lazy) => lazy.reveal().map(Cow::Owned),
+            _ => None,
+        }
+    }
+
     /// Set a new value for this field
     pub fn set_value(&mut self, value: &str) {
         if self.protected() {
@@ -178,7 +310,7 @@ impl Field {
     /// Note: This is instructional for official clients, this library does not
     /// support memory protection
     pub fn protected(&self) -> bool {
-        matches!(self.value, Value::Protected(_))
+        matches!(self.value, Value::Protected(_) | Value::Lazy(_))
     }
 
     /// Set whether memory protection and extra encryption should be applied
@@ -186,19 +318,46 @@ impl Field {
     /// Note: This is instructional for official clients, this library does not
     /// support memory protection
     pub fn set_protected(&mut self, protected: bool) {
-        let existing_value = std::mem::take(&mut self.value);
-        self.value = match (protected, existing_value) {
-            (true, Value::Standard(s)) => Value::Protected(s),
-            (false, Value::Protected(s)) => Value::Standard(s),
+        self.value = match (protected, &mut self.value) {
+            (true, Value::Standard(s)) => Value::Protected(std::mem::take(s)),
+            (false, Value::Protected(s)) => Value::Standard(std::mem::take(s)),
             (true, Value::Empty) => Value::ProtectEmpty,
             (false, Value::ProtectEmpty) => Value::Empty,
-            (_, v) => v,
+            (false, Value::Lazy(lazy)) => match lazy.reveal() {
+                Some(s) => Value::Standard(s),
+                None => return,
+            },
+            _ => return,
         }
     }
+
+    /// Cut the value down to at most `max_len` bytes, on a character boundary
+    fn truncate(&mut self, max_len: usize) {
+        let protected = self.protected();
+        let mut value = match self.reveal() {
+            Some(value) if value.len() > max_len => value.into_owned(),
+            _ => return,
+        };
+        let mut end = max_len;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+        self.value = if protected {
+            Value::Protected(value)
+        } else {
+            Value::Standard(value)
+        };
+    }
 }
 
 /// Historical versions of a single entry
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct History {
     entries: Vec<Entry>,
 }
@@ -258,8 +417,155 @@ impl IndexMut<usize> for History {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Binary data referenced by entry attachments
+///
+/// KDBX 4 databases store these in the inner header, older versions in the
+/// meta section of the XML.
+pub struct Binary {
+    /// Identifier attachments use to refer to this binary
+    pub id: usize,
+    /// Contents of the binary
+    pub data: Vec<u8>,
+    /// Whether memory protection should be applied
+    pub protected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A file attached to an entry
+///
+/// Copies of an attachment, such as in an entry's history, share the same data.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct Attachment {
+    /// File name of the attachment
+    pub(crate) name: String,
+    /// Contents of the attachment
+    pub(crate) data: Arc<Vec<u8>>,
+    /// Whether memory protection should be applied
+    pub(crate) protected: bool,
+}
+
+impl Attachment {
+    /// Create a new attachment from a file name and contents
+    pub fn new<S: ToString>(name: S, data: Vec<u8>) -> Attachment {
+        Attachment {
+            name: name.to_string(),
+            data: Arc::new(data),
+            protected: false,
+        }
+    }
+
+    /// File name of the attachment
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Contents of the attachment
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get whether memory protection should be applied
+    ///
+    /// Note: This is instructional for official clients, this library does not
+    /// support memory protection
+    pub fn protected(&self) -> bool {
+        self.protected
+    }
+
+    /// Set whether memory protection should be applied
+    pub fn set_protected(&mut self, protected: bool) {
+        self.protected = protected;
+    }
+}
+
+/// Binaries referenced by a database's attachments, numbered in order of first use
+///
+/// Attachments with identical contents share a single binary, as in KeePass.
+#[derive(Debug, Default)]
+pub(crate) struct BinaryPool {
+    binaries: Vec<(Arc<Vec<u8>>, bool)>,
+    ids: HashMap<*const Vec<u8>, usize>,
+    hashes: HashMap<Vec<u8>, usize>,
+}
+
+impl BinaryPool {
+    /// Collect the binaries used by every entry in the database, including history
+    pub(crate) fn new(database: &Database) -> BinaryPool {
+        let mut pool = BinaryPool::default();
+        for entry in database.root().recursive_entries() {
+            for version in std::iter::once(entry).chain(entry.history().entries()) {
+                for attachment in &version.attachments {
+                    pool.add(attachment);
+                }
+            }
+        }
+        pool
+    }
+
+    fn add(&mut self, attachment: &Attachment) {
+        let binaries = &mut self.binaries;
+        let hashes = &mut self.hashes;
+        let id = *self
+            .ids
+            .entry(Arc::as_ptr(&attachment.data))
+            .or_insert_with(|| {
+                *hashes
+                    .entry(crate::crypto::sha256(&attachment.data))
+                    .or_insert_with(|| {
+                        binaries.push((attachment.data.clone(), false));
+                        binaries.len() - 1
+                    })
+            });
+        binaries[id].1 |= attachment.protected;
+    }
+
+    /// Identifier of the binary holding an attachment's data
+    pub(crate) fn id(&self, attachment: &Attachment) -> Option<usize> {
+        self.ids.get(&Arc::as_ptr(&attachment.data)).copied()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.binaries.is_empty()
+    }
+
+    /// Binaries in identifier order, with whether each is protected
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&[u8], bool)> {
+        self.binaries
+            .iter()
+            .map(|(data, protected)| (data.as_slice(), *protected))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// XML preserved from a source file to be written back out unchanged
+pub(crate) enum XmlNode {
+    /// An XML comment
+    Comment(String),
+    /// An element not understood by this library, along with its contents
+    Element {
+        /// Name of the element
+        name: xml::name::OwnedName,
+        /// Attributes on the element
+        attributes: Vec<xml::attribute::OwnedAttribute>,
+        /// Text, comments and elements inside the element
+        children: Vec<XmlNode>,
+    },
+    /// Text inside an unknown element
+    Text(String),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// A single password entry
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct Entry {
     /// Identifier for this entry
     uuid: Uuid,
@@ -269,11 +575,80 @@ pub struct Entry {
     pub(crate) history: History,
     /// Information about access times
     pub(crate) times: Times,
+    /// Custom icon shown for this entry
+    pub(crate) custom_icon_uuid: Option<Uuid>,
+    /// Files attached to this entry
+    pub(crate) attachments: Vec<Attachment>,
+    /// Plugin data stored with this entry
+    pub(crate) custom_data: Vec<CustomDataItem>,
+    /// XML from the source file not otherwise understood by this library
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) unknown_xml: Vec<XmlNode>,
+    /// Automatic history state, not stored in the database
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history_tracking: HistoryTracking,
+}
+
+/// Automatic history state for an entry
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct HistoryTracking {
+    enabled: bool,
+    /// Whether a version has been pushed to history since [`Database::mark_saved`] was last called
+    snapshot_taken: bool,
 }
 
 impl Entry {
+    /// Enable automatic history for this entry
+    ///
+    /// See [`Entry::set_history_tracking`].
+    pub fn with_history_tracking(mut self) -> Entry {
+        self.set_history_tracking(true);
+        self
+    }
+
+    /// Whether changes made through this entry's setters are versioned automatically
+    pub fn history_tracking(&self) -> bool {
+        self.history_tracking.enabled
+    }
+
+    /// Set whether changes made through this entry's setters are versioned automatically
+    ///
+    /// When enabled, the first change made by [`Entry::add_field`],
+    /// [`Entry::remove_field`] or a `set_*` method calls [`Entry::new_version`]
+    /// first. Later changes only update the modification time, until
+    /// [`Database::mark_saved`] is called, usually after saving the database. Changes made through [`Entry::fields_mut`]
+    /// or [`Entry::find_mut`] are not tracked.
+    pub fn set_history_tracking(&mut self, enabled: bool) {
+        self.history_tracking.enabled = enabled;
+    }
+
+    /// Borrow this entry mutably, updating its modification time if it is changed
+    ///
+    /// The modification time is set to now when the returned guard is dropped,
+    /// if the entry was mutably accessed through it.
+    pub fn tracked_mut(&mut self) -> EntryGuard<'_> {
+        EntryGuard {
+            entry: self,
+            modified: false,
+        }
+    }
+
+    /// Snapshot the entry before a tracked change
+    fn before_change(&mut self) {
+        if !self.history_tracking.enabled {
+            return;
+        }
+        if self.history_tracking.snapshot_taken {
+            self.times.last_modification_time = Times::default().last_modification_time;
+        } else {
+            self.new_version();
+            self.history_tracking.snapshot_taken = true;
+        }
+    }
+
     /// Add a new field to the entry
     pub fn add_field(&mut self, field: Field) {
+        self.before_change();
         self.fields.push(field);
     }
 
@@ -281,6 +656,7 @@ impl Entry {
     ///
     /// If there are duplicate fields, removes them all
     pub fn remove_field(&mut self, key: &str) {
+        self.before_change();
         let mut matching_field_indices: Vec<_> = self
             .fields
             .iter()
@@ -294,11 +670,201 @@ impl Entry {
         }
     }
 
+    /// Files attached to this entry
+    pub fn attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments.iter()
+    }
+
+    /// Find an attachment by its file name
+    pub fn attachment(&self, name: &str) -> Option<&Attachment> {
+        self.attachments.iter().find(|a| a.name == name)
+    }
+
+    /// Find an attachment by its file name, returns it mutably
+    pub fn attachment_mut(&mut self, name: &str) -> Option<&mut Attachment> {
+        self.attachments.iter_mut().find(|a| a.name == name)
+    }
+
+    /// Attach a file to this entry, replacing any attachment with the same name
+    pub fn add_attachment<S: ToString>(&mut self, name: S, data: Vec<u8>) {
+        self.before_change();
+        let attachment = Attachment::new(name, data);
+        match self
+            .attachments
+            .iter_mut()
+            .find(|a| a.name == attachment.name)
+        {
+            Some(existing) => *existing = attachment,
+            None => self.attachments.push(attachment),
+        }
+    }
+
+    /// Bytes of attachment data used by this entry in a database
+    ///
+    /// Attachments sharing the same binary in `db` are counted once. History
+    /// versions are not included.
+    pub fn attachment_bytes(&self, db: &Database) -> usize {
+        let pool = BinaryPool::new(db);
+        let mut counted = HashSet::new();
+        self.attachments
+            .iter()
+            .map(|attachment| match pool.id(attachment) {
+                Some(id) if !counted.insert(id) => 0,
+                _ => attachment.data.len(),
+            })
+            .sum()
+    }
+
+    /// Remove an attachment by its file name, returning whether it was present
+    pub fn remove_attachment(&mut self, name: &str) -> bool {
+        if self.attachment(name).is_none() {
+            return false;
+        }
+        self.before_change();
+        self.attachments.retain(|a| a.name != name);
+        true
+    }
+
+    /// Append the times, fields and attachments of this entry to a text dump
+    fn write_text(&self, output: &mut String, options: &TextOptions) {
+        use std::fmt::Write;
+        self.times.write_text(output);
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by(|a, b| a.key.cmp(&b.key));
+        for field in fields {
+            let value = match field.reveal() {
+                Some(value) if field.protected() && !options.reveal_protected => format!(
+                    "sha256:{}",
+                    crate::utils::to_hex_string(&crate::crypto::sha256(value.as_bytes()))
+                ),
+                Some(value) => format!("{:?}", value),
+                None => "<undecryptable>".to_string(),
+            };
+            writeln!(output, "    {:?} = {}", field.key, value).unwrap();
+        }
+        let mut attachments: Vec<_> = self.attachments.iter().collect();
+        attachments.sort_by(|a, b| a.name.cmp(&b.name));
+        for attachment in attachments {
+            writeln!(
+                output,
+                "    Attachment {:?} = sha256:{} ({} bytes)",
+                attachment.name,
+                crate::utils::to_hex_string(&crate::crypto::sha256(&attachment.data)),
+                attachment.data.len()
+            )
+            .unwrap();
+        }
+    }
+
     /// Generate a new version of this entry, pushing the current state to history
+    ///
+    /// The historical version keeps its own times, while the current entry's
+    /// modification time is updated to now.
     pub fn new_version(&mut self) {
         let mut new_entry = self.clone();
         new_entry.history = History::default();
         self.history.push(new_entry);
+        self.times.last_modification_time = Times::default().last_modification_time;
+    }
+
+    /// Copy this entry with a new UUID, for adding alongside the original
+    ///
+    /// Fields and history are kept, while the creation and modification
+    /// times are set to now.
+    pub fn duplicate(&self) -> Entry {
+        let mut entry = self.clone();
+        entry.uuid = Uuid::new_v4();
+        for version in entry.history.entries_mut() {
+            version.uuid = entry.uuid;
+        }
+        let now = current_time();
+        entry.times.creation_time = now;
+        entry.times.last_modification_time = now;
+        entry
+    }
+
+    /// Estimated serialized size of this entry's fields in bytes
+    fn estimated_size(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|field| field.key.len() + field.value.len())
+            .sum()
+    }
+
+    /// Remove the oldest history versions until within the given limits
+    ///
+    /// The size of each version is estimated from the byte length of its
+    /// field keys and values.
+    pub fn prune_history(&mut self, max_items: Option<usize>, max_size: Option<usize>) {
+        let entries = &mut self.history.entries;
+        if let Some(max_items) = max_items {
+            let excess = entries.len().saturating_sub(max_items);
+            entries.drain(..excess);
+        }
+        if let Some(max_size) = max_size {
+            let mut total: usize = entries.iter().map(Entry::estimated_size).sum();
+            let mut excess = 0;
+            for version in entries.iter() {
+                if total <= max_size {
+                    break;
+                }
+                total -= version.estimated_size();
+                excess += 1;
+            }
+            entries.drain(..excess);
+        }
+    }
+
+    /// Remove lazily parsed protected fields that fail to decrypt, including in history
+    fn drop_undecryptable_fields(&mut self) -> usize {
+        let before = self.fields.len();
+        self.fields
+            .retain(|f| !matches!(&f.value, Value::Lazy(lazy) if lazy.reveal().is_none()));
+        let history_removed: usize = self
+            .history
+            .entries_mut()
+            .map(Entry::drop_undecryptable_fields)
+            .sum();
+        before - self.fields.len() + history_removed
+    }
+
+    /// Copy of this entry with lazily parsed protected fields decrypted, including in history
+    ///
+    /// Automatic history state is not stored in the file, so it is reset in the copy.
+    fn revealed(&self) -> Entry {
+        let mut entry = self.clone();
+        entry.history_tracking = HistoryTracking::default();
+        for field in &mut entry.fields {
+            if let Value::Lazy(lazy) = &field.value {
+                if let Some(value) = lazy.reveal() {
+                    field.value = Value::Protected(value);
+                }
+            }
+        }
+        for version in entry.history.entries_mut() {
+            *version = version.revealed();
+        }
+        entry
+    }
+
+    /// Add `older` and its history to this entry's history, skipping versions already present
+    fn absorb_versions(&mut self, older: &Entry) {
+        for version in older.history.entries().chain(std::iter::once(older)) {
+            let time = version.times.last_modification_time;
+            let known = time == self.times.last_modification_time
+                || self
+                    .history
+                    .entries()
+                    .any(|e| e.times.last_modification_time == time);
+            if !known {
+                let mut version = version.clone();
+                version.history = History::default();
+                self.history.push(version);
+            }
+        }
+        self.history
+            .entries
+            .sort_by_key(|e| e.times.last_modification_time);
     }
 
     /// Iterate through all the fields
@@ -311,6 +877,13 @@ impl Entry {
         self.fields.iter_mut()
     }
 
+    /// Fields other than the database's standard fields
+    pub fn custom_fields<'a>(&'a self, db: &'a Database) -> impl Iterator<Item = &'a Field> {
+        self.fields
+            .iter()
+            .filter(move |field| !db.standard_fields.contains(&field.key))
+    }
+
     /// Iterate through all the fields
     pub fn history(&self) -> &History {
         &self.history
@@ -326,6 +899,18 @@ impl Entry {
         self.fields.iter().find(|i| i.key.as_str() == key)
     }
 
+    /// Find a field in this entry with a given key, ignoring case
+    ///
+    /// An exact match is preferred over one differing only in case.
+    pub fn find_ignore_case(&self, key: &str) -> Option<&Field> {
+        let key_lower = key.to_lowercase();
+        self.find(key).or_else(|| {
+            self.fields
+                .iter()
+                .find(|i| i.key.to_lowercase() == key_lower)
+        })
+    }
+
     /// Find a field in this entry with a given key
     pub fn find_mut(&mut self, key: &str) -> Option<&mut Field> {
         self.fields.iter_mut().find(|i| i.key.as_str() == key)
@@ -341,10 +926,110 @@ impl Entry {
         &mut self.times
     }
 
-    fn find_string_value(&self, key: &str) -> Option<&str> {
+    /// Record a use of this entry, such as copying or autofilling it
+    ///
+    /// See [`Times::touch`]
+    pub fn touch(&mut self) {
+        self.times.touch();
+    }
+
+    /// Whether this entry has passed its expiry time
+    pub fn is_expired(&self) -> bool {
+        self.times.is_expired()
+    }
+
+    /// UUID of the custom icon shown for this entry, if any
+    pub fn custom_icon_uuid(&self) -> Option<Uuid> {
+        self.custom_icon_uuid
+    }
+
+    /// Set or clear the custom icon shown for this entry
+    pub fn set_custom_icon_uuid(&mut self, uuid: Option<Uuid>) {
+        self.custom_icon_uuid = uuid;
+    }
+
+    /// Plugin data stored with this entry
+    pub fn custom_data(&self) -> &[CustomDataItem] {
+        &self.custom_data
+    }
+
+    /// Mutable access to the plugin data stored with this entry
+    pub fn custom_data_mut(&mut self) -> &mut Vec<CustomDataItem> {
+        &mut self.custom_data
+    }
+
+    /// Use a PNG image as this entry's icon
+    ///
+    /// The image is added to the database's custom icons if not already present.
+    pub fn set_custom_icon_png(&mut self, db: &mut Database, png: &[u8]) {
+        self.custom_icon_uuid = Some(db.meta_mut().add_custom_icon_png(png));
+    }
+
+    /// Value of the field in this entry with a given key
+    ///
+    /// Like [`Field::value`], lazily parsed protected values return `None`,
+    /// use [`Entry::reveal`] to decrypt them.
+    pub fn value(&self, key: &str) -> Option<&str> {
         self.find(key).and_then(|f| f.value())
     }
 
+    /// Value of the field in this entry with a given key, decrypting it first if it was parsed lazily
+    pub fn reveal(&self, key: &str) -> Option<Cow<'_, str>> {
+        self.find(key).and_then(|f| f.reveal())
+    }
+
+    /// Replace placeholders in `text` with this entry's field values
+    ///
+    /// Supports `{TITLE}`, `{USERNAME}`, `{PASSWORD}`, `{URL}`, `{NOTES}`,
+    /// `{UUID}` and `{S:<field name>}` for custom fields, ignoring case.
+    /// Unknown placeholders and fields missing from this entry are left as is.
+    pub fn expand_placeholders(&self, text: &str) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+            let value = placeholder
+                .find('}')
+                .and_then(|end| Some((self.placeholder_value(&placeholder[1..end])?, end)));
+            match value {
+                Some((value, end)) => {
+                    expanded.push_str(&value);
+                    rest = &placeholder[end + 1..];
+                }
+                None => {
+                    expanded.push('{');
+                    rest = &placeholder[1..];
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    fn placeholder_value(&self, name: &str) -> Option<Cow<'_, str>> {
+        let upper = name.to_ascii_uppercase();
+        let key = match upper.as_str() {
+            "TITLE" => "Title",
+            "USERNAME" => "UserName",
+            "PASSWORD" => "Password",
+            "URL" => "URL",
+            "NOTES" => "Notes",
+            "UUID" => return Some(Cow::Owned(self.uuid.simple().to_string().to_uppercase())),
+            _ if upper.starts_with("S:") => &name[2..],
+            _ => return None,
+        };
+        self.find(key).and_then(|f| f.reveal())
+    }
+
+    /// Value of the field with a given key, with `{REF:...}` field references replaced
+    ///
+    /// See [`Database::resolve_references`] for the supported syntax.
+    pub fn resolved_value(&self, key: &str, db: &Database) -> Option<String> {
+        let value = self.find(key)?.reveal()?;
+        Some(db.resolve_references(&value))
+    }
+
     /// Set the identifier for this item
     pub fn uuid(&self) -> Uuid {
         self.uuid
@@ -357,11 +1042,12 @@ impl Entry {
 
     /// Return the title of this item
     pub fn title(&self) -> Option<&str> {
-        self.find_string_value("Title")
+        self.value("Title")
     }
 
     /// Set the title of this entry
     pub fn set_title<S: ToString>(&mut self, title: S) {
+        self.before_change();
         let title = title.to_string();
         match self.find_mut("Title") {
             Some(f) => f.value = Value::Standard(title),
@@ -371,11 +1057,12 @@ impl Entry {
 
     /// Return the username of this item
     pub fn username(&self) -> Option<&str> {
-        self.find_string_value("UserName")
+        self.value("UserName")
     }
 
     /// Set the username of this entry
     pub fn set_username<S: ToString>(&mut self, username: S) {
+        self.before_change();
         let username = username.to_string();
         match self.find_mut("UserName") {
             Some(f) => f.value = Value::Standard(username),
@@ -385,11 +1072,12 @@ impl Entry {
 
     /// Return the URL of this item
     pub fn url(&self) -> Option<&str> {
-        self.find_string_value("URL")
+        self.value("URL")
     }
 
     /// Set the URL of this entry
     pub fn set_url<S: ToString>(&mut self, url: S) {
+        self.before_change();
         let url = url.to_string();
         match self.find_mut("URL") {
             Some(f) => f.value = Value::Standard(url),
@@ -398,14 +1086,13 @@ impl Entry {
     }
 
     /// Return the TOTP of this item, as stored by KeepassXC
-    pub fn otp(&self) -> Option<Otp> {
-        self.find_string_value("otp").map(|url| Otp {
-            url: Cow::Borrowed(url),
-        })
+    pub fn otp(&self) -> Option<Otp<'_>> {
+        self.reveal("otp").map(|url| Otp { url })
     }
 
     /// Return the TOTP of this item, as stored by KeepassXC
     pub fn set_otp(&mut self, otp: Otp) {
+        self.before_change();
         match self.find_mut("otp") {
             Some(f) => f.value = Value::Protected(otp.url.to_string()),
             None => self
@@ -415,12 +1102,15 @@ impl Entry {
     }
 
     /// Return the password of this item
+    ///
+    /// Returns `None` for a lazily parsed password, see [`Entry::reveal`].
     pub fn password(&self) -> Option<&str> {
-        self.find_string_value("Password")
+        self.value("Password")
     }
 
     /// Set the password of this entry
     pub fn set_password<S: ToString>(&mut self, password: S) {
+        self.before_change();
         let password = password.to_string();
         match self.find_mut("Password") {
             Some(f) => f.value = Value::Protected(password),
@@ -438,12 +1128,53 @@ impl Default for Entry {
             fields: Vec::new(),
             history: History::default(),
             times: Times::default(),
+            custom_icon_uuid: None,
+            attachments: Vec::new(),
+            custom_data: Vec::new(),
+            unknown_xml: Vec::new(),
+            history_tracking: HistoryTracking::default(),
+        }
+    }
+}
+
+/// Mutable access to an entry that updates its modification time when changed
+///
+/// Created by [`Entry::tracked_mut`].
+#[derive(Debug)]
+pub struct EntryGuard<'a> {
+    entry: &'a mut Entry,
+    modified: bool,
+}
+
+impl Deref for EntryGuard<'_> {
+    type Target = Entry;
+    fn deref(&self) -> &Entry {
+        self.entry
+    }
+}
+
+impl DerefMut for EntryGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Entry {
+        self.modified = true;
+        self.entry
+    }
+}
+
+impl Drop for EntryGuard<'_> {
+    fn drop(&mut self) {
+        if self.modified {
+            self.entry.times.last_modification_time = current_time();
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// A group or folder of password entries and child groups
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct Group {
     /// Identifier for this group
     uuid: Uuid,
@@ -455,17 +1186,31 @@ pub struct Group {
     groups: Vec<Group>,
     /// Access times for this group
     pub(crate) times: Times,
+    /// Custom icon shown for this group
+    pub(crate) custom_icon_uuid: Option<Uuid>,
+    /// Plugin data stored with this group
+    pub(crate) custom_data: Vec<CustomDataItem>,
+    /// Whether this group is expanded in the group tree
+    pub(crate) is_expanded: bool,
+    /// Auto-type sequence for entries without their own, empty to inherit
+    pub(crate) default_auto_type_sequence: String,
+    /// Whether auto-type is enabled for entries in this group, `None` to inherit
+    pub(crate) enable_auto_type: Option<bool>,
+    /// Whether entries in this group are searched, `None` to inherit
+    pub(crate) enable_searching: Option<bool>,
+    /// Entry shown at the top of the entry list when this group was last viewed
+    pub(crate) last_top_visible_entry: Option<Uuid>,
+    /// XML from the source file not otherwise understood by this library
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) unknown_xml: Vec<XmlNode>,
 }
 
 impl Group {
     /// Create a new group with the given name
     pub fn new<S: ToString>(name: S) -> Group {
         Group {
-            uuid: Uuid::new_v4(),
             name: name.to_string(),
-            entries: Vec::new(),
-            groups: Vec::new(),
-            times: Times::default(),
+            ..Group::default()
         }
     }
 
@@ -497,7 +1242,8 @@ impl Group {
     /// Remove an entry by its UUID
     ///
     /// This is a no-op if the no direct child of this group has the
-    /// given UUID
+    /// given UUID. No [`DeletedObject`] is recorded, see
+    /// [`Database::remove_entry`].
     pub fn remove_entry(&mut self, uuid: Uuid) -> Option<Entry> {
         let index = self
             .entries
@@ -518,10 +1264,25 @@ impl Group {
         self.groups.push(group);
     }
 
+    /// Remove child groups, at any depth, without entries or groups, except the one with UUID `keep`
+    ///
+    /// Returns the number of groups removed.
+    fn remove_empty_groups(&mut self, keep: Option<Uuid>) -> usize {
+        let mut removed = 0;
+        for group in &mut self.groups {
+            removed += group.remove_empty_groups(keep);
+        }
+        let before = self.groups.len();
+        self.groups
+            .retain(|g| !(g.entries.is_empty() && g.groups.is_empty()) || Some(g.uuid) == keep);
+        removed + before - self.groups.len()
+    }
+
     /// Remove an child group by its UUID
     ///
     /// This is a no-op if the no direct child of this group has the
-    /// given UUID
+    /// given UUID. No [`DeletedObject`] is recorded, see
+    /// [`Database::remove_group`].
     pub fn remove_group(&mut self, uuid: Uuid) -> Option<Group> {
         let index = self
             .groups
@@ -537,48 +1298,178 @@ impl Group {
         }
     }
 
-    /// Iterate through all the direct child groups of this group
-    pub fn groups(&self) -> impl Iterator<Item = &Group> {
-        self.groups.iter()
+    fn entries_with_path<'a>(
+        &'a self,
+        parent_path: &[&'a str],
+    ) -> Box<dyn Iterator<Item = (Vec<&'a str>, &'a Entry)> + 'a> {
+        let mut path = parent_path.to_vec();
+        path.push(&self.name);
+        let own_path = path.clone();
+        Box::new(
+            self.entries
+                .iter()
+                .map(move |entry| (own_path.clone(), entry))
+                .chain(
+                    self.groups
+                        .iter()
+                        .flat_map(move |group| group.entries_with_path(&path)),
+                ),
+        )
     }
 
-    /// Iterate mutably through all the direct child groups of this group
-    pub fn groups_mut(&mut self) -> impl Iterator<Item = &mut Group> {
-        self.groups.iter_mut()
+    /// Text dump of this group and everything inside it, keyed for sorting
+    fn text_blocks(
+        &self,
+        parent_path: &[&str],
+        options: &TextOptions,
+        blocks: &mut Vec<(String, String)>,
+    ) {
+        let mut path = parent_path.to_vec();
+        path.push(&self.name);
+        let group_path = path.join("/");
+        let mut block = format!("Group: {}\n", group_path);
+        self.times.write_text(&mut block);
+        blocks.push((group_path.clone(), block));
+        for entry in &self.entries {
+            let entry_path = format!("{}/{}", group_path, entry.title().unwrap_or_default());
+            let mut block = format!("Entry: {}\n", entry_path);
+            entry.write_text(&mut block, options);
+            blocks.push((entry_path, block));
+        }
+        for group in &self.groups {
+            group.text_blocks(&path, options, blocks);
+        }
     }
 
-    /// Count of direct child groups of this group
-    pub fn group_count(&self) -> usize {
-        self.groups.len()
+    /// Copy of this group without any entries or child groups
+    fn without_children(&self) -> Group {
+        Group {
+            uuid: self.uuid,
+            name: self.name.clone(),
+            entries: Vec::new(),
+            groups: Vec::new(),
+            times: self.times.clone(),
+            custom_icon_uuid: self.custom_icon_uuid,
+            custom_data: self.custom_data.clone(),
+            is_expanded: self.is_expanded,
+            default_auto_type_sequence: self.default_auto_type_sequence.clone(),
+            enable_auto_type: self.enable_auto_type,
+            enable_searching: self.enable_searching,
+            last_top_visible_entry: self.last_top_visible_entry,
+            unknown_xml: self.unknown_xml.clone(),
+        }
     }
 
-    /// Count of direct entries of this group
-    pub fn entry_count(&self) -> usize {
-        self.entries.len()
+    /// Collect this group and all children along with the UUID of their parent group
+    fn collect_tree<'a>(
+        &'a self,
+        parent: Option<Uuid>,
+        groups: &mut Vec<(Option<Uuid>, &'a Group)>,
+        entries: &mut Vec<(Uuid, &'a Entry)>,
+    ) {
+        groups.push((parent, self));
+        entries.extend(self.entries.iter().map(|entry| (self.uuid, entry)));
+        for group in &self.groups {
+            group.collect_tree(Some(self.uuid), groups, entries);
+        }
     }
 
-    /// Iterate through all the direct entries of this group
-    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
-        self.entries.iter()
+    /// Latest modification time of this group or anything inside it
+    fn latest_modification(&self) -> NaiveDateTime {
+        self.recursive_entries()
+            .map(|e| e.times.last_modification_time)
+            .chain(
+                self.recursive_groups()
+                    .map(|g| g.times.last_modification_time),
+            )
+            .fold(self.times.last_modification_time, NaiveDateTime::max)
     }
 
-    /// Iterate mutably through all the direct entries of this group
-    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut Entry> {
-        self.entries.iter_mut()
+    fn collect_entries<'a, F: FnMut(&Entry) -> bool>(
+        &'a self,
+        f: &mut F,
+        found: &mut Vec<&'a Entry>,
+    ) {
+        found.extend(self.entries.iter().filter(|entry| f(entry)));
+        for group in &self.groups {
+            group.collect_entries(f, found);
+        }
     }
 
-    /// Iterator through all entries in this group or children
-    pub fn recursive_entries<'a>(&'a self) -> Box<dyn Iterator<Item = &Entry> + 'a> {
-        Box::new(
-            self.groups
-                .iter()
-                .flat_map(|c| c.recursive_entries())
+    fn collect_entries_mut<'a, F: FnMut(&Entry) -> bool>(
+        &'a mut self,
+        f: &mut F,
+        found: &mut Vec<&'a mut Entry>,
+    ) {
+        found.extend(self.entries.iter_mut().filter(|entry| f(entry)));
+        for group in &mut self.groups {
+            group.collect_entries_mut(f, found);
+        }
+    }
+
+    fn remove_entry_recursive(&mut self, uuid: Uuid) -> Option<Entry> {
+        self.remove_entry(uuid).or_else(|| {
+            self.groups
+                .iter_mut()
+                .find_map(|group| group.remove_entry_recursive(uuid))
+        })
+    }
+
+    fn remove_group_recursive(&mut self, uuid: Uuid) -> Option<Group> {
+        self.remove_group(uuid).or_else(|| {
+            self.groups
+                .iter_mut()
+                .find_map(|group| group.remove_group_recursive(uuid))
+        })
+    }
+
+    /// Iterate through all the direct child groups of this group
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.groups.iter()
+    }
+
+    /// Iterate mutably through all the direct child groups of this group
+    pub fn groups_mut(&mut self) -> impl Iterator<Item = &mut Group> {
+        self.groups.iter_mut()
+    }
+
+    /// Count of direct child groups of this group
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count of direct entries of this group
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Count of direct entries of this group, excluding those in child groups
+    pub fn direct_entry_count(&self) -> usize {
+        self.entry_count()
+    }
+
+    /// Iterate through all the direct entries of this group
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// Iterate mutably through all the direct entries of this group
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut Entry> {
+        self.entries.iter_mut()
+    }
+
+    /// Iterator through all entries in this group or children
+    pub fn recursive_entries<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Entry> + 'a> {
+        Box::new(
+            self.groups
+                .iter()
+                .flat_map(|c| c.recursive_entries())
                 .chain(self.entries.iter()),
         )
     }
 
     /// Mutable Iterator through all entries in this group or children
-    pub fn recursive_entries_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = &mut Entry> + 'a> {
+    pub fn recursive_entries_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = &'a mut Entry> + 'a> {
         Box::new(
             self.groups
                 .iter_mut()
@@ -587,13 +1478,41 @@ impl Group {
         )
     }
 
+    /// Apply a function to every entry in this group or children
+    pub fn walk_entries_mut<F: FnMut(&mut Entry)>(&mut self, mut f: F) {
+        self.walk_entries_mut_internal(&mut f)
+    }
+
+    fn walk_entries_mut_internal<F: FnMut(&mut Entry)>(&mut self, f: &mut F) {
+        self.entries.iter_mut().for_each(&mut *f);
+        for group in &mut self.groups {
+            group.walk_entries_mut_internal(f);
+        }
+    }
+
+    /// Apply a function to every child group of this group
+    ///
+    /// Each group is visited before its own children, so changes made to its
+    /// child groups are seen when they are visited.
+    pub fn walk_groups_mut<F: FnMut(&mut Group)>(&mut self, mut f: F) {
+        self.walk_groups_mut_internal(&mut f)
+    }
+
+    fn walk_groups_mut_internal<F: FnMut(&mut Group)>(&mut self, f: &mut F) {
+        for group in &mut self.groups {
+            f(group);
+            group.walk_groups_mut_internal(f);
+        }
+    }
+
     /// Iterator through all child groups of this group
-    pub fn recursive_groups<'a>(&'a self) -> Box<dyn Iterator<Item = &Group> + 'a> {
+    ///
+    /// Each group is visited once, followed by its own children.
+    pub fn recursive_groups<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Group> + 'a> {
         Box::new(
             self.groups
                 .iter()
-                .flat_map(|g| g.recursive_groups())
-                .chain(self.groups.iter()),
+                .flat_map(|g| std::iter::once(g).chain(g.recursive_groups())),
         )
     }
 
@@ -687,6 +1606,88 @@ impl Group {
     pub fn times_mut(&mut self) -> &mut Times {
         &mut self.times
     }
+
+    /// Whether this group has passed its expiry time
+    pub fn is_expired(&self) -> bool {
+        self.times.is_expired()
+    }
+
+    /// UUID of the custom icon shown for this group, if any
+    pub fn custom_icon_uuid(&self) -> Option<Uuid> {
+        self.custom_icon_uuid
+    }
+
+    /// Set or clear the custom icon shown for this group
+    pub fn set_custom_icon_uuid(&mut self, uuid: Option<Uuid>) {
+        self.custom_icon_uuid = uuid;
+    }
+
+    /// Plugin data stored with this group
+    pub fn custom_data(&self) -> &[CustomDataItem] {
+        &self.custom_data
+    }
+
+    /// Mutable access to the plugin data stored with this group
+    pub fn custom_data_mut(&mut self) -> &mut Vec<CustomDataItem> {
+        &mut self.custom_data
+    }
+
+    /// Whether this group is expanded in the group tree
+    pub fn is_expanded(&self) -> bool {
+        self.is_expanded
+    }
+
+    /// Set whether this group is expanded in the group tree
+    pub fn set_expanded(&mut self, expanded: bool) {
+        self.is_expanded = expanded;
+    }
+
+    /// Auto-type sequence for entries without their own, empty to inherit from the parent
+    pub fn default_auto_type_sequence(&self) -> &str {
+        &self.default_auto_type_sequence
+    }
+
+    /// Set the auto-type sequence for entries without their own
+    pub fn set_default_auto_type_sequence<S: ToString>(&mut self, sequence: S) {
+        self.default_auto_type_sequence = sequence.to_string();
+    }
+
+    /// Whether auto-type is enabled for entries in this group, `None` to inherit from the parent
+    pub fn enable_auto_type(&self) -> Option<bool> {
+        self.enable_auto_type
+    }
+
+    /// Enable or disable auto-type for entries in this group, `None` to inherit from the parent
+    pub fn set_enable_auto_type(&mut self, enabled: Option<bool>) {
+        self.enable_auto_type = enabled;
+    }
+
+    /// Whether entries in this group are searched, `None` to inherit from the parent
+    pub fn enable_searching(&self) -> Option<bool> {
+        self.enable_searching
+    }
+
+    /// Include or exclude entries in this group from searches, `None` to inherit from the parent
+    pub fn set_enable_searching(&mut self, enabled: Option<bool>) {
+        self.enable_searching = enabled;
+    }
+
+    /// Entry shown at the top of the entry list when this group was last viewed
+    pub fn last_top_visible_entry(&self) -> Option<Uuid> {
+        self.last_top_visible_entry
+    }
+
+    /// Set the entry shown at the top of the entry list for this group
+    pub fn set_last_top_visible_entry(&mut self, uuid: Option<Uuid>) {
+        self.last_top_visible_entry = uuid;
+    }
+
+    /// Use a PNG image as this group's icon
+    ///
+    /// The image is added to the database's custom icons if not already present.
+    pub fn set_custom_icon_png(&mut self, db: &mut Database, png: &[u8]) {
+        self.custom_icon_uuid = Some(db.meta_mut().add_custom_icon_png(png));
+    }
 }
 
 impl Default for Group {
@@ -697,12 +1698,25 @@ impl Default for Group {
             entries: Vec::new(),
             groups: Vec::new(),
             times: Times::default(),
+            custom_icon_uuid: None,
+            custom_data: Vec::new(),
+            is_expanded: true,
+            default_auto_type_sequence: String::new(),
+            enable_auto_type: None,
+            enable_searching: None,
+            last_top_visible_entry: None,
+            unknown_xml: Vec::new(),
         }
     }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 /// Identifies which fields are encrypted in memory for official clients
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct MemoryProtection {
     /// Whether title fields should be encrypted
     pub protect_title: bool,
@@ -716,23 +1730,187 @@ pub struct MemoryProtection {
     pub protect_notes: bool,
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+impl MemoryProtection {
+    /// The settings KeePass uses when a database does not specify them
+    ///
+    /// Only passwords are protected. These are used for databases read
+    /// without a `MemoryProtection` block, or with missing settings in it.
+    pub fn keepass_defaults() -> MemoryProtection {
+        MemoryProtection {
+            protect_password: true,
+            ..MemoryProtection::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// Meta information about this database
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct Meta {
     /// Application used to generate this database
     pub generator: String,
     /// Short name for the database
     pub database_name: String,
+    /// Time the database name was last changed
+    pub database_name_changed: Option<NaiveDateTime>,
     /// Longer description of the database
     pub database_description: String,
+    /// Time the database description was last changed
+    pub database_description_changed: Option<NaiveDateTime>,
+    /// User name filled in for new entries
+    pub default_username: String,
+    /// Time the default user name for new entries was last changed
+    pub default_user_name_changed: Option<NaiveDateTime>,
+    /// Time the credentials used to unlock the database were last changed
+    pub master_key_changed: Option<NaiveDateTime>,
+    /// Time the database settings were last changed
+    pub settings_changed: Option<NaiveDateTime>,
     /// Non standard information from plugins and other clients
-    pub custom_data: Vec<Field>,
+    pub custom_data: Vec<CustomDataItem>,
     /// Memory protection configuration for this client
+    ///
+    /// Databases read without a `MemoryProtection` block get
+    /// [`MemoryProtection::keepass_defaults`].
     pub memory_protection: MemoryProtection,
+    /// Icons that entries and groups can use in place of the standard icons
+    pub custom_icons: Vec<CustomIcon>,
+    /// Whether deleted entries are moved to the recycle bin
+    pub recycle_bin_enabled: bool,
+    /// Group used as the recycle bin, if one has been created
+    pub recycle_bin_uuid: Option<Uuid>,
+    /// Time the recycle bin group was last changed
+    pub recycle_bin_changed: NaiveDateTime,
+    /// Most history versions to keep per entry, `None` for unlimited
+    pub history_max_items: Option<usize>,
+    /// Most bytes of history to keep per entry, `None` for unlimited
+    pub history_max_size: Option<usize>,
+}
+
+impl Default for Meta {
+    fn default() -> Meta {
+        Meta {
+            generator: String::new(),
+            database_name: String::new(),
+            database_name_changed: None,
+            database_description: String::new(),
+            database_description_changed: None,
+            default_username: String::new(),
+            default_user_name_changed: None,
+            master_key_changed: None,
+            settings_changed: None,
+            custom_data: Vec::new(),
+            memory_protection: MemoryProtection::default(),
+            custom_icons: Vec::new(),
+            recycle_bin_enabled: true,
+            recycle_bin_uuid: None,
+            recycle_bin_changed: Times::default().last_modification_time,
+            history_max_items: Some(10),
+            history_max_size: Some(6 * 1024 * 1024),
+        }
+    }
+}
+
+impl Meta {
+    /// Set the user name filled in for new entries, updating its change time
+    pub fn set_default_username<S: ToString>(&mut self, username: S) {
+        self.default_username = username.to_string();
+        self.default_user_name_changed = Some(current_time());
+    }
+
+    /// Add a PNG image as a custom icon, returning its UUID
+    ///
+    /// If an icon with the same image data already exists, its UUID is
+    /// returned instead of adding a duplicate.
+    pub fn add_custom_icon_png(&mut self, png: &[u8]) -> Uuid {
+        let hash = crate::crypto::sha256(png);
+        if let Some(icon) = self
+            .custom_icons
+            .iter()
+            .find(|icon| crate::crypto::sha256(&icon.data) == hash)
+        {
+            return icon.uuid;
+        }
+        let uuid = Uuid::new_v4();
+        self.custom_icons.push(CustomIcon {
+            uuid,
+            data: png.to_vec(),
+        });
+        uuid
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Item of non standard information in the database meta, an entry or a group
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct CustomDataItem {
+    /// Key and value of this item
+    pub field: Field,
+    /// Time this item was last changed, only recorded by KDBX 4.1 clients
+    pub last_modification_time: Option<NaiveDateTime>,
+}
+
+impl From<Field> for CustomDataItem {
+    fn from(field: Field) -> CustomDataItem {
+        CustomDataItem {
+            field,
+            last_modification_time: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Custom icon image stored in the database
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct CustomIcon {
+    /// Identifier used by entries and groups to refer to this icon
+    pub uuid: Uuid,
+    /// PNG image data
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Record that an entry or group was deleted, used when merging databases
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct DeletedObject {
+    /// Identifier of the deleted entry or group
+    pub uuid: Uuid,
+    /// Time the item was deleted
+    pub deletion_time: NaiveDateTime,
+}
+
+impl DeletedObject {
+    /// Create a record of an item deleted now
+    pub fn new(uuid: Uuid) -> DeletedObject {
+        DeletedObject {
+            uuid,
+            deletion_time: Times::default().last_modification_time,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// Audit times for this item
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct Times {
     /// Time last edited
     pub last_modification_time: NaiveDateTime,
@@ -750,12 +1928,45 @@ pub struct Times {
     pub usage_count: u32,
 }
 
+pub(crate) fn current_time() -> NaiveDateTime {
+    chrono::Local::now()
+        .naive_local()
+        .with_nanosecond(0)
+        .unwrap()
+}
+
+impl Times {
+    /// Record a use of this item, updating the access time and usage count
+    pub fn touch(&mut self) {
+        self.last_access_time = current_time();
+        self.usage_count = self.usage_count.saturating_add(1);
+    }
+
+    /// Whether this item expires and its expiry time has passed
+    pub fn is_expired(&self) -> bool {
+        self.expires && self.expiry_time <= chrono::Utc::now().naive_utc()
+    }
+
+    /// Append the creation, modification and expiry times to a text dump
+    fn write_text(&self, output: &mut String) {
+        use std::fmt::Write;
+        writeln!(output, "    Created: {}", self.creation_time).unwrap();
+        writeln!(output, "    Modified: {}", self.last_modification_time).unwrap();
+        if self.expires {
+            writeln!(output, "    Expires: {}", self.expiry_time).unwrap();
+        }
+    }
+
+    /// Make this item expire at the given time
+    pub fn set_expiry(&mut self, when: NaiveDateTime) {
+        self.expires = true;
+        self.expiry_time = when;
+    }
+}
+
 impl Default for Times {
     fn default() -> Times {
-        let now = chrono::Local::now()
-            .naive_local()
-            .with_nanosecond(0)
-            .unwrap();
+        let now = current_time();
         Times {
             expires: false,
             usage_count: 0,
@@ -772,11 +1983,31 @@ impl Default for Times {
 /// Decrypted password database
 ///
 /// See the [module-level documentation][crate::database] for more information.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 pub struct Database {
     /// Meta information about this database
     pub(crate) meta: Meta,
     /// Trees of items in this database
     pub(crate) groups: Vec<Group>,
+    /// XML directly inside the document root not otherwise understood by this library
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) unknown_xml: Vec<XmlNode>,
+    /// XML inside the meta section not otherwise understood by this library
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) meta_unknown_xml: Vec<XmlNode>,
+    /// Recoverable problems found while parsing the source XML
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) parse_warnings: Vec<ParseWarning>,
+    /// Tombstones for deleted entries and groups
+    pub(crate) deleted_objects: Vec<DeletedObject>,
+    /// Whether entries added to the database get automatic history
+    pub(crate) auto_history: bool,
+    /// Field keys treated as standard rather than custom fields
+    pub(crate) standard_fields: StandardFields,
 }
 
 impl Default for Database {
@@ -785,11 +2016,32 @@ impl Default for Database {
         Database {
             meta: Meta::default(),
             groups: vec![root],
+            unknown_xml: Vec::new(),
+            meta_unknown_xml: Vec::new(),
+            parse_warnings: Vec::new(),
+            deleted_objects: Vec::new(),
+            auto_history: false,
+            standard_fields: StandardFields::default(),
         }
     }
 }
 
 impl Database {
+    /// Recoverable problems found while parsing the database XML
+    pub fn parse_warnings(&self) -> &[ParseWarning] {
+        &self.parse_warnings
+    }
+
+    /// Tombstones recording entries and groups deleted from this database
+    pub fn deleted_objects(&self) -> &[DeletedObject] {
+        &self.deleted_objects
+    }
+
+    /// Mutable tombstones recording entries and groups deleted from this database
+    pub fn deleted_objects_mut(&mut self) -> &mut Vec<DeletedObject> {
+        &mut self.deleted_objects
+    }
+
     /// Return meta information about the database like name and access times
     pub fn meta(&self) -> &Meta {
         &self.meta
@@ -808,6 +2060,7 @@ impl Database {
     /// Set the database name
     pub fn set_name<S: ToString>(&mut self, name: S) {
         self.meta.database_name = name.to_string();
+        self.meta.database_name_changed = Some(current_time());
     }
 
     /// Get the database description
@@ -818,10 +2071,52 @@ impl Database {
     /// Set the database name
     pub fn set_description<S: ToString>(&mut self, desc: S) {
         self.meta.database_description = desc.to_string();
+        self.meta.database_description_changed = Some(current_time());
+    }
+
+    /// Write the database as indented JSON, for inspecting it while debugging
+    ///
+    /// **Protected values such as passwords are written in plaintext**, so
+    /// the output must be handled as carefully as the decrypted database.
+    #[cfg(feature = "serde")]
+    pub fn to_json_pretty(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Read a database from JSON written by [`Database::to_json_pretty`]
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Database, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Get the application that generated this database
+    pub fn generator(&self) -> &str {
+        &self.meta.generator
+    }
+
+    /// Set the application recorded as generating this database
+    ///
+    /// If empty, `kdbx-rs` is recorded when the database is written.
+    pub fn set_generator<S: ToString>(&mut self, generator: S) {
+        self.meta.generator = generator.to_string();
+    }
+
+    /// Create an entry with the database's default user name filled in
+    ///
+    /// The entry is not added to the database.
+    pub fn new_entry(&self) -> Entry {
+        let mut entry = Entry::default();
+        if !self.meta.default_username.is_empty() {
+            entry.set_username(&self.meta.default_username);
+        }
+        entry
     }
 
     /// Add a entry to the root group
-    pub fn add_entry(&mut self, entry: Entry) {
+    pub fn add_entry(&mut self, mut entry: Entry) {
+        if self.auto_history {
+            entry.set_history_tracking(true);
+        }
         self.groups[0].entries.push(entry);
     }
 
@@ -830,6 +2125,162 @@ impl Database {
         self.groups[0].groups.push(entry);
     }
 
+    /// Add many entries to the group with the given UUID
+    ///
+    /// The group is looked up once, so this is faster than adding entries one at a time.
+    pub fn add_entries_to<I: IntoIterator<Item = Entry>>(
+        &mut self,
+        group_uuid: Uuid,
+        entries: I,
+    ) -> Result<(), AddError> {
+        let auto_history = self.auto_history;
+        let group = self
+            .group_by_uuid_mut(group_uuid)
+            .ok_or(AddError::GroupNotFound(group_uuid))?;
+        group.entries.extend(entries.into_iter().map(|mut entry| {
+            if auto_history {
+                entry.set_history_tracking(true);
+            }
+            entry
+        }));
+        Ok(())
+    }
+
+    /// Iterate through all entries along with the names of the groups containing them
+    ///
+    /// Paths start with the root group's name and end with the entry's parent group.
+    pub fn iter_entries(&self) -> impl Iterator<Item = (Vec<&str>, &Entry)> {
+        self.root().entries_with_path(&[])
+    }
+
+    /// Bytes of attachment data stored when this database is written
+    ///
+    /// Binaries shared by several attachments are counted once. Attachments
+    /// of history versions are included, as they are stored too.
+    pub fn total_attachment_bytes(&self) -> usize {
+        BinaryPool::new(self)
+            .iter()
+            .map(|(data, _)| data.len())
+            .sum()
+    }
+
+    /// Find a group from the names of the groups leading to it, starting with the root group
+    ///
+    /// Where several sibling groups share a name, the first is used.
+    pub fn find_group_by_path(&self, path: &[&str]) -> Option<&Group> {
+        let (root_name, rest) = path.split_first()?;
+        if self.root().name() != *root_name {
+            return None;
+        }
+        rest.iter().try_fold(self.root(), |group, name| {
+            group.groups().find(|child| child.name() == *name)
+        })
+    }
+
+    /// Remove an entry by its UUID from whichever group contains it
+    ///
+    /// A [`DeletedObject`] is recorded for the entry, so merging in an older
+    /// copy of the database does not bring it back. Use
+    /// [`Database::move_entry`] to move an entry to another group instead.
+    pub fn remove_entry(&mut self, uuid: Uuid) -> Option<Entry> {
+        let entry = self.take_entry(uuid)?;
+        self.deleted_objects.push(DeletedObject::new(uuid));
+        Some(entry)
+    }
+
+    /// Remove an entry from the tree without recording its deletion
+    fn take_entry(&mut self, uuid: Uuid) -> Option<Entry> {
+        self.groups[0].remove_entry_recursive(uuid)
+    }
+
+    /// Move an entry into the group with UUID `group`, returning whether both were found
+    pub fn move_entry(&mut self, uuid: Uuid, group: Uuid) -> bool {
+        if self.group_by_uuid(group).is_none() {
+            return false;
+        }
+        let mut entry = match self.take_entry(uuid) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        entry.times_mut().location_changed = Times::default().last_modification_time;
+        self.group_by_uuid_mut(group).unwrap().add_entry(entry);
+        true
+    }
+
+    /// Group deleted entries are moved to, if one exists
+    pub fn recycle_bin(&self) -> Option<&Group> {
+        self.group_by_uuid(self.meta.recycle_bin_uuid?)
+    }
+
+    /// Delete an entry the way KeePass does, returning whether it was found
+    ///
+    /// If the recycle bin is enabled the entry is moved into it, creating the
+    /// bin group first if needed. Entries already in the bin, and all entries
+    /// when the bin is disabled, are removed outright and a [`DeletedObject`]
+    /// is recorded for them.
+    pub fn recycle(&mut self, entry_uuid: Uuid) -> bool {
+        let already_recycled = self
+            .recycle_bin()
+            .map(|bin| bin.find_entry(|e| e.uuid() == entry_uuid).is_some())
+            .unwrap_or(false);
+        let mut entry = match self.take_entry(entry_uuid) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if !self.meta.recycle_bin_enabled || already_recycled {
+            self.deleted_objects.push(DeletedObject::new(entry_uuid));
+            return true;
+        }
+        let now = Times::default().last_modification_time;
+        entry.times_mut().location_changed = now;
+        if self.recycle_bin().is_none() {
+            let bin = Group::new("Recycle Bin");
+            self.meta.recycle_bin_uuid = Some(bin.uuid());
+            self.meta.recycle_bin_changed = now;
+            self.add_group(bin);
+        }
+        let bin_uuid = self.meta.recycle_bin_uuid.unwrap();
+        self.group_by_uuid_mut(bin_uuid).unwrap().add_entry(entry);
+        true
+    }
+
+    /// Remove a group by its UUID from whichever group contains it
+    ///
+    /// The root group cannot be removed. As KeePass does, a [`DeletedObject`]
+    /// is recorded for the group and every group and entry inside it, so
+    /// merging in an older copy of the database does not bring them back.
+    pub fn remove_group(&mut self, uuid: Uuid) -> Option<Group> {
+        let group = self.groups[0].remove_group_recursive(uuid)?;
+        let descendants = std::iter::once(&group)
+            .chain(group.recursive_groups())
+            .map(|group| group.uuid())
+            .chain(group.recursive_entries().map(|entry| entry.uuid()));
+        self.deleted_objects
+            .extend(descendants.map(DeletedObject::new));
+        Some(group)
+    }
+
+    /// Move a group into the group with UUID `parent`, returning whether it was moved
+    ///
+    /// The root group cannot be moved, and a group cannot be moved into itself
+    /// or one of its own children.
+    pub fn move_group(&mut self, uuid: Uuid, parent: Uuid) -> bool {
+        let moving = match self.root().find_group(|g| g.uuid() == uuid) {
+            Some(group) => group,
+            None => return false,
+        };
+        if moving.uuid() == parent || moving.find_group(|g| g.uuid() == parent).is_some() {
+            return false;
+        }
+        if self.group_by_uuid(parent).is_none() {
+            return false;
+        }
+        let mut group = self.groups[0].remove_group_recursive(uuid).unwrap();
+        group.times_mut().location_changed = Times::default().last_modification_time;
+        self.group_by_uuid_mut(parent).unwrap().add_group(group);
+        true
+    }
+
     /// Replace the root group (and therefore all entries!) with a custom tree
     pub fn replace_root(&mut self, group: Group) {
         self.groups = vec![group];
@@ -855,6 +2306,514 @@ impl Database {
         self.root_mut().find_entry_mut(f)
     }
 
+    /// Recursively searches for all entries matching a filter
+    ///
+    /// Entries are returned in the same order [`Database::find_entry`] searches them.
+    pub fn find_entries<F: FnMut(&Entry) -> bool>(&self, mut f: F) -> Vec<&Entry> {
+        let mut found = Vec::new();
+        self.root().collect_entries(&mut f, &mut found);
+        found
+    }
+
+    /// Replace KeePass field references in `text` with the values they point to
+    ///
+    /// References take the form `{REF:<wanted>@<search in>:<term>}`, where both
+    /// field codes are one of `T` (title), `U` (username), `P` (password),
+    /// `A` (URL), `N` (notes) or `I` (UUID). For example `{REF:P@I:<uuid>}` is
+    /// the password of the entry with the given UUID. Text fields are searched
+    /// for the first entry containing the term, ignoring case. References that
+    /// can't be resolved, or nest more than 12 deep such as in a cycle, are left as is.
+    pub fn resolve_references(&self, text: &str) -> String {
+        resolve_references_at(self, text, 0)
+    }
+
+    /// Iterate through all entries along with their field values, references resolved
+    ///
+    /// Fields that can't be decrypted are left out. See
+    /// [`Database::resolve_references`] for the supported syntax.
+    pub fn entries_resolved(&self) -> impl Iterator<Item = (&Entry, HashMap<String, String>)> {
+        self.root().recursive_entries().map(move |entry| {
+            let fields = entry
+                .fields()
+                .filter_map(|field| {
+                    let value = field.reveal()?;
+                    Some((field.key().to_string(), self.resolve_references(&value)))
+                })
+                .collect();
+            (entry, fields)
+        })
+    }
+
+    /// Most recent modification time of any entry or group in the database
+    pub fn last_modified(&self) -> NaiveDateTime {
+        self.root().latest_modification()
+    }
+
+    /// Deterministic plain text dump of the database, suitable for `diff`
+    ///
+    /// Protected values are replaced by their SHA-256 hash, see
+    /// [`Database::to_text_with_options`] to include them instead.
+    pub fn to_text(&self) -> String {
+        self.to_text_with_options(&TextOptions::default())
+    }
+
+    /// Deterministic plain text dump of the database using the given options
+    ///
+    /// Groups and entries are listed by path, with fields and attachments
+    /// sorted by name, so the output does not depend on the order items were added in.
+    pub fn to_text_with_options(&self, options: &TextOptions) -> String {
+        let mut blocks = Vec::new();
+        self.root().text_blocks(&[], options, &mut blocks);
+        blocks.sort();
+        let mut text = format!("Database: {}\n", self.name());
+        for (_, block) in blocks {
+            text.push('\n');
+            text.push_str(&block);
+        }
+        text
+    }
+
+    /// Find entries with a field value containing `query`, ignoring case
+    ///
+    /// Every string field is searched, including title, username, URL, notes
+    /// and custom fields. Protected fields such as the password are only
+    /// searched when `include_protected` is set.
+    pub fn search(&self, query: &str, include_protected: bool) -> Vec<&Entry> {
+        let query = query.to_lowercase();
+        self.root()
+            .recursive_entries()
+            .filter(|entry| {
+                entry
+                    .fields()
+                    .filter(|field| include_protected || !field.protected())
+                    .filter_map(|field| field.reveal())
+                    .any(|value| value.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    /// Recursively searches for all entries matching a filter, returns them mutably
+    pub fn find_entries_mut<F: FnMut(&Entry) -> bool>(&mut self, mut f: F) -> Vec<&mut Entry> {
+        let mut found = Vec::new();
+        self.root_mut().collect_entries_mut(&mut f, &mut found);
+        found
+    }
+
+    /// Find an entry anywhere in the database by its UUID
+    pub fn entry_by_uuid(&self, uuid: Uuid) -> Option<&Entry> {
+        self.find_entry(|entry| entry.uuid() == uuid)
+    }
+
+    /// Find an entry anywhere in the database by its UUID, returns it mutably
+    pub fn entry_by_uuid_mut(&mut self, uuid: Uuid) -> Option<&mut Entry> {
+        self.find_entry_mut(|entry| entry.uuid() == uuid)
+    }
+
+    /// Find a group anywhere in the database, including the root, by its UUID
+    pub fn group_by_uuid(&self, uuid: Uuid) -> Option<&Group> {
+        if self.root().uuid() == uuid {
+            return Some(self.root());
+        }
+        self.find_group(|group| group.uuid() == uuid)
+    }
+
+    /// Find a group anywhere in the database, including the root, by its UUID, returns it mutably
+    pub fn group_by_uuid_mut(&mut self, uuid: Uuid) -> Option<&mut Group> {
+        if self.root().uuid() == uuid {
+            return Some(self.root_mut());
+        }
+        self.find_group_mut(|group| group.uuid() == uuid)
+    }
+
+    /// Enable or disable automatic history on every entry in the database
+    ///
+    /// Entries added later with [`Database::add_entry`] also get this setting.
+    /// See [`Entry::set_history_tracking`] for which changes are tracked.
+    pub fn set_auto_history(&mut self, enabled: bool) {
+        self.auto_history = enabled;
+        self.root_mut()
+            .walk_entries_mut(|entry| entry.set_history_tracking(enabled));
+    }
+
+    /// Field keys treated as standard rather than custom fields
+    pub fn standard_fields(&self) -> &StandardFields {
+        &self.standard_fields
+    }
+
+    /// Mutable access to the field keys treated as standard
+    ///
+    /// This setting is not stored in the database file.
+    pub fn standard_fields_mut(&mut self) -> &mut StandardFields {
+        &mut self.standard_fields
+    }
+
+    /// Describe the first difference in content from another database, if any
+    ///
+    /// The generator is ignored, as an empty generator is filled in when a database is written.
+    pub(crate) fn content_difference(&self, other: &Database) -> Option<String> {
+        let mut other_meta = other.meta.clone();
+        other_meta.generator = self.meta.generator.clone();
+        if self.meta != other_meta {
+            return Some("database meta differs".to_string());
+        }
+        if self.unknown_xml != other.unknown_xml || self.meta_unknown_xml != other.meta_unknown_xml
+        {
+            return Some("unrecognised XML differs".to_string());
+        }
+        if self.deleted_objects != other.deleted_objects {
+            return Some("deleted objects differ".to_string());
+        }
+        fn all_groups(db: &Database) -> Vec<&Group> {
+            db.groups
+                .iter()
+                .flat_map(|g| std::iter::once(g).chain(g.recursive_groups()))
+                .collect()
+        }
+        let children = |group: &Group| -> (Vec<Uuid>, Vec<Uuid>) {
+            (
+                group.entries.iter().map(|e| e.uuid).collect(),
+                group.groups.iter().map(|g| g.uuid).collect(),
+            )
+        };
+        let other_groups = all_groups(other);
+        if self.groups.len() != other.groups.len() {
+            return Some("number of root groups differs".to_string());
+        }
+        for group in all_groups(self) {
+            let other_group = match other_groups.iter().find(|g| g.uuid == group.uuid) {
+                Some(other_group) => other_group,
+                None => return Some(format!("group {} is missing", group.uuid)),
+            };
+            if group.without_children() != other_group.without_children()
+                || children(group) != children(other_group)
+            {
+                return Some(format!("group {} differs", group.uuid));
+            }
+            for (entry, other_entry) in group.entries.iter().zip(&other_group.entries) {
+                if entry.revealed() != other_entry.revealed() {
+                    return Some(format!("entry {} differs", entry.uuid));
+                }
+            }
+        }
+        None
+    }
+
+    /// Start new automatic history snapshots on the next tracked change to each entry
+    ///
+    /// Writing or saving a [`Kdbx`][crate::Kdbx] never does this by itself.
+    /// Call it after a successful save so that the next change to each entry
+    /// is versioned again.
+    pub fn mark_saved(&mut self) {
+        self.root_mut()
+            .walk_entries_mut(|entry| entry.history_tracking.snapshot_taken = false);
+    }
+
+    /// Prune the history of every entry to the limits set in the database meta
+    pub fn prune_all_history(&mut self) {
+        let max_items = self.meta.history_max_items;
+        let max_size = self.meta.history_max_size;
+        self.root_mut()
+            .walk_entries_mut(|entry| entry.prune_history(max_items, max_size));
+    }
+
+    /// Clean up data that bloats the database, returning what was removed
+    ///
+    /// Binaries no attachment refers to are dropped whenever the database is
+    /// written, so [`CompactReport::binaries_removed`] counts those orphaned
+    /// by the other steps.
+    pub fn compact(&mut self, options: &CompactOptions) -> CompactReport {
+        let mut report = CompactReport::default();
+        let binaries_before = BinaryPool::new(self).iter().count();
+        if options.prune_history {
+            let versions = |db: &Database| -> usize {
+                db.root()
+                    .recursive_entries()
+                    .map(|e| e.history().len())
+                    .sum()
+            };
+            let before = versions(self);
+            self.prune_all_history();
+            report.history_removed = before - versions(self);
+        }
+        if options.drop_undecryptable {
+            let mut removed = 0;
+            self.root_mut()
+                .walk_entries_mut(|entry| removed += entry.drop_undecryptable_fields());
+            report.undecryptable_removed = removed;
+        }
+        if options.remove_empty_groups {
+            let keep = self.meta.recycle_bin_uuid;
+            report.empty_groups_removed = self.root_mut().remove_empty_groups(keep);
+        }
+        report.binaries_removed = binaries_before - BinaryPool::new(self).iter().count();
+        report
+    }
+
+    /// Merge changes from another copy of this database, matching items by UUID
+    ///
+    /// Where both databases have an entry or group, the one with the newer
+    /// modification time is kept, and the other version of an entry is added
+    /// to its history. Items only in `other` are added to the group with the
+    /// same UUID as their parent there, or the root group if there is none.
+    /// Tombstones from both databases are combined, and items not modified
+    /// since they were deleted are removed. Items moved between groups are
+    /// not relocated. The database name and description are each taken from
+    /// whichever database changed them most recently.
+    pub fn merge(&mut self, other: &Database) -> MergeReport {
+        let mut report = MergeReport::default();
+        if other.meta.database_name_changed > self.meta.database_name_changed {
+            self.meta.database_name = other.meta.database_name.clone();
+            self.meta.database_name_changed = other.meta.database_name_changed;
+        }
+        if other.meta.database_description_changed > self.meta.database_description_changed {
+            self.meta.database_description = other.meta.database_description.clone();
+            self.meta.database_description_changed = other.meta.database_description_changed;
+        }
+        for tombstone in &other.deleted_objects {
+            match self
+                .deleted_objects
+                .iter_mut()
+                .find(|d| d.uuid == tombstone.uuid)
+            {
+                Some(existing) => {
+                    existing.deletion_time = existing.deletion_time.max(tombstone.deletion_time)
+                }
+                None => self.deleted_objects.push(tombstone.clone()),
+            }
+        }
+
+        let mut groups = Vec::new();
+        let mut entries = Vec::new();
+        other.root().collect_tree(None, &mut groups, &mut entries);
+        for (parent, group) in groups {
+            self.merge_group(group, parent, &mut report);
+        }
+        for (parent, entry) in entries {
+            self.merge_entry(entry, parent, &mut report);
+        }
+        report.deleted = self.apply_deleted_objects();
+        report
+    }
+
+    fn deleted_since(&self, uuid: Uuid, time: NaiveDateTime) -> bool {
+        self.deleted_objects
+            .iter()
+            .any(|d| d.uuid == uuid && d.deletion_time >= time)
+    }
+
+    fn merge_target(&mut self, parent: Uuid) -> &mut Group {
+        if self.group_by_uuid(parent).is_some() {
+            self.group_by_uuid_mut(parent).unwrap()
+        } else {
+            self.root_mut()
+        }
+    }
+
+    fn merge_group(&mut self, other: &Group, parent: Option<Uuid>, report: &mut MergeReport) {
+        let other_time = other.times.last_modification_time;
+        if let Some(local) = self.group_by_uuid_mut(other.uuid) {
+            if other_time > local.times.last_modification_time {
+                // Take every property of the newer group, keeping the local children
+                *local = Group {
+                    entries: std::mem::take(&mut local.entries),
+                    groups: std::mem::take(&mut local.groups),
+                    ..other.without_children()
+                };
+                report.updated += 1;
+            } else {
+                report.skipped += 1;
+            }
+            return;
+        }
+        // A differing root group is merged into this database's root
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        if self.deleted_since(other.uuid, other_time) {
+            report.skipped += 1;
+        } else {
+            self.merge_target(parent)
+                .add_group(other.without_children());
+            report.added += 1;
+        }
+    }
+
+    fn merge_entry(&mut self, other: &Entry, parent: Uuid, report: &mut MergeReport) {
+        let other_time = other.times.last_modification_time;
+        if let Some(local) = self.entry_by_uuid_mut(other.uuid) {
+            if other_time > local.times.last_modification_time {
+                let mut merged = other.clone();
+                merged.absorb_versions(local);
+                *local = merged;
+                report.updated += 1;
+            } else {
+                local.absorb_versions(other);
+                report.skipped += 1;
+            }
+        } else if self.deleted_since(other.uuid, other_time) {
+            report.skipped += 1;
+        } else {
+            self.merge_target(parent).add_entry(other.clone());
+            report.added += 1;
+        }
+    }
+
+    fn apply_deleted_objects(&mut self) -> usize {
+        let mut deleted = 0;
+        for tombstone in self.deleted_objects.clone() {
+            let entry_time = self
+                .entry_by_uuid(tombstone.uuid)
+                .map(|e| e.times.last_modification_time);
+            let group_time = self
+                .group_by_uuid(tombstone.uuid)
+                .map(|g| g.latest_modification());
+            if entry_time.map_or(false, |t| t <= tombstone.deletion_time) {
+                self.take_entry(tombstone.uuid);
+                deleted += 1;
+            } else if group_time.map_or(false, |t| t <= tombstone.deletion_time)
+                && self.groups[0]
+                    .remove_group_recursive(tombstone.uuid)
+                    .is_some()
+            {
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    /// UUIDs of all groups directly holding more than `limit` entries
+    pub fn groups_exceeding(&self, limit: usize) -> Vec<Uuid> {
+        std::iter::once(self.root())
+            .chain(self.root().recursive_groups())
+            .filter(|group| group.direct_entry_count() > limit)
+            .map(|group| group.uuid())
+            .collect()
+    }
+
+    /// Entries lacking a value for any of the given field keys
+    ///
+    /// Fields that are present but empty count as missing.
+    pub fn entries_missing(&self, keys: &[&str]) -> Vec<&Entry> {
+        self.root()
+            .recursive_entries()
+            .filter(|entry| {
+                keys.iter().any(|key| {
+                    entry
+                        .reveal(key)
+                        .map(|value| value.is_empty())
+                        .unwrap_or(true)
+                })
+            })
+            .collect()
+    }
+
+    /// Whether plugins or other clients have stored custom data in this database
+    ///
+    /// Such data is kept when saving, but it is not understood by this library,
+    /// so edits may leave it out of date.
+    pub fn has_plugin_data(&self) -> bool {
+        !self.plugin_data_keys().is_empty()
+    }
+
+    /// Sorted keys of the custom data in the database meta, entries and groups
+    pub fn plugin_data_keys(&self) -> Vec<&str> {
+        let entry_items = self
+            .root()
+            .recursive_entries()
+            .flat_map(|e| std::iter::once(e).chain(e.history().entries()))
+            .flat_map(|e| &e.custom_data);
+        let group_items = std::iter::once(self.root())
+            .chain(self.root().recursive_groups())
+            .flat_map(|g| &g.custom_data);
+        self.meta
+            .custom_data
+            .iter()
+            .chain(entry_items)
+            .chain(group_items)
+            .map(|item| item.field.key())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Count how many entries use each non-standard field key
+    pub fn custom_field_histogram(&self) -> BTreeMap<String, usize> {
+        let mut histogram = BTreeMap::new();
+        for field in self.root().recursive_entries().flat_map(|e| e.fields()) {
+            if !self.standard_fields.contains(field.key()) {
+                *histogram.entry(field.key().to_string()).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Find fields with values longer than `max_len` bytes
+    ///
+    /// Historical versions of entries are checked too, and reported with
+    /// the UUID of the entry they belong to.
+    pub fn oversized_fields(&self, max_len: usize) -> Vec<OversizedField> {
+        let mut oversized = Vec::new();
+        for entry in self.root().recursive_entries() {
+            for version in std::iter::once(entry).chain(entry.history().entries()) {
+                for field in version.fields() {
+                    if field.value.len() > max_len {
+                        oversized.push(OversizedField {
+                            entry_uuid: entry.uuid(),
+                            key: field.key.clone(),
+                            len: field.value.len(),
+                        });
+                    }
+                }
+            }
+        }
+        oversized
+    }
+
+    /// Cut every field value, including in history, down to at most `max_len` bytes
+    pub(crate) fn truncate_fields(&mut self, max_len: usize) {
+        self.root_mut().walk_entries_mut(|entry| {
+            for field in entry.fields.iter_mut() {
+                field.truncate(max_len);
+            }
+            for version in entry.history.entries_mut() {
+                for field in version.fields.iter_mut() {
+                    field.truncate(max_len);
+                }
+            }
+        });
+    }
+
+    /// Report password strength, reuse, expiry and age for every entry
+    pub fn entry_health(&self) -> Vec<EntryHealth> {
+        let now = chrono::Utc::now().naive_utc();
+        let passwords: Vec<_> = self
+            .root()
+            .recursive_entries()
+            .map(|entry| (entry, entry.reveal("Password").unwrap_or_default()))
+            .collect();
+        let mut password_uses = std::collections::HashMap::new();
+        for (_, password) in &passwords {
+            if !password.is_empty() {
+                *password_uses.entry(password.as_ref()).or_insert(0) += 1;
+            }
+        }
+
+        passwords
+            .iter()
+            .map(|(entry, password)| {
+                let password = password.as_ref();
+                EntryHealth {
+                    uuid: entry.uuid(),
+                    entropy: estimate_entropy(password),
+                    reused: password_uses.get(password).copied().unwrap_or(0) > 1,
+                    expired: entry.is_expired(),
+                    age_days: (now - entry.times.last_modification_time).num_days(),
+                }
+            })
+            .collect()
+    }
+
     /// Top level group for database entries
     pub fn root(&self) -> &Group {
         &self.groups[0]
@@ -866,7 +2825,176 @@ impl Database {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Field keys treated as standard rather than custom fields
+///
+/// Defaults to the keys with a dedicated meaning in KeePass, plus `otp`.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct StandardFields {
+    keys: Vec<String>,
+}
+
+impl StandardFields {
+    /// Whether `key` is a standard field
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| k == key)
+    }
+
+    /// Treat `key` as a standard field
+    pub fn add<S: ToString>(&mut self, key: S) {
+        let key = key.to_string();
+        if !self.contains(&key) {
+            self.keys.push(key);
+        }
+    }
+
+    /// Treat `key` as a custom field, returning whether it was standard
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.keys.len();
+        self.keys.retain(|k| k != key);
+        self.keys.len() != len
+    }
+
+    /// The standard field keys
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(String::as_str)
+    }
+}
+
+impl Default for StandardFields {
+    fn default() -> StandardFields {
+        StandardFields {
+            keys: STANDARD_FIELDS.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+/// Errors adding items to a database
+pub enum AddError {
+    /// No group exists with the given UUID
+    #[error("No group found with UUID {0}")]
+    GroupNotFound(Uuid),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Steps taken by [`Database::compact`]
+pub struct CompactOptions {
+    /// Prune entry history to the limits in [`Meta`]
+    pub prune_history: bool,
+    /// Remove groups without entries or child groups, other than the root and recycle bin
+    pub remove_empty_groups: bool,
+    /// Remove protected fields that were parsed lazily and fail to decrypt
+    pub drop_undecryptable: bool,
+}
+
+impl Default for CompactOptions {
+    fn default() -> CompactOptions {
+        CompactOptions {
+            prune_history: true,
+            remove_empty_groups: true,
+            drop_undecryptable: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Options for [`Database::to_text_with_options`]
+pub struct TextOptions {
+    /// Show protected values instead of their SHA-256 hash
+    pub reveal_protected: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Summary of the data removed by [`Database::compact`]
+pub struct CompactReport {
+    /// Historical entry versions removed
+    pub history_removed: usize,
+    /// Attachment binaries no longer referred to by any entry
+    pub binaries_removed: usize,
+    /// Empty groups removed
+    pub empty_groups_removed: usize,
+    /// Fields removed because they could not be decrypted
+    pub undecryptable_removed: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Summary of the changes made by [`Database::merge`]
+pub struct MergeReport {
+    /// Entries and groups only present in the other database
+    pub added: usize,
+    /// Entries and groups replaced by a newer version from the other database
+    pub updated: usize,
+    /// Entries and groups where the existing version was kept
+    pub skipped: usize,
+    /// Entries and groups removed because of a tombstone
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A field found by [`Database::oversized_fields`]
+pub struct OversizedField {
+    /// Identifier of the entry containing the field
+    pub entry_uuid: Uuid,
+    /// Name of the field
+    pub key: String,
+    /// Length of the field value in bytes
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Password health report for a single entry
+pub struct EntryHealth {
+    /// Identifier of the entry
+    pub uuid: Uuid,
+    /// Estimated strength of the password in bits
+    pub entropy: f64,
+    /// Whether another entry uses the same password
+    pub reused: bool,
+    /// Whether the entry has passed its expiry time, see [`Entry::is_expired`]
+    pub expired: bool,
+    /// Whole days since the entry was last modified, measured in UTC
+    pub age_days: i64,
+}
+
+/// Estimate password strength in bits from its length and the character classes it uses
+fn estimate_entropy(password: &str) -> f64 {
+    let mut pool = 0u32;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0.0;
+    }
+    password.chars().count() as f64 * f64::from(pool).log2()
+}
+
+const OTP_DEFAULT_PERIOD: u32 = 30;
+const OTP_DEFAULT_DIGITS: u32 = 6;
+
 /// TOTP one time password secret in KeepassXC format
+///
+/// Both `otpauth://` URLs and bare Base32 secrets are understood. Bare secrets
+/// use a period of 30 seconds and 6 digits.
 pub struct Otp<'a> {
     url: Cow<'a, str>,
 }
@@ -901,18 +3029,119 @@ impl<'a> Otp<'a> {
         None
     }
 
+    fn bare_secret(&self) -> Option<&str> {
+        let secret = self.url.trim();
+        let is_base32 = secret
+            .chars()
+            .filter(|c| *c != ' ')
+            .all(|c| matches!(c, 'A'..='Z' | 'a'..='z' | '2'..='7' | '='));
+        if !secret.is_empty() && is_base32 {
+            Some(secret)
+        } else {
+            None
+        }
+    }
+
     /// Retrieve the secret used to generate one time passwords
+    ///
+    /// Bare secrets are returned as stored, including any grouping spaces.
     pub fn secret(&self) -> Option<&str> {
-        self.find_url_param("secret")
+        self.bare_secret().or_else(|| self.find_url_param("secret"))
     }
 
     /// Return the period for which passwords are valid
     pub fn period(&self) -> Option<u32> {
-        self.find_url_param("secret").and_then(|p| p.parse().ok())
+        if self.bare_secret().is_some() {
+            return Some(OTP_DEFAULT_PERIOD);
+        }
+        self.find_url_param("period").and_then(|p| p.parse().ok())
     }
 
     /// Return the number of digits in the resulting code
     pub fn digits(&self) -> Option<u32> {
+        if self.bare_secret().is_some() {
+            return Some(OTP_DEFAULT_DIGITS);
+        }
         self.find_url_param("digits").and_then(|p| p.parse().ok())
     }
 }
+
+/// Nesting depth after which field references are left unresolved, as in KeePass
+const MAX_REFERENCE_DEPTH: usize = 12;
+
+/// Standard field named by a single letter code in a `{REF:...}` field reference
+fn reference_field(code: char) -> Option<&'static str> {
+    match code.to_ascii_uppercase() {
+        'T' => Some("Title"),
+        'U' => Some("UserName"),
+        'P' => Some("Password"),
+        'A' => Some("URL"),
+        'N' => Some("Notes"),
+        _ => None,
+    }
+}
+
+/// Find the entry a `{REF:...}` reference points to and read the wanted value from it
+///
+/// `body` is the part of the reference between `{REF:` and `}`, such as `P@I:<uuid>`.
+fn resolve_reference(db: &Database, body: &str, depth: usize) -> Option<String> {
+    let mut chars = body.chars();
+    let wanted = chars.next()?.to_ascii_uppercase();
+    if chars.next()? != '@' {
+        return None;
+    }
+    let search_in = chars.next()?.to_ascii_uppercase();
+    if chars.next()? != ':' {
+        return None;
+    }
+    let term = chars.as_str();
+
+    let entry = if search_in == 'I' {
+        db.entry_by_uuid(Uuid::parse_str(term).ok()?)?
+    } else {
+        let field = reference_field(search_in)?;
+        let term = term.to_lowercase();
+        db.find_entry(|entry| {
+            entry
+                .find(field)
+                .and_then(|f| f.reveal())
+                .map(|value| value.to_lowercase().contains(&term))
+                .unwrap_or(false)
+        })?
+    };
+
+    if wanted == 'I' {
+        return Some(entry.uuid().simple().to_string().to_uppercase());
+    }
+    let value = entry.find(reference_field(wanted)?)?.reveal()?;
+    Some(resolve_references_at(db, &value, depth + 1))
+}
+
+fn resolve_references_at(db: &Database, text: &str, depth: usize) -> String {
+    const REF_START: &str = "{REF:";
+    if depth > MAX_REFERENCE_DEPTH {
+        return text.to_string();
+    }
+    let mut resolved = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.to_ascii_uppercase().find(REF_START) {
+        resolved.push_str(&rest[..start]);
+        let reference = &rest[start..];
+        let replacement = reference.find('}').and_then(|end| {
+            let value = resolve_reference(db, &reference[REF_START.len()..end], depth)?;
+            Some((value, end + 1))
+        });
+        match replacement {
+            Some((value, len)) => {
+                resolved.push_str(&value);
+                rest = &reference[len..];
+            }
+            None => {
+                resolved.push_str(&reference[..REF_START.len()]);
+                rest = &reference[REF_START.len()..];
+            }
+        }
+    }
+    resolved.push_str(rest);
+    resolved
+}
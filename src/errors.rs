@@ -1,8 +1,10 @@
 //! Error types for kdbx-rs
 
-pub use crate::binary::errors::{HeaderError, OpenError, UnlockError, WriteError};
+pub use crate::binary::errors::{HeaderError, Incompatibility, OpenError, UnlockError, WriteError};
 pub use crate::binary::FailedUnlock;
 pub use crate::crypto::KeyGenerationError;
+pub use crate::database::AddError;
+pub use crate::import::ImportError;
 pub use crate::stream::random::InnerStreamError;
 pub use crate::xml::parse::Error as XmlReadError;
 pub use crate::xml::serialize::Error as XmlWriteError;
@@ -29,6 +31,9 @@ pub enum Error {
     /// Failed generating crypto keys
     #[error("Failed to create encryption keys")]
     KeyGeneration(#[from] KeyGenerationError),
+    /// A database read back after writing did not match the original
+    #[error("Database changed when written and read back: {0}")]
+    RoundTripMismatch(String),
 }
 
 impl From<FailedUnlock> for Error {
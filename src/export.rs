@@ -0,0 +1,72 @@
+//! Export decrypted databases to other formats
+
+use crate::database::{Database, Entry};
+use std::io::{self, Write};
+
+/// Columns written by [`to_csv`], in order
+pub const CSV_HEADER: [&str; 6] = ["Group", "Title", "Username", "Password", "URL", "Notes"];
+
+/// Entry fields written to the columns after `Group`
+const CSV_FIELDS: [&str; 5] = ["Title", "UserName", "Password", "URL", "Notes"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Options for [`to_csv_with_options`]
+pub struct CsvOptions {
+    /// Leave protected values, such as passwords, empty
+    pub redact_protected: bool,
+}
+
+/// Write every entry in a database as CSV, including protected values
+///
+/// The first row is the header in [`CSV_HEADER`]. Each entry is written as
+/// one row, starting with the names of the groups containing it joined by `/`.
+/// Every value is quoted, so values may contain commas, quotes and newlines.
+pub fn to_csv<W: Write>(db: &Database, writer: W) -> io::Result<()> {
+    to_csv_with_options(db, writer, &CsvOptions::default())
+}
+
+/// Write every entry in a database as CSV using the given options
+///
+/// See [`to_csv`] for the format.
+pub fn to_csv_with_options<W: Write>(
+    db: &Database,
+    mut writer: W,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    write_csv_row(&mut writer, CSV_HEADER.iter().copied())?;
+    for (path, entry) in db.iter_entries() {
+        let group = path.join("/");
+        let values: Vec<String> = CSV_FIELDS
+            .iter()
+            .map(|key| csv_value(entry, key, options))
+            .collect();
+        write_csv_row(
+            &mut writer,
+            std::iter::once(group.as_str()).chain(values.iter().map(String::as_str)),
+        )?;
+    }
+    writer.flush()
+}
+
+/// Value of a field for a CSV row, empty if missing or redacted
+fn csv_value(entry: &Entry, key: &str, options: &CsvOptions) -> String {
+    match entry.find(key) {
+        Some(field) if !(options.redact_protected && field.protected()) => {
+            field.reveal().unwrap_or_default().into_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+fn write_csv_row<'a, W: Write, I: Iterator<Item = &'a str>>(
+    writer: &mut W,
+    values: I,
+) -> io::Result<()> {
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        write!(writer, "\"{}\"", value.replace('"', "\"\""))?;
+    }
+    writer.write_all(b"\n")
+}
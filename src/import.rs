@@ -0,0 +1,179 @@
+//! Import entries from other formats into a database
+
+use crate::database::{Database, Entry, Field, Group};
+use std::io::{self, Read};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+/// Errors encountered importing a database
+pub enum ImportError {
+    /// Failed to read the input
+    #[error("Could not read import data: {0}")]
+    Io(#[from] io::Error),
+    /// The input is not valid UTF-8
+    #[error("Import data is not valid UTF-8")]
+    InvalidUtf8,
+    /// The input has no header row
+    #[error("CSV data has no header row")]
+    MissingHeader,
+    /// A quoted value is not closed before the end of the input
+    #[error("Quoted CSV value starting on line {0} is never closed")]
+    UnterminatedQuote(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Options for [`from_csv_with_options`]
+pub struct CsvOptions {
+    /// Character separating values in a row
+    pub delimiter: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions { delimiter: ',' }
+    }
+}
+
+/// Column of a CSV file an entry field or group path is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Group,
+    Field(&'static str),
+}
+
+impl Column {
+    /// Column for a header name, matching KeePass and KeePassXC exports
+    fn from_header(name: &str) -> Option<Column> {
+        let column = match name.trim().to_ascii_lowercase().as_ref() {
+            "group" => Column::Group,
+            "title" | "account" => Column::Field("Title"),
+            "username" | "user name" | "login name" => Column::Field("UserName"),
+            "password" => Column::Field("Password"),
+            "url" | "web site" => Column::Field("URL"),
+            "notes" | "comments" => Column::Field("Notes"),
+            _ => return None,
+        };
+        Some(column)
+    }
+}
+
+/// Read entries from a CSV file into a new database
+///
+/// The first row is a header naming the columns. `Group`, `Title`,
+/// `Username`, `Password`, `URL` and `Notes` columns are read, in any order,
+/// along with the KeePass 1 names such as `Account` and `Web Site`. Other
+/// columns are ignored. Values may be quoted, with `""` for a quote, to
+/// contain the delimiter, quotes or newlines.
+///
+/// The group column holds the names of the groups containing an entry
+/// joined by `/`, starting with the root group, as written by
+/// [`export::to_csv`][crate::export::to_csv]. Missing groups are created.
+/// Each entry gets a new UUID and the current time for its times.
+pub fn from_csv<R: Read>(reader: R) -> Result<Database, ImportError> {
+    from_csv_with_options(reader, &CsvOptions::default())
+}
+
+/// Read entries from a CSV file into a new database using the given options
+///
+/// See [`from_csv`] for the format.
+pub fn from_csv_with_options<R: Read>(
+    mut reader: R,
+    options: &CsvOptions,
+) -> Result<Database, ImportError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let text = String::from_utf8(data).map_err(|_| ImportError::InvalidUtf8)?;
+    let mut rows = parse_csv(&text, options.delimiter)?.into_iter();
+    let columns: Vec<Option<Column>> = rows
+        .next()
+        .ok_or(ImportError::MissingHeader)?
+        .iter()
+        .map(|name| Column::from_header(name))
+        .collect();
+
+    let mut db = Database::default();
+    let mut root_named = false;
+    for row in rows {
+        let mut path = Vec::new();
+        let mut entry = Entry::default();
+        for (column, value) in columns.iter().zip(row) {
+            match column {
+                Some(Column::Group) => {
+                    path = value.split('/').map(str::to_string).collect();
+                }
+                Some(Column::Field("Password")) => entry.set_password(value),
+                Some(Column::Field(key)) => entry.add_field(Field::new(key, &value)),
+                None => {}
+            }
+        }
+        let mut names = path.iter().filter(|name| !name.is_empty());
+        if let Some(root_name) = names.next() {
+            if !root_named {
+                db.root_mut().set_name(root_name);
+                root_named = true;
+            }
+        }
+        let group = names.fold(db.root_mut(), |group, name| child_group(group, name));
+        group.add_entry(entry);
+    }
+    Ok(db)
+}
+
+/// The child group of `group` with a given name, created if it does not exist
+fn child_group<'a>(group: &'a mut Group, name: &str) -> &'a mut Group {
+    let existing = group.groups().position(|child| child.name() == name);
+    let index = existing.unwrap_or_else(|| {
+        group.add_group(Group::new(name));
+        group.groups().count() - 1
+    });
+    group.groups_mut().nth(index).unwrap()
+}
+
+/// Split CSV text into rows of values, skipping empty lines
+fn parse_csv(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, ImportError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut value = String::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if value.is_empty() => {
+                let start_line = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            value.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            value.push(c);
+                        }
+                        None => return Err(ImportError::UnterminatedQuote(start_line)),
+                    }
+                }
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                row.push(std::mem::take(&mut value));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            c if c == delimiter => row.push(std::mem::take(&mut value)),
+            c => value.push(c),
+        }
+    }
+    if !row.is_empty() || !value.is_empty() {
+        row.push(value);
+        rows.push(row);
+    }
+    Ok(rows)
+}
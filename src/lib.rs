@@ -26,7 +26,8 @@
 //! ```
 //!
 //! Alternatively, [`kdbx_rs::from_reader`] can be used to open a database
-//! from a non file source (such as in-memory or a network stream)
+//! from a non file source (such as a network stream), or [`kdbx_rs::from_bytes`]
+//! for a database already in memory
 //!
 //! # Generating a new password database
 //!
@@ -60,12 +61,12 @@
 //! credentials to save your database. This can be done with [`Kdbx.set_key`].
 //! Provide a [`CompositeKey`] instance, which can be created the same way as for
 //! unlocking database. This will then be used to generate the remaining keys
-//! allowing you to save the database using [`Kdbx.write()`]
+//! allowing you to save the database using [`Kdbx.save()`], or [`Kdbx.write()`]
+//! for other outputs.
 //!
 //! ```rust
 //! use kdbx_rs::{CompositeKey, Kdbx};
 //! # use kdbx_rs::Database;
-//! # use std::fs::File;
 //!
 //! # fn main() -> Result<(), kdbx_rs::Error> {
 //! # let mut database = Database::default();
@@ -73,8 +74,7 @@
 //! let mut kdbx = Kdbx::from_database(database);
 //! kdbx.set_key(CompositeKey::from_password("foo123"))?;
 //!
-//! let mut file = File::create(file_path).unwrap();
-//! kdbx.write(&mut file)?;
+//! kdbx.save(file_path)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -87,23 +87,31 @@
 //! [`CompositeKey`]: crate::CompositeKey
 //! [`Database`]: crate::Database
 //! [`Database::default()`]: crate::Database#method.default
+//! [`kdbx_rs::from_bytes`]: crate::from_bytes
 //! [`kdbx_rs::from_reader`]: crate::from_reader
 //! [`kdbx_rs::open`]: crate::open
 //! [`Kdbx`]: crate::Kdbx
 //! [`Kdbx.from_database`]: crate::Kdbx#method.from_database
 //! [`Kdbx.set_key`]: crate::Kdbx#method.set_key
 //! [`Kdbx.unlock`]: crate::Kdbx#method.unlock
-//! [`Kdbx.write`]: crate::Kdbx#method.write
+//! [`Kdbx.save()`]: crate::Kdbx#method.save
+//! [`Kdbx.write()`]: crate::Kdbx#method.write
 
 pub mod binary;
 mod crypto;
 pub mod database;
 pub mod errors;
+pub mod export;
+pub mod import;
 mod stream;
 pub mod utils;
 pub mod xml;
 
 pub use crate::database::Database;
-pub use binary::{from_reader, open, Kdbx};
+#[cfg(feature = "tokio")]
+pub use binary::from_async_reader;
+#[cfg(all(unix, feature = "mmap"))]
+pub use binary::open_mmap;
+pub use binary::{detect_format, from_bytes, from_reader, open, open_any, Kdbx};
 pub use crypto::CompositeKey;
 pub use errors::Error;
@@ -10,5 +10,8 @@ pub(crate) use self::stream_cipher::{
     StreamCipherReader, StreamCipherWriter, StreamCipherWriterExt,
 };
 pub(crate) use block_cipher::{BlockCipherReader, BlockCipherWriter, BlockCipherWriterExt};
-pub(crate) use kdbx3::HashedBlockReader;
-pub(crate) use pipeline::{kdbx3_read_stream, kdbx4_read_stream, kdbx4_write_stream};
+pub(crate) use kdbx3::{HashedBlockReader, HashedBlockWriter};
+pub(crate) use pipeline::{
+    decryption_stream, kdbx3_read_stream, kdbx3_write_stream, kdbx4_read_stream,
+    kdbx4_write_stream, StageError, WriteStage,
+};
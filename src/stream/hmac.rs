@@ -1,5 +1,6 @@
 pub const HMAC_WRITE_BLOCK_SIZE: usize = 1024 * 1024;
 
+use super::pipeline::{tag_stage, WriteStage};
 use crate::crypto::HmacKey;
 use std::io::{self, Read, Write};
 
@@ -112,9 +113,9 @@ where
 {
     pub(crate) fn finish(mut self) -> io::Result<W> {
         if !self.buffer.is_empty() {
-            self.write_block()?;
+            self.write_block().map_err(tag_stage(WriteStage::Hmac))?;
         }
-        self.write_block()?;
+        self.write_block().map_err(tag_stage(WriteStage::Hmac))?;
         Ok(self.inner)
     }
 }
@@ -126,14 +127,17 @@ impl<'a, W: 'a + io::Write> io::Write for HmacWriter<'a, W> {
         self.buffer.extend_from_slice(&buf[0..write_size]);
         if write_size < buf.len() {
             // Internal buffer full, write it out
-            self.write_block()?;
+            self.write_block().map_err(tag_stage(WriteStage::Hmac))?;
             self.buffer.clear();
         }
         Ok(write_size)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.write_block()?;
-        Ok(())
+        // An empty block marks the end of the stream, so only write out pending data
+        if !self.buffer.is_empty() {
+            self.write_block().map_err(tag_stage(WriteStage::Hmac))?;
+        }
+        self.inner.flush().map_err(tag_stage(WriteStage::Hmac))
     }
 }
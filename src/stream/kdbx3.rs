@@ -1,5 +1,7 @@
 use crate::crypto;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
+
+const HASHED_BLOCK_SIZE: usize = 1024 * 1024;
 
 pub struct HashedBlockReader<R>
 where
@@ -33,7 +35,8 @@ where
         self.buffer.resize_with(len, Default::default);
         self.inner.read_exact(&mut self.buffer)?;
         self.buf_idx = 0;
-        if crypto::verify_sha256(&self.buffer, &hash_buf) {
+        // The final empty block has an all zero hash rather than the hash of no data
+        if len == 0 || crypto::verify_sha256(&self.buffer, &hash_buf) {
             Ok(len)
         } else {
             Err(io::Error::new(
@@ -62,3 +65,72 @@ where
         Ok(copy_len)
     }
 }
+
+pub struct HashedBlockWriter<W>
+where
+    W: Write,
+{
+    inner: W,
+    buffer: Vec<u8>,
+    block_idx: u32,
+}
+
+impl<W> HashedBlockWriter<W>
+where
+    W: Write,
+{
+    pub(crate) fn new(inner: W) -> HashedBlockWriter<W> {
+        HashedBlockWriter {
+            inner,
+            buffer: Vec::with_capacity(HASHED_BLOCK_SIZE),
+            block_idx: 0,
+        }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let hash = if self.buffer.is_empty() {
+            vec![0u8; 32]
+        } else {
+            crypto::sha256(&self.buffer)
+        };
+        self.inner.write_all(&self.block_idx.to_le_bytes())?;
+        self.inner.write_all(&hash)?;
+        self.inner
+            .write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&self.buffer)?;
+        self.buffer.clear();
+        self.block_idx += 1;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.write_block()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W> Write for HashedBlockWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let space_in_buffer = HASHED_BLOCK_SIZE - self.buffer.len();
+        let write_size = usize::min(buf.len(), space_in_buffer);
+        self.buffer.extend_from_slice(&buf[0..write_size]);
+        if self.buffer.len() == HASHED_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // An empty block marks the end of the stream, so only write out pending data
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.flush()
+    }
+}
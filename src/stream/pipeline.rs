@@ -1,6 +1,6 @@
 use crate::binary;
 use crate::crypto;
-use std::io;
+use std::io::{self, Write};
 
 use aes::{Aes128, Aes256};
 use chacha20::ChaCha20;
@@ -10,13 +10,43 @@ use cipher::BlockEncrypt;
 use cipher::KeyInit;
 use cipher::KeyIvInit;
 use derive_more::From;
+use thiserror::Error;
 use twofish::Twofish;
 
 use super::{
     BlockCipherReader, BlockCipherWriter, BlockCipherWriterExt, HMacReader, HashedBlockReader,
-    HmacWriter, StreamCipherWriterExt,
+    HashedBlockWriter, HmacWriter, StreamCipherWriterExt,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Stage of the write pipeline in which an error occurred
+pub(crate) enum WriteStage {
+    Compression,
+    Cipher,
+    Hmac,
+}
+
+#[derive(Debug, Error)]
+#[error("{source}")]
+/// IO error annotated with the write pipeline stage it came from
+pub(crate) struct StageError {
+    pub(crate) stage: WriteStage,
+    pub(crate) source: io::Error,
+}
+
+/// Annotate an error with the stage it occurred in, unless a later stage
+/// already claimed it
+pub(crate) fn tag_stage(stage: WriteStage) -> impl Fn(io::Error) -> io::Error {
+    move |e| {
+        let already_tagged = e.get_ref().map(|inner| inner.is::<StageError>());
+        if already_tagged.unwrap_or(false) {
+            e
+        } else {
+            io::Error::new(e.kind(), StageError { stage, source: e })
+        }
+    }
+}
+
 fn block_cipher_read_stream<C, R>(
     inner: R,
     key: crypto::CipherKey,
@@ -34,6 +64,18 @@ where
     })
 }
 
+fn chacha20_cipher(key: &crypto::CipherKey, iv: &[u8]) -> io::Result<ChaCha20> {
+    ChaCha20::new_from_slices(&key.0, iv).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid cipher params - ChaCha20 requires a 12 byte IV, got {}",
+                iv.len()
+            ),
+        )
+    })
+}
+
 pub(crate) fn decryption_stream<'a, R: io::Read + 'a>(
     inner: R,
     cipher_key: crypto::CipherKey,
@@ -51,7 +93,7 @@ pub(crate) fn decryption_stream<'a, R: io::Read + 'a>(
             inner, cipher_key, iv,
         )?),
         binary::Cipher::ChaCha20 => {
-            let cipher = ChaCha20::new_from_slices(&cipher_key.0, iv).unwrap();
+            let cipher = chacha20_cipher(&cipher_key, iv)?;
             Box::new(super::StreamCipherReader::new(inner, cipher))
         }
         _ => {
@@ -128,19 +170,20 @@ enum EncryptWrite<'a, W>
 where
     W: 'a + io::Write,
 {
-    Block(Box<dyn BlockCipherWriterExt<'a, HmacWriter<'a, W>> + 'a>),
-    Stream(Box<dyn StreamCipherWriterExt<HmacWriter<'a, W>> + 'a>),
+    Block(Box<dyn BlockCipherWriterExt<'a, W> + 'a>),
+    Stream(Box<dyn StreamCipherWriterExt<W> + 'a>),
 }
 
 impl<'a, W> EncryptWrite<'a, W>
 where
     W: 'a + io::Write,
 {
-    fn finish(self) -> io::Result<HmacWriter<'a, W>> {
+    fn finish(self) -> io::Result<W> {
         match self {
             EncryptWrite::Block(mut inner) => inner.finish(),
             EncryptWrite::Stream(mut inner) => Ok(inner.take_innner()),
         }
+        .map_err(tag_stage(WriteStage::Cipher))
     }
 }
 
@@ -153,6 +196,7 @@ where
             EncryptWrite::Block(inner) => inner.write(buf),
             EncryptWrite::Stream(inner) => inner.write(buf),
         }
+        .map_err(tag_stage(WriteStage::Cipher))
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -160,29 +204,59 @@ where
             EncryptWrite::Block(inner) => inner.flush(),
             EncryptWrite::Stream(inner) => inner.flush(),
         }
+        .map_err(tag_stage(WriteStage::Cipher))
     }
 }
 
-enum Kdbx4WriteInner<'a, W>
+enum CompressWrite<W>
 where
-    W: 'a + io::Write,
+    W: io::Write,
 {
-    Raw(EncryptWrite<'a, W>),
-    Gzip(libflate::gzip::Encoder<EncryptWrite<'a, W>>),
+    Raw(W),
+    Gzip(libflate::gzip::Encoder<W>),
 }
 
-#[derive(From)]
-pub struct Kdbx4Write<'a, W: 'a + io::Write>(Kdbx4WriteInner<'a, W>);
+impl<W> CompressWrite<W>
+where
+    W: io::Write,
+{
+    fn finish(self) -> io::Result<W> {
+        match self {
+            CompressWrite::Raw(inner) => Ok(inner),
+            CompressWrite::Gzip(gz) => gz.finish().into_result(),
+        }
+        .map_err(tag_stage(WriteStage::Compression))
+    }
+}
+
+impl<W> io::Write for CompressWrite<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressWrite::Raw(ref mut inner) => inner.write(buf),
+            CompressWrite::Gzip(ref mut inner) => inner.write(buf),
+        }
+        .map_err(tag_stage(WriteStage::Compression))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressWrite::Raw(ref mut inner) => inner.flush(),
+            CompressWrite::Gzip(ref mut inner) => inner.flush(),
+        }
+        .map_err(tag_stage(WriteStage::Compression))
+    }
+}
+
+pub struct Kdbx4Write<'a, W: 'a + io::Write>(CompressWrite<EncryptWrite<'a, HmacWriter<'a, W>>>);
 
 impl<'a, W> Kdbx4Write<'a, W>
 where
     W: 'a + io::Write,
 {
     pub(crate) fn finish(self) -> io::Result<W> {
-        let encryption = match self.0 {
-            Kdbx4WriteInner::Raw(inner) => Ok(inner),
-            Kdbx4WriteInner::Gzip(gz) => gz.finish().into_result(),
-        }?;
+        let encryption = self.0.finish()?;
         let hmacw = encryption.finish()?;
         let mut inner = hmacw.finish()?;
         inner.flush()?;
@@ -195,26 +269,47 @@ where
     W: 'a + io::Write,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self.0 {
-            Kdbx4WriteInner::Raw(ref mut inner) => inner.write(buf),
-            Kdbx4WriteInner::Gzip(ref mut inner) => inner.write(buf),
-        }
+        self.0.write(buf)
     }
     fn flush(&mut self) -> io::Result<()> {
-        match self.0 {
-            Kdbx4WriteInner::Raw(ref mut inner) => inner.flush(),
-            Kdbx4WriteInner::Gzip(ref mut inner) => inner.flush(),
-        }
+        self.0.flush()
+    }
+}
+
+pub struct Kdbx3Write<'a, W: 'a + io::Write>(CompressWrite<HashedBlockWriter<EncryptWrite<'a, W>>>);
+
+impl<'a, W> Kdbx3Write<'a, W>
+where
+    W: 'a + io::Write,
+{
+    pub(crate) fn finish(self) -> io::Result<W> {
+        let hashed = self.0.finish()?;
+        let encryption = hashed.finish()?;
+        let mut inner = encryption.finish()?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<'a, W> io::Write for Kdbx3Write<'a, W>
+where
+    W: 'a + io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
     }
 }
 
 fn block_cipher_write_stream<'a, C, W>(
-    inner: HmacWriter<'a, W>,
+    inner: W,
     key: crypto::CipherKey,
     iv: &[u8],
 ) -> io::Result<EncryptWrite<'a, W>>
 where
-    W: io::Write,
+    W: 'a + io::Write,
     C: BlockCipher + BlockEncrypt + KeyInit + 'static,
 {
     let writer = BlockCipherWriter::<C, _>::wrap(inner, key, iv).map_err(|_| {
@@ -227,24 +322,19 @@ where
     Ok(EncryptWrite::Block(Box::new(writer) as Box<_>))
 }
 
-pub(crate) fn kdbx4_write_stream<'a, W: 'a + io::Write>(
+fn encryption_stream<'a, W: 'a + io::Write>(
     inner: W,
-    hmac_key: crypto::HmacKey,
     cipher_key: crypto::CipherKey,
     cipher: binary::Cipher,
     iv: &[u8],
-    compression: binary::CompressionType,
-) -> io::Result<Kdbx4Write<'a, W>> {
-    let verified = HmacWriter::new(inner, hmac_key);
-    let encrypted = match cipher {
-        binary::Cipher::Aes256 => block_cipher_write_stream::<Aes256, _>(verified, cipher_key, iv)?,
-        binary::Cipher::Aes128 => block_cipher_write_stream::<Aes128, _>(verified, cipher_key, iv)?,
-        binary::Cipher::TwoFish => {
-            block_cipher_write_stream::<Twofish, _>(verified, cipher_key, iv)?
-        }
+) -> io::Result<EncryptWrite<'a, W>> {
+    Ok(match cipher {
+        binary::Cipher::Aes256 => block_cipher_write_stream::<Aes256, _>(inner, cipher_key, iv)?,
+        binary::Cipher::Aes128 => block_cipher_write_stream::<Aes128, _>(inner, cipher_key, iv)?,
+        binary::Cipher::TwoFish => block_cipher_write_stream::<Twofish, _>(inner, cipher_key, iv)?,
         binary::Cipher::ChaCha20 => {
-            let cipher = ChaCha20::new_from_slices(&cipher_key.0, iv).unwrap();
-            EncryptWrite::Stream(Box::new(super::StreamCipherWriter::new(verified, cipher)))
+            let cipher = chacha20_cipher(&cipher_key, iv)?;
+            EncryptWrite::Stream(Box::new(super::StreamCipherWriter::new(inner, cipher)))
         }
         _ => {
             return Err(io::Error::new(
@@ -252,11 +342,22 @@ pub(crate) fn kdbx4_write_stream<'a, W: 'a + io::Write>(
                 format!("Unsupported cipher setting {:?}", cipher),
             ))
         }
-    };
+    })
+}
+
+fn compression_stream<W: io::Write>(
+    inner: W,
+    compression: binary::CompressionType,
+) -> io::Result<CompressWrite<W>> {
     Ok(match compression {
-        binary::CompressionType::None => Kdbx4WriteInner::Raw(encrypted).into(),
+        binary::CompressionType::None => CompressWrite::Raw(inner),
         binary::CompressionType::Gzip => {
-            Kdbx4WriteInner::Gzip(libflate::gzip::Encoder::new(encrypted)?).into()
+            // No timestamp, so unchanged content always compresses to the same bytes
+            let header = libflate::gzip::HeaderBuilder::new()
+                .modification_time(0)
+                .finish();
+            let options = libflate::gzip::EncodeOptions::new().header(header);
+            CompressWrite::Gzip(libflate::gzip::Encoder::with_options(inner, options)?)
         }
         binary::CompressionType::Unknown(_) => {
             return Err(io::Error::new(
@@ -266,3 +367,32 @@ pub(crate) fn kdbx4_write_stream<'a, W: 'a + io::Write>(
         }
     })
 }
+
+pub(crate) fn kdbx3_write_stream<'a, W: 'a + io::Write>(
+    inner: W,
+    cipher_key: crypto::CipherKey,
+    cipher: binary::Cipher,
+    iv: &[u8],
+    compression: binary::CompressionType,
+    start_bytes: &[u8],
+) -> io::Result<Kdbx3Write<'a, W>> {
+    let mut encrypted =
+        encryption_stream(inner, cipher_key, cipher, iv).map_err(tag_stage(WriteStage::Cipher))?;
+    encrypted.write_all(start_bytes)?;
+    let hashed = HashedBlockWriter::new(encrypted);
+    Ok(Kdbx3Write(compression_stream(hashed, compression)?))
+}
+
+pub(crate) fn kdbx4_write_stream<'a, W: 'a + io::Write>(
+    inner: W,
+    hmac_key: crypto::HmacKey,
+    cipher_key: crypto::CipherKey,
+    cipher: binary::Cipher,
+    iv: &[u8],
+    compression: binary::CompressionType,
+) -> io::Result<Kdbx4Write<'a, W>> {
+    let verified = HmacWriter::new(inner, hmac_key);
+    let encrypted = encryption_stream(verified, cipher_key, cipher, iv)
+        .map_err(tag_stage(WriteStage::Cipher))?;
+    Ok(Kdbx4Write(compression_stream(encrypted, compression)?))
+}
@@ -1,9 +1,10 @@
 use chacha20::ChaCha20;
 use cipher::KeyIvInit;
-use cipher::StreamCipher;
+use cipher::{StreamCipher, StreamCipherSeek};
 use salsa20::Salsa20;
 use sha2::{Digest, Sha256, Sha512};
 use thiserror::Error;
+use zeroize::Zeroize;
 
 use crate::binary::InnerStreamCipherAlgorithm;
 
@@ -20,20 +21,66 @@ pub enum InnerStreamError {
 impl InnerStreamCipherAlgorithm {
     /// Create a stream cipher instance for this algorithm
     pub fn stream_cipher(self, key: &[u8]) -> Result<Box<dyn StreamCipher>, InnerStreamError> {
+        self.stream_cipher_at(key, 0)
+    }
+
+    /// Create a stream cipher instance positioned `offset` bytes into the keystream
+    pub(crate) fn stream_cipher_at(
+        self,
+        key: &[u8],
+        offset: u64,
+    ) -> Result<Box<dyn StreamCipher>, InnerStreamError> {
         match self {
             InnerStreamCipherAlgorithm::ChaCha20 => {
                 let iv = Sha512::digest(key);
-                Ok(Box::new(
-                    ChaCha20::new_from_slices(&iv[0..32], &iv[32..44]).unwrap(),
-                ))
+                let mut cipher = ChaCha20::new_from_slices(&iv[0..32], &iv[32..44]).unwrap();
+                cipher.seek(offset);
+                Ok(Box::new(cipher))
             }
             InnerStreamCipherAlgorithm::Salsa20 => {
                 let iv = Sha256::digest(key);
-                Ok(Box::new(
-                    Salsa20::new_from_slices(&iv[0..32], &SALSA20_IV).unwrap(),
-                ))
+                let mut cipher = Salsa20::new_from_slices(&iv[0..32], &SALSA20_IV).unwrap();
+                cipher.seek(offset);
+                Ok(Box::new(cipher))
             }
             _ => Err(InnerStreamError::UnsupportedCipher(self)),
         }
     }
 }
+
+/// Inner stream key kept around to decrypt protected values on demand
+#[derive(PartialEq, Eq)]
+pub(crate) struct InnerStreamKey {
+    algorithm: InnerStreamCipherAlgorithm,
+    key: Vec<u8>,
+}
+
+impl InnerStreamKey {
+    pub(crate) fn new(algorithm: InnerStreamCipherAlgorithm, key: &[u8]) -> InnerStreamKey {
+        InnerStreamKey {
+            algorithm,
+            key: key.to_vec(),
+        }
+    }
+
+    /// Decrypt `data` which starts `offset` bytes into the keystream
+    pub(crate) fn decrypt_at(&self, data: &mut [u8], offset: u64) -> Result<(), InnerStreamError> {
+        let mut cipher = self.algorithm.stream_cipher_at(&self.key, offset)?;
+        cipher.apply_keystream(data);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for InnerStreamKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InnerStreamKey")
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl Drop for InnerStreamKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
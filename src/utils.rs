@@ -49,7 +49,7 @@ where
 impl<'a, I: io::Read> io::Read for CachingReader<'a, I> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let size = self.inner.read(buf)?;
-        self.data.extend(buf.iter().cloned());
+        self.data.extend_from_slice(&buf[..size]);
         Ok(size)
     }
 }
@@ -67,14 +67,49 @@ impl<'a, I: io::Read> CachingReader<'a, I> {
     }
 }
 
-#[allow(dead_code)]
-/// Useful debugging method to convert a byte array to a hex string
+/// Reader that drops null bytes and whitespace before the first other byte
+///
+/// Some generators pad the inner header of a database before the XML starts.
+pub(crate) struct SkipPadding<R> {
+    inner: R,
+    skipping: bool,
+}
+
+impl<R: io::Read> SkipPadding<R> {
+    pub(crate) fn new(inner: R) -> SkipPadding<R> {
+        SkipPadding {
+            inner,
+            skipping: true,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for SkipPadding<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let size = self.inner.read(buf)?;
+            if !self.skipping || size == 0 {
+                return Ok(size);
+            }
+            let start = buf[..size]
+                .iter()
+                .position(|b| *b != 0 && !b.is_ascii_whitespace());
+            if let Some(start) = start {
+                self.skipping = false;
+                buf.copy_within(start..size, 0);
+                return Ok(size - start);
+            }
+        }
+    }
+}
+
+/// Convert a byte array to a hex string
 /// e.g. [0xf2, 0xa2, 0x12] => "f2a212"
 pub(crate) fn to_hex_string(data: &[u8]) -> String {
     let mut output = String::new();
 
     for byte in data {
-        write!(output, "{:x}", byte).unwrap();
+        write!(output, "{:02x}", byte).unwrap();
     }
 
     output
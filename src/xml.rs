@@ -5,6 +5,8 @@ pub(crate) mod parse;
 pub(crate) mod serialize;
 
 pub use crate::stream::random::InnerStreamError;
-pub use decoders::{decode_datetime, decode_uuid, encode_datetime, encode_uuid};
-pub use parse::parse_xml;
-pub use serialize::write_xml;
+pub use decoders::{
+    decode_datetime, decode_uuid, encode_datetime, encode_datetime_iso, encode_uuid,
+};
+pub use parse::{parse_xml, parse_xml_with_options, read_plain_xml, ParseOptions, ParseWarning};
+pub use serialize::{write_plain_xml, write_xml};
@@ -1,3 +1,5 @@
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
 use base64::prelude::{Engine, BASE64_STANDARD};
 use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime};
 use uuid::Uuid;
@@ -9,6 +11,25 @@ pub fn keepass_epoch() -> NaiveDateTime {
         .unwrap()
 }
 
+/// Base64 decoding settings accepting data with or without padding
+const LENIENT_PADDING: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const LENIENT_STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, LENIENT_PADDING);
+const LENIENT_URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, LENIENT_PADDING);
+
+/// Decode base64 data from a Keepass XML file
+///
+/// Whitespace such as line breaks is ignored, and the URL safe alphabet is
+/// accepted along with the standard one, as some clients write wrapped or
+/// URL safe base64.
+pub(crate) fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    LENIENT_STANDARD
+        .decode(&data)
+        .or_else(|_| LENIENT_URL_SAFE.decode(&data))
+        .ok()
+}
+
 /// Decode a UUID from a Keepass XML file
 ///
 /// The UUID in Keepass XML files is stored base 64 encoded
@@ -45,6 +66,13 @@ pub fn encode_uuid(uuid: Uuid) -> String {
     BASE64_STANDARD.encode(uuid.as_bytes())
 }
 
+/// Encode a datetime for a Keepass XML file for kdbx3
+///
+/// KDBX 3 files store datetimes as ISO8601 strings in UTC
+pub fn encode_datetime_iso(date: NaiveDateTime) -> String {
+    date.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
 /// Encode a datetime for a Keepass XML file for kdbx4
 pub fn encode_datetime(date: NaiveDateTime) -> String {
     let epoch_seconds = date.signed_duration_since(keepass_epoch()).num_seconds();
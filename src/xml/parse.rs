@@ -1,13 +1,19 @@
-use super::decoders::{decode_datetime, decode_uuid};
+use super::decoders::{decode_base64, decode_datetime, decode_uuid};
 use crate::database::{
-    Database, Entry, Field, Group, History, MemoryProtection, Meta, Times, Value,
+    Attachment, Binary, CustomDataItem, CustomIcon, Database, DeletedObject, Entry, Field, Group,
+    History, LazyValue, MemoryProtection, Meta, Times, Value, XmlNode,
 };
-use base64::prelude::{Engine, BASE64_STANDARD};
+use crate::stream::random::InnerStreamKey;
+use crate::utils::NullStreamCipher;
 use chrono::NaiveDateTime;
 use cipher::StreamCipher;
+use std::collections::HashMap;
 use std::io::Read;
+use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
 use xml::reader::{EventReader, XmlEvent};
 
 #[derive(Debug, Error)]
@@ -31,10 +37,88 @@ pub enum Error {
     /// A string field did not decrypt correctly
     #[error("Could not decrypt value for Key {0:?}")]
     DecryptFailed(String),
+    /// Binary data is not valid base64
+    #[error("Binary data is not valid base64")]
+    InvalidBase64,
+    /// Compressed attachment data could not be decompressed
+    #[error("Attachment data could not be decompressed")]
+    InvalidCompressedBinary,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Options controlling how database XML is parsed
+pub struct ParseOptions {
+    /// Keep XML comments directly inside the document root, meta section,
+    /// groups and entries so they are written back out on save
+    ///
+    /// Comments are re-emitted at the start of the element containing them.
+    pub preserve_comments: bool,
+    /// Leave protected values encrypted until they are read with [`Field::reveal`]
+    ///
+    /// Only applies when unlocking a database, as it needs the inner stream key.
+    /// Lazily decrypted values return `None` from [`Field::value`].
+    pub lazy_protected: bool,
+}
+
+/// Where protected values get their keystream from while parsing
+pub(crate) enum ProtectedValues<'a, S: StreamCipher + ?Sized> {
+    /// Decrypt each value as it is parsed
+    Eager(&'a mut S),
+    /// Keep values encrypted, recording their position in the keystream
+    Lazy {
+        stream: Arc<InnerStreamKey>,
+        offset: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Recoverable problems found while parsing database XML
+pub enum ParseWarning {
+    /// More than one Meta section was present, only the first was used
+    DuplicateMeta,
+    /// The Root section contained no groups, so an empty root group was created
+    EmptyRoot,
+    /// An attachment referred to binary data that is not in the database, so it was dropped
+    MissingAttachmentData,
+}
+
+/// Binary data attachments may refer to while parsing
+#[derive(Default)]
+struct Binaries {
+    by_id: HashMap<usize, (Arc<Vec<u8>>, bool)>,
+    /// Whether any attachment referred to a binary that does not exist
+    missing: bool,
+}
+
+impl Binaries {
+    fn new(binaries: Vec<Binary>) -> Binaries {
+        let by_id = binaries
+            .into_iter()
+            .map(|binary| (binary.id, (Arc::new(binary.data), binary.protected)))
+            .collect();
+        Binaries {
+            by_id,
+            missing: false,
+        }
+    }
+
+    fn attachment(&mut self, name: String, id: usize) -> Option<Attachment> {
+        match self.by_id.get(&id) {
+            Some((data, protected)) => Some(Attachment {
+                name,
+                data: data.clone(),
+                protected: *protected,
+            }),
+            None => {
+                self.missing = true;
+                None
+            }
+        }
+    }
+}
+
 impl From<xml::reader::Error> for Error {
     fn from(e: xml::reader::Error) -> Error {
         Error::Xml(e.msg().to_string())
@@ -73,6 +157,12 @@ macro_rules! parse_numeric_type {
 }
 
 parse_numeric_type!(parse_u32, u32);
+parse_numeric_type!(parse_i64, i64);
+
+/// Parse a limit where negative values mean unlimited
+fn parse_limit<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Option<usize>> {
+    Ok(parse_i64(xml_event_reader)?.and_then(|limit| usize::try_from(limit).ok()))
+}
 
 fn parse_uuid<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Uuid> {
     parse_string(xml_event_reader)?
@@ -86,6 +176,17 @@ fn parse_datetime<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Naiv
         .ok_or(Error::InvalidDatetime)
 }
 
+/// Parse a boolean that may be `null` to inherit the setting from a parent
+fn parse_optional_bool<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Option<bool>> {
+    Ok(
+        parse_string(xml_event_reader)?.and_then(|b| match b.to_lowercase().as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }),
+    )
+}
+
 fn parse_bool<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<bool> {
     Ok(parse_string(xml_event_reader)?
         .map(|b| b.to_lowercase() == "true")
@@ -95,11 +196,23 @@ fn parse_bool<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<bool> {
 fn parse_field<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
     tag_name: &str,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedValues<'_, S>,
 ) -> Result<Field> {
+    parse_timed_field(xml_event_reader, tag_name, protected_values).map(|(field, _)| field)
+}
+
+fn parse_timed_field<R: Read, S: StreamCipher + ?Sized>(
+    xml_event_reader: &mut EventReader<R>,
+    tag_name: &str,
+    protected_values: &mut ProtectedValues<'_, S>,
+) -> Result<(Field, Option<NaiveDateTime>)> {
     let mut field = Field::default();
+    let mut last_modification_time = None;
     loop {
         match xml_event_reader.next()? {
+            XmlEvent::StartElement { name, .. } if &name.local_name == "LastModificationTime" => {
+                last_modification_time = Some(parse_datetime(xml_event_reader)?);
+            }
             XmlEvent::StartElement { name, .. } if &name.local_name == "Key" => {
                 let parse_result = parse_string(xml_event_reader)?;
                 let val = parse_result.ok_or(Error::KeyEmptyName)?;
@@ -108,15 +221,27 @@ fn parse_field<R: Read, S: StreamCipher + ?Sized>(
             XmlEvent::StartElement {
                 name, attributes, ..
             } if &name.local_name == "Value" => {
-                let protected = attributes.iter().any(|attr| {
-                    attr.name.local_name == "Protected" && attr.value.to_lowercase() == "true"
-                });
+                let is_true = |attr_name: &str| {
+                    attributes.iter().any(|attr| {
+                        attr.name.local_name == attr_name && attr.value.to_lowercase() == "true"
+                    })
+                };
+                let protected = is_true("Protected");
+                // Plain text value that should be protected, as in KeePass XML exports
+                let protect_in_memory = is_true("ProtectInMemory");
                 field.value = if let Some(contents) = parse_string(xml_event_reader)? {
                     if protected {
                         // Would be nice to avoid the clone but it gets moved into the map_err closure
                         let key_clone = field.key.clone();
-                        match BASE64_STANDARD.decode(&contents) {
-                            Ok(mut decoded) => {
+                        match (decode_base64(&contents), &mut *protected_values) {
+                            (Some(decoded), ProtectedValues::Lazy { stream, offset }) => {
+                                let len = decoded.len() as u64;
+                                let value =
+                                    Value::Lazy(LazyValue::new(decoded, *offset, stream.clone()));
+                                *offset += len;
+                                value
+                            }
+                            (Some(mut decoded), ProtectedValues::Eager(stream_cipher)) => {
                                 stream_cipher
                                     .try_apply_keystream(decoded.as_mut())
                                     .map_err(|e| {
@@ -129,8 +254,10 @@ fn parse_field<R: Read, S: StreamCipher + ?Sized>(
                                     .map_err(|_| Error::DecryptFailed(key_clone))?;
                                 Value::Protected(to_str)
                             }
-                            Err(_) => return Err(Error::DecryptFailed(key_clone)),
+                            (None, _) => return Err(Error::DecryptFailed(key_clone)),
                         }
+                    } else if protect_in_memory {
+                        Value::Protected(contents)
                     } else {
                         Value::Standard(contents)
                     }
@@ -142,18 +269,72 @@ fn parse_field<R: Read, S: StreamCipher + ?Sized>(
             _ => {}
         }
     }
-    Ok(field)
+    Ok((field, last_modification_time))
+}
+
+/// Decrypt protected binary data in place with the inner stream
+fn decrypt_binary<S: StreamCipher + ?Sized>(
+    data: &mut [u8],
+    protected_values: &mut ProtectedValues<'_, S>,
+) -> Result<()> {
+    let decrypt_failed = |e: String| Error::DecryptFailed(format!("Binary data: {}", e));
+    match protected_values {
+        ProtectedValues::Eager(stream_cipher) => stream_cipher
+            .try_apply_keystream(data)
+            .map_err(|e| decrypt_failed(e.to_string())),
+        ProtectedValues::Lazy { stream, offset } => {
+            stream
+                .decrypt_at(data, *offset)
+                .map_err(|e| decrypt_failed(e.to_string()))?;
+            *offset += data.len() as u64;
+            Ok(())
+        }
+    }
+}
+
+/// Parse an entry's reference to an attachment, returning its name and binary identifier
+///
+/// The `Ref` attribute is matched case insensitively, and attachments without
+/// a name are named after the binary id.
+fn parse_binary_ref<R: Read>(
+    xml_event_reader: &mut EventReader<R>,
+) -> Result<Option<(String, usize)>> {
+    let mut name = None;
+    let mut id: Option<usize> = None;
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement { name: tag, .. } if &tag.local_name == "Key" => {
+                name = parse_string(xml_event_reader)?;
+            }
+            XmlEvent::StartElement {
+                name: tag,
+                attributes,
+                ..
+            } if &tag.local_name == "Value" => {
+                id = attributes
+                    .iter()
+                    .find(|attr| attr.name.local_name.eq_ignore_ascii_case("Ref"))
+                    .map(|attr| attr.value.parse().map_err(|_| Error::InvalidNumber))
+                    .transpose()?;
+                parse_string(xml_event_reader)?;
+            }
+            XmlEvent::EndElement { name: tag, .. } if &tag.local_name == "Binary" => break,
+            _ => {}
+        }
+    }
+    Ok(id.map(|id| (name.unwrap_or_else(|| id.to_string()), id)))
 }
 
 fn parse_history<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
 ) -> Result<History> {
     let mut history = History::default();
     loop {
         match xml_event_reader.next()? {
             XmlEvent::StartElement { name, .. } if &name.local_name == "Entry" => {
-                history.push(parse_entry(xml_event_reader, stream_cipher)?);
+                history.push(parse_entry(xml_event_reader, protected_values, binaries)?);
             }
             XmlEvent::EndElement { name, .. } if &name.local_name == "History" => break,
             _ => {}
@@ -190,24 +371,66 @@ fn parse_times<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Times>
     Ok(times)
 }
 
+/// Capture an element not understood by this library, so it can be written back out
+fn parse_unknown_element<R: Read>(
+    xml_event_reader: &mut EventReader<R>,
+    name: OwnedName,
+    attributes: Vec<OwnedAttribute>,
+) -> Result<XmlNode> {
+    let mut children = Vec::new();
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => children.push(parse_unknown_element(xml_event_reader, name, attributes)?),
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                children.push(XmlNode::Text(text))
+            }
+            XmlEvent::Comment(comment) => children.push(XmlNode::Comment(comment)),
+            XmlEvent::EndElement { .. } => break,
+            _ => {}
+        }
+    }
+    Ok(XmlNode::Element {
+        name,
+        attributes,
+        children,
+    })
+}
+
 fn parse_entry<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
 ) -> Result<Entry> {
     let mut entry = Entry::default();
     loop {
         match xml_event_reader.next()? {
-            XmlEvent::StartElement { name, .. } => {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
                 if &name.local_name == "History" {
-                    entry.history = parse_history(xml_event_reader, stream_cipher)?;
+                    entry.history = parse_history(xml_event_reader, protected_values, binaries)?;
                 } else if &name.local_name == "String" {
-                    entry.add_field(parse_field(xml_event_reader, "String", stream_cipher)?);
+                    entry.add_field(parse_field(xml_event_reader, "String", protected_values)?);
+                } else if &name.local_name == "Binary" {
+                    if let Some((name, id)) = parse_binary_ref(xml_event_reader)? {
+                        entry.attachments.extend(binaries.attachment(name, id));
+                    }
                 } else if &name.local_name == "UUID" {
                     entry.set_uuid(parse_uuid(xml_event_reader)?);
                 } else if &name.local_name == "Times" {
                     entry.times = parse_times(xml_event_reader)?;
+                } else if &name.local_name == "CustomIconUUID" {
+                    entry.custom_icon_uuid = Some(parse_uuid(xml_event_reader)?);
+                } else if &name.local_name == "CustomData" {
+                    entry.custom_data = parse_custom_data(xml_event_reader, protected_values)?;
+                } else {
+                    let node = parse_unknown_element(xml_event_reader, name, attributes)?;
+                    entry.unknown_xml.push(node);
                 }
             }
+            XmlEvent::Comment(comment) => entry.unknown_xml.push(XmlNode::Comment(comment)),
             XmlEvent::EndElement { name, .. } if &name.local_name == "Entry" => break,
             _ => {}
         }
@@ -217,24 +440,47 @@ fn parse_entry<R: Read, S: StreamCipher + ?Sized>(
 
 fn parse_group<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
 ) -> Result<Group> {
     let mut group = Group::default();
     loop {
         match xml_event_reader.next()? {
-            XmlEvent::StartElement { name, .. } => {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
                 if &name.local_name == "Group" {
-                    group.add_group(parse_group(xml_event_reader, stream_cipher)?);
+                    group.add_group(parse_group(xml_event_reader, protected_values, binaries)?);
                 } else if &name.local_name == "Entry" {
-                    group.add_entry(parse_entry(xml_event_reader, stream_cipher)?);
+                    group.add_entry(parse_entry(xml_event_reader, protected_values, binaries)?);
                 } else if &name.local_name == "UUID" {
                     group.set_uuid(parse_uuid(xml_event_reader)?);
                 } else if &name.local_name == "Name" {
                     group.set_name(parse_string(xml_event_reader)?.unwrap_or_default());
                 } else if &name.local_name == "Times" {
                     group.times = parse_times(xml_event_reader)?;
+                } else if &name.local_name == "CustomIconUUID" {
+                    group.custom_icon_uuid = Some(parse_uuid(xml_event_reader)?);
+                } else if &name.local_name == "CustomData" {
+                    group.custom_data = parse_custom_data(xml_event_reader, protected_values)?;
+                } else if &name.local_name == "IsExpanded" {
+                    group.is_expanded = parse_bool(xml_event_reader)?;
+                } else if &name.local_name == "DefaultAutoTypeSequence" {
+                    group.default_auto_type_sequence =
+                        parse_string(xml_event_reader)?.unwrap_or_default();
+                } else if &name.local_name == "EnableAutoType" {
+                    group.enable_auto_type = parse_optional_bool(xml_event_reader)?;
+                } else if &name.local_name == "EnableSearching" {
+                    group.enable_searching = parse_optional_bool(xml_event_reader)?;
+                } else if &name.local_name == "LastTopVisibleEntry" {
+                    let uuid = parse_uuid(xml_event_reader)?;
+                    group.last_top_visible_entry = if uuid.is_nil() { None } else { Some(uuid) };
+                } else {
+                    let node = parse_unknown_element(xml_event_reader, name, attributes)?;
+                    group.unknown_xml.push(node);
                 }
             }
+            XmlEvent::Comment(comment) => group.unknown_xml.push(XmlNode::Comment(comment)),
             XmlEvent::EndElement { name, .. } if &name.local_name == "Group" => break,
             _ => {}
         }
@@ -242,44 +488,124 @@ fn parse_group<R: Read, S: StreamCipher + ?Sized>(
     Ok(group)
 }
 
+fn parse_deleted_object<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<DeletedObject> {
+    let mut uuid = None;
+    let mut deletion_time = None;
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_ref() {
+                "UUID" => uuid = Some(parse_uuid(xml_event_reader)?),
+                "DeletionTime" => deletion_time = Some(parse_datetime(xml_event_reader)?),
+                _ => {}
+            },
+            XmlEvent::EndElement { name, .. } if &name.local_name == "DeletedObject" => break,
+            _ => {}
+        }
+    }
+    Ok(DeletedObject {
+        uuid: uuid.ok_or(Error::InvalidUuid)?,
+        deletion_time: deletion_time.ok_or(Error::InvalidDatetime)?,
+    })
+}
+
+fn parse_deleted_objects<R: Read>(
+    xml_event_reader: &mut EventReader<R>,
+) -> Result<Vec<DeletedObject>> {
+    let mut deleted = Vec::new();
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement { name, .. } if &name.local_name == "DeletedObject" => {
+                deleted.push(parse_deleted_object(xml_event_reader)?);
+            }
+            XmlEvent::EndElement { name, .. } if &name.local_name == "DeletedObjects" => break,
+            _ => {}
+        }
+    }
+    Ok(deleted)
+}
+
 fn parse_root<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
-) -> Result<Vec<Group>> {
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
+) -> Result<(Vec<Group>, Vec<DeletedObject>)> {
     let mut groups = Vec::new();
+    let mut deleted = Vec::new();
     loop {
         match xml_event_reader.next()? {
             XmlEvent::StartElement { name, .. } if &name.local_name == "Group" => {
-                groups.push(parse_group(xml_event_reader, stream_cipher)?);
+                groups.push(parse_group(xml_event_reader, protected_values, binaries)?);
+            }
+            XmlEvent::StartElement { name, .. } if &name.local_name == "DeletedObjects" => {
+                deleted.extend(parse_deleted_objects(xml_event_reader)?);
             }
             XmlEvent::EndElement { name, .. } if &name.local_name == "Root" => break,
             _ => {}
         }
     }
-    Ok(groups)
+    Ok((groups, deleted))
 }
 
 fn parse_custom_data<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
-) -> Result<Vec<Field>> {
-    let mut fields = Vec::new();
+    protected_values: &mut ProtectedValues<'_, S>,
+) -> Result<Vec<CustomDataItem>> {
+    let mut items = Vec::new();
     loop {
         match xml_event_reader.next()? {
             XmlEvent::StartElement { name, .. } if &name.local_name == "Item" => {
-                fields.push(parse_field(xml_event_reader, "Item", stream_cipher)?);
+                let (field, last_modification_time) =
+                    parse_timed_field(xml_event_reader, "Item", protected_values)?;
+                items.push(CustomDataItem {
+                    field,
+                    last_modification_time,
+                });
             }
             XmlEvent::EndElement { name, .. } if &name.local_name == "CustomData" => break,
             _ => {}
         }
     }
-    Ok(fields)
+    Ok(items)
+}
+
+fn parse_custom_icon<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Option<CustomIcon>> {
+    let mut uuid = None;
+    let mut data = None;
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement { name, .. } if &name.local_name == "UUID" => {
+                uuid = Some(parse_uuid(xml_event_reader)?);
+            }
+            XmlEvent::StartElement { name, .. } if &name.local_name == "Data" => {
+                data = parse_string(xml_event_reader)?
+                    .map(|data| decode_base64(&data).ok_or(Error::InvalidBase64))
+                    .transpose()?;
+            }
+            XmlEvent::EndElement { name, .. } if &name.local_name == "Icon" => break,
+            _ => {}
+        }
+    }
+    Ok(uuid.zip(data).map(|(uuid, data)| CustomIcon { uuid, data }))
+}
+
+fn parse_custom_icons<R: Read>(xml_event_reader: &mut EventReader<R>) -> Result<Vec<CustomIcon>> {
+    let mut icons = Vec::new();
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement { name, .. } if &name.local_name == "Icon" => {
+                icons.extend(parse_custom_icon(xml_event_reader)?);
+            }
+            XmlEvent::EndElement { name, .. } if &name.local_name == "CustomIcons" => break,
+            _ => {}
+        }
+    }
+    Ok(icons)
 }
 
 fn parse_memory_protection<R: Read>(
     xml_event_reader: &mut EventReader<R>,
 ) -> Result<MemoryProtection> {
-    let mut protection = MemoryProtection::default();
+    let mut protection = MemoryProtection::keepass_defaults();
     loop {
         match xml_event_reader.next()? {
             XmlEvent::StartElement { name, .. } => match name.local_name.as_ref() {
@@ -307,14 +633,70 @@ fn parse_memory_protection<R: Read>(
     Ok(protection)
 }
 
+/// Parse binary data stored in the meta section, as in KDBX 3.1 files
+fn parse_meta_binaries<R: Read, S: StreamCipher + ?Sized>(
+    xml_event_reader: &mut EventReader<R>,
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
+) -> Result<()> {
+    loop {
+        match xml_event_reader.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if &name.local_name == "Binary" => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == key)
+                        .map(|attr| attr.value.clone())
+                };
+                let is_true = |key: &str| {
+                    attribute(key)
+                        .map(|value| value.to_lowercase() == "true")
+                        .unwrap_or_default()
+                };
+                let id = attribute("ID")
+                    .map(|id| id.parse::<usize>().map_err(|_| Error::InvalidNumber))
+                    .transpose()?;
+                let (compressed, protected) = (is_true("Compressed"), is_true("Protected"));
+                let mut data = decode_base64(&parse_string(xml_event_reader)?.unwrap_or_default())
+                    .ok_or(Error::InvalidBase64)?;
+                if protected {
+                    decrypt_binary(&mut data, protected_values)?;
+                }
+                if compressed {
+                    let mut decompressed = Vec::new();
+                    libflate::gzip::Decoder::new(&*data)
+                        .and_then(|mut decoder| decoder.read_to_end(&mut decompressed))
+                        .map_err(|_| Error::InvalidCompressedBinary)?;
+                    data = decompressed;
+                }
+                if let Some(id) = id {
+                    binaries.by_id.insert(id, (Arc::new(data), protected));
+                }
+            }
+            XmlEvent::EndElement { name, .. } if &name.local_name == "Binaries" => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 fn parse_meta<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
+    unknown_xml: &mut Vec<XmlNode>,
 ) -> Result<Meta> {
-    let mut meta = Meta::default();
+    let mut meta = Meta {
+        memory_protection: MemoryProtection::keepass_defaults(),
+        ..Meta::default()
+    };
     loop {
         match xml_event_reader.next()? {
-            XmlEvent::StartElement { name, .. } => match name.local_name.as_ref() {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_ref() {
                 "Generator" => {
                     meta.generator = parse_string(xml_event_reader)?.unwrap_or_default();
                 }
@@ -324,14 +706,59 @@ fn parse_meta<R: Read, S: StreamCipher + ?Sized>(
                 "DatabaseDescription" => {
                     meta.database_description = parse_string(xml_event_reader)?.unwrap_or_default();
                 }
+                "DatabaseNameChanged" => {
+                    meta.database_name_changed = Some(parse_datetime(xml_event_reader)?);
+                }
+                "DatabaseDescriptionChanged" => {
+                    meta.database_description_changed = Some(parse_datetime(xml_event_reader)?);
+                }
+                "DefaultUserName" => {
+                    meta.default_username = parse_string(xml_event_reader)?.unwrap_or_default();
+                }
+                "DefaultUserNameChanged" => {
+                    meta.default_user_name_changed = Some(parse_datetime(xml_event_reader)?);
+                }
+                "MasterKeyChanged" => {
+                    meta.master_key_changed = Some(parse_datetime(xml_event_reader)?);
+                }
+                "SettingsChanged" => {
+                    meta.settings_changed = Some(parse_datetime(xml_event_reader)?);
+                }
                 "CustomData" => {
-                    meta.custom_data = parse_custom_data(xml_event_reader, stream_cipher)?;
+                    meta.custom_data = parse_custom_data(xml_event_reader, protected_values)?;
                 }
                 "MemoryProtection" => {
                     meta.memory_protection = parse_memory_protection(xml_event_reader)?;
                 }
-                _ => {}
+                "CustomIcons" => {
+                    meta.custom_icons = parse_custom_icons(xml_event_reader)?;
+                }
+                "RecycleBinEnabled" => {
+                    meta.recycle_bin_enabled = parse_bool(xml_event_reader)?;
+                }
+                "RecycleBinUUID" => {
+                    let uuid = parse_uuid(xml_event_reader)?;
+                    meta.recycle_bin_uuid = if uuid.is_nil() { None } else { Some(uuid) };
+                }
+                "RecycleBinChanged" => {
+                    meta.recycle_bin_changed = parse_datetime(xml_event_reader)?;
+                }
+                "HistoryMaxItems" => {
+                    meta.history_max_items = parse_limit(xml_event_reader)?;
+                }
+                "HistoryMaxSize" => {
+                    meta.history_max_size = parse_limit(xml_event_reader)?;
+                }
+                "Binaries" => {
+                    parse_meta_binaries(xml_event_reader, protected_values, binaries)?;
+                }
+                "HeaderHash" => {
+                    // Only valid for the header the file was read with
+                    parse_string(xml_event_reader)?;
+                }
+                _ => unknown_xml.push(parse_unknown_element(xml_event_reader, name, attributes)?),
             },
+            XmlEvent::Comment(comment) => unknown_xml.push(XmlNode::Comment(comment)),
             XmlEvent::EndElement { name, .. } if &name.local_name == "Meta" => break,
             _ => {}
         }
@@ -341,21 +768,48 @@ fn parse_meta<R: Read, S: StreamCipher + ?Sized>(
 
 fn parse_file<R: Read, S: StreamCipher + ?Sized>(
     xml_event_reader: &mut EventReader<R>,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedValues<'_, S>,
+    binaries: &mut Binaries,
 ) -> Result<Database> {
     let mut db = Database::default();
+    let mut seen_meta = false;
     loop {
         match xml_event_reader.next()? {
             XmlEvent::StartElement { name, .. } if &name.local_name == "Root" => {
-                db.groups = parse_root(xml_event_reader, stream_cipher)?;
+                let (groups, deleted_objects) =
+                    parse_root(xml_event_reader, protected_values, binaries)?;
+                db.groups = groups;
+                db.deleted_objects = deleted_objects;
+                if db.groups.is_empty() {
+                    db.groups.push(Group::new("Root"));
+                    db.parse_warnings.push(ParseWarning::EmptyRoot);
+                }
             }
             XmlEvent::StartElement { name, .. } if &name.local_name == "Meta" => {
-                db.meta = parse_meta(xml_event_reader, stream_cipher)?;
+                // Later Meta sections are still parsed to keep protected values in order
+                let mut unknown_xml = Vec::new();
+                let meta = parse_meta(
+                    xml_event_reader,
+                    protected_values,
+                    binaries,
+                    &mut unknown_xml,
+                )?;
+                if seen_meta {
+                    db.parse_warnings.push(ParseWarning::DuplicateMeta);
+                } else {
+                    db.meta = meta;
+                    db.meta_unknown_xml = unknown_xml;
+                    seen_meta = true;
+                }
             }
+            XmlEvent::Comment(comment) => db.unknown_xml.push(XmlNode::Comment(comment)),
             XmlEvent::EndElement { name, .. } if &name.local_name == "KeePassFile" => break,
             _ => {}
         }
     }
+    if binaries.missing {
+        db.parse_warnings.push(ParseWarning::MissingAttachmentData);
+    }
     Ok(db)
 }
 
@@ -368,10 +822,52 @@ fn parse_file<R: Read, S: StreamCipher + ?Sized>(
 pub fn parse_xml<R: Read, S: StreamCipher + ?Sized>(
     xml_data: R,
     stream_cipher: &mut S,
+) -> Result<Database> {
+    parse_xml_with_options(xml_data, stream_cipher, &ParseOptions::default())
+}
+
+/// Parse an unencrypted XML export, such as from KeePass or [`write_plain_xml`]
+///
+/// Values marked with `ProtectInMemory="True"` are read as protected values.
+///
+/// [`write_plain_xml`]: crate::xml::write_plain_xml
+pub fn read_plain_xml<R: Read>(xml_data: R) -> Result<Database> {
+    parse_xml(xml_data, &mut NullStreamCipher)
+}
+
+/// Parse decrypted XML into a database, with control over what is preserved
+///
+/// See [`parse_xml`] for how to obtain a stream cipher. Protected values are
+/// always decrypted eagerly here, [`ParseOptions::lazy_protected`] is ignored.
+pub fn parse_xml_with_options<R: Read, S: StreamCipher + ?Sized>(
+    xml_data: R,
+    stream_cipher: &mut S,
+    options: &ParseOptions,
+) -> Result<Database> {
+    parse_xml_protected(
+        xml_data,
+        &mut ProtectedValues::Eager(stream_cipher),
+        options,
+        Vec::new(),
+    )
+}
+
+/// Parse decrypted XML, with attachments able to refer to `binaries` from outside the XML
+pub(crate) fn parse_xml_protected<R: Read, S: StreamCipher + ?Sized>(
+    xml_data: R,
+    protected_values: &mut ProtectedValues<'_, S>,
+    options: &ParseOptions,
+    binaries: Vec<Binary>,
 ) -> Result<Database> {
     let xml_config = xml::ParserConfig::new()
         .trim_whitespace(true)
-        .cdata_to_characters(true);
+        .cdata_to_characters(true)
+        .ignore_comments(!options.preserve_comments);
+    let xml_data = crate::utils::SkipPadding::new(xml_data);
     let mut xml_event_reader = EventReader::new_with_config(xml_data, xml_config);
-    parse_file(&mut xml_event_reader, stream_cipher)
+    parse_file(
+        &mut xml_event_reader,
+        protected_values,
+        &mut Binaries::new(binaries),
+    )
 }
@@ -1,11 +1,18 @@
-use super::decoders::{encode_datetime, encode_uuid};
-use crate::database::{Database, Entry, Field, Group, MemoryProtection, Meta, Times, Value};
+use super::decoders::{encode_datetime, encode_datetime_iso, encode_uuid};
+use crate::database::{
+    Attachment, BinaryPool, CustomDataItem, CustomIcon, Database, DeletedObject, Entry, Field,
+    Group, MemoryProtection, Meta, Times, Value, XmlNode,
+};
+use crate::utils::NullStreamCipher;
 use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::NaiveDateTime;
 use cipher::StreamCipher;
 use std::io::Write;
 use thiserror::Error;
+use uuid::Uuid;
 use xml::writer::events::XmlEvent;
 use xml::writer::EventWriter as XmlWriter;
+use zeroize::Zeroizing;
 
 #[derive(Debug, Error)]
 /// Failures to write an XML file
@@ -20,6 +27,14 @@ pub enum Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// How protected values are written
+enum ProtectedOutput<'a, S: StreamCipher + ?Sized> {
+    /// Encrypt each value with the inner stream cipher
+    Encrypted(&'a mut S),
+    /// Write values in plain text, as in a KeePass XML export
+    Plain,
+}
+
 fn write_bool_tag<W: Write>(writer: &mut XmlWriter<W>, name: &str, value: bool) -> Result<()> {
     writer.write(XmlEvent::start_element(name))?;
     writer.write(XmlEvent::characters(if value { "True" } else { "False" }))?;
@@ -27,13 +42,62 @@ fn write_bool_tag<W: Write>(writer: &mut XmlWriter<W>, name: &str, value: bool)
     Ok(())
 }
 
+/// Write a boolean, using `null` for unset
+fn write_optional_bool_tag<W: Write>(
+    writer: &mut XmlWriter<W>,
+    name: &str,
+    value: Option<bool>,
+) -> Result<()> {
+    match value {
+        Some(value) => write_bool_tag(writer, name, value),
+        None => write_string_tag(writer, name, "null"),
+    }
+}
+
+/// Format a datetime as ISO 8601 for KDBX 3 or base64 epoch seconds for KDBX 4
+fn format_datetime(date: NaiveDateTime, major_version: u16) -> String {
+    if major_version < 4 {
+        encode_datetime_iso(date)
+    } else {
+        encode_datetime(date)
+    }
+}
+
+/// Write a limit, using -1 for unlimited
+fn write_limit_tag<W: Write>(
+    writer: &mut XmlWriter<W>,
+    name: &str,
+    limit: Option<usize>,
+) -> Result<()> {
+    match limit {
+        Some(limit) => write_string_tag(writer, name, limit.to_string()),
+        None => write_string_tag(writer, name, "-1"),
+    }
+}
+
+/// Write a time, leaving the tag out if it is unset
+fn write_optional_datetime_tag<W: Write>(
+    writer: &mut XmlWriter<W>,
+    major_version: u16,
+    name: &str,
+    time: Option<NaiveDateTime>,
+) -> Result<()> {
+    match time {
+        Some(time) => write_string_tag(writer, name, format_datetime(time, major_version)),
+        None => Ok(()),
+    }
+}
+
 fn write_string_tag<W: Write, S: AsRef<str>>(
     writer: &mut XmlWriter<W>,
     name: &str,
     value: S,
 ) -> Result<()> {
     writer.write(XmlEvent::start_element(name))?;
-    writer.write(XmlEvent::characters(value.as_ref()))?;
+    // Empty values are written as an empty element, as KeePass does
+    if !value.as_ref().is_empty() {
+        writer.write(XmlEvent::characters(value.as_ref()))?;
+    }
     writer.write(XmlEvent::end_element())?;
     Ok(())
 }
@@ -42,19 +106,43 @@ fn write_field<W: Write, S: StreamCipher + ?Sized>(
     writer: &mut XmlWriter<W>,
     wrapper: &str,
     field: &Field,
-    stream_cipher: &mut S,
+    protected_values: &mut ProtectedOutput<'_, S>,
 ) -> Result<()> {
     writer.write(XmlEvent::start_element(wrapper))?;
+    write_field_contents(writer, field, protected_values)?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn write_field_contents<W: Write, S: StreamCipher + ?Sized>(
+    writer: &mut XmlWriter<W>,
+    field: &Field,
+    protected_values: &mut ProtectedOutput<'_, S>,
+) -> Result<()> {
     write_string_tag(writer, "Key", &field.key)?;
     match &field.value {
-        Value::Protected(v) => {
-            writer.write(XmlEvent::start_element("Value").attr("Protected", "True"))?;
-            let mut encrypt_buf = v.clone().into_bytes();
-            stream_cipher
-                .try_apply_keystream(&mut encrypt_buf)
-                .map_err(|e| Error::Cipher(format!("Encryption cipher failed: {}", e)))?;
-            let encrypted = BASE64_STANDARD.encode(&encrypt_buf);
-            writer.write(XmlEvent::characters(&encrypted))?;
+        Value::Protected(_) | Value::Lazy(_) => {
+            let plain = field
+                .reveal()
+                .ok_or_else(|| Error::Cipher(format!("Could not decrypt field {}", field.key)))?;
+            match protected_values {
+                ProtectedOutput::Encrypted(stream_cipher) => {
+                    writer.write(XmlEvent::start_element("Value").attr("Protected", "True"))?;
+                    let mut encrypt_buf = plain.into_owned().into_bytes();
+                    stream_cipher
+                        .try_apply_keystream(&mut encrypt_buf)
+                        .map_err(|e| Error::Cipher(format!("Encryption cipher failed: {}", e)))?;
+                    let encrypted = BASE64_STANDARD.encode(&encrypt_buf);
+                    writer.write(XmlEvent::characters(&encrypted))?;
+                }
+                ProtectedOutput::Plain => {
+                    writer
+                        .write(XmlEvent::start_element("Value").attr("ProtectInMemory", "True"))?;
+                    if !plain.is_empty() {
+                        writer.write(XmlEvent::characters(&plain))?;
+                    }
+                }
+            }
             writer.write(XmlEvent::end_element())?;
         }
         Value::Standard(v) => write_string_tag(writer, "Value", v)?,
@@ -63,10 +151,85 @@ fn write_field<W: Write, S: StreamCipher + ?Sized>(
             writer.write(XmlEvent::end_element())?;
         }
     }
+    Ok(())
+}
+
+fn write_custom_data_item<W: Write, S: StreamCipher + ?Sized>(
+    writer: &mut XmlWriter<W>,
+    major_version: u16,
+    item: &CustomDataItem,
+    protected_values: &mut ProtectedOutput<'_, S>,
+) -> Result<()> {
+    writer.write(XmlEvent::start_element("Item"))?;
+    write_field_contents(writer, &item.field, protected_values)?;
+    if let Some(time) = item.last_modification_time {
+        write_string_tag(
+            writer,
+            "LastModificationTime",
+            format_datetime(time, major_version),
+        )?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn write_custom_data<W: Write, S: StreamCipher + ?Sized>(
+    writer: &mut XmlWriter<W>,
+    major_version: u16,
+    items: &[CustomDataItem],
+    protected_values: &mut ProtectedOutput<'_, S>,
+) -> Result<()> {
+    writer.write(XmlEvent::start_element("CustomData"))?;
+    for item in items {
+        write_custom_data_item(writer, major_version, item, protected_values)?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn write_deleted_objects<W: Write>(
+    writer: &mut XmlWriter<W>,
+    major_version: u16,
+    deleted_objects: &[DeletedObject],
+) -> Result<()> {
+    writer.write(XmlEvent::start_element("DeletedObjects"))?;
+    for deleted in deleted_objects {
+        writer.write(XmlEvent::start_element("DeletedObject"))?;
+        write_string_tag(writer, "UUID", encode_uuid(deleted.uuid))?;
+        write_string_tag(
+            writer,
+            "DeletionTime",
+            format_datetime(deleted.deletion_time, major_version),
+        )?;
+        writer.write(XmlEvent::end_element())?;
+    }
     writer.write(XmlEvent::end_element())?;
     Ok(())
 }
 
+fn write_unknown_xml<W: Write>(writer: &mut XmlWriter<W>, nodes: &[XmlNode]) -> Result<()> {
+    for node in nodes {
+        match node {
+            XmlNode::Comment(comment) => writer.write(XmlEvent::comment(comment))?,
+            XmlNode::Element {
+                name,
+                attributes,
+                children,
+            } => {
+                let mut start = XmlEvent::start_element(name.borrow());
+                for attribute in attributes {
+                    start = start.attr(attribute.name.borrow(), &attribute.value);
+                }
+                writer.write(start)?;
+                write_unknown_xml(writer, children)?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            XmlNode::Text(text) => writer.write(XmlEvent::characters(text))?,
+        }
+    }
+    Ok(())
+}
+
 fn write_memory_protection<W: Write>(
     writer: &mut XmlWriter<W>,
     protection: &MemoryProtection,
@@ -81,65 +244,204 @@ fn write_memory_protection<W: Write>(
     Ok(())
 }
 
+fn write_custom_icons<W: Write>(writer: &mut XmlWriter<W>, icons: &[CustomIcon]) -> Result<()> {
+    writer.write(XmlEvent::start_element("CustomIcons"))?;
+    for icon in icons {
+        writer.write(XmlEvent::start_element("Icon"))?;
+        write_string_tag(writer, "UUID", encode_uuid(icon.uuid))?;
+        write_string_tag(writer, "Data", BASE64_STANDARD.encode(&icon.data))?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// Write the KDBX 3.1 binary pool, encrypting protected binaries with the inner stream cipher
+fn write_meta_binaries<W: Write, S: StreamCipher + ?Sized>(
+    writer: &mut XmlWriter<W>,
+    pool: &BinaryPool,
+    protected_values: &mut ProtectedOutput<'_, S>,
+) -> Result<()> {
+    writer.write(XmlEvent::start_element("Binaries"))?;
+    for (id, (data, protected)) in pool.iter().enumerate() {
+        let id = id.to_string();
+        match protected_values {
+            ProtectedOutput::Encrypted(stream_cipher) if protected => {
+                writer.write(
+                    XmlEvent::start_element("Binary")
+                        .attr("ID", &id)
+                        .attr("Protected", "True"),
+                )?;
+                let mut encrypt_buf = Zeroizing::new(data.to_vec());
+                stream_cipher
+                    .try_apply_keystream(&mut encrypt_buf)
+                    .map_err(|e| Error::Cipher(format!("Encryption cipher failed: {}", e)))?;
+                writer.write(XmlEvent::characters(&BASE64_STANDARD.encode(&*encrypt_buf)))?;
+            }
+            _ => {
+                writer.write(XmlEvent::start_element("Binary").attr("ID", &id))?;
+                writer.write(XmlEvent::characters(&BASE64_STANDARD.encode(data)))?;
+            }
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
 fn write_meta<W: Write, S: StreamCipher + ?Sized>(
     writer: &mut XmlWriter<W>,
+    major_version: u16,
     meta: &Meta,
-    stream_cipher: &mut S,
+    unknown_xml: &[XmlNode],
+    meta_binaries: Option<&BinaryPool>,
+    protected_values: &mut ProtectedOutput<'_, S>,
 ) -> Result<()> {
     writer.write(XmlEvent::start_element("Meta"))?;
-    write_string_tag(writer, "Generator", "kdbx-rs")?;
+    write_unknown_xml(writer, unknown_xml)?;
+    let generator = if meta.generator.is_empty() {
+        "kdbx-rs"
+    } else {
+        &meta.generator
+    };
+    write_string_tag(writer, "Generator", generator)?;
+    write_optional_datetime_tag(
+        writer,
+        major_version,
+        "SettingsChanged",
+        meta.settings_changed,
+    )?;
     write_string_tag(writer, "DatabaseName", &meta.database_name)?;
+    write_optional_datetime_tag(
+        writer,
+        major_version,
+        "DatabaseNameChanged",
+        meta.database_name_changed,
+    )?;
     write_string_tag(writer, "DatabaseDescription", &meta.database_description)?;
-    writer.write(XmlEvent::start_element("CustomData"))?;
-    for field in &meta.custom_data {
-        write_field(writer, "Item", field, stream_cipher)?;
-    }
-    writer.write(XmlEvent::end_element())?;
+    write_optional_datetime_tag(
+        writer,
+        major_version,
+        "DatabaseDescriptionChanged",
+        meta.database_description_changed,
+    )?;
+    write_string_tag(writer, "DefaultUserName", &meta.default_username)?;
+    write_optional_datetime_tag(
+        writer,
+        major_version,
+        "DefaultUserNameChanged",
+        meta.default_user_name_changed,
+    )?;
+    write_optional_datetime_tag(
+        writer,
+        major_version,
+        "MasterKeyChanged",
+        meta.master_key_changed,
+    )?;
+    write_custom_data(writer, major_version, &meta.custom_data, protected_values)?;
     write_memory_protection(writer, &meta.memory_protection)?;
+    if !meta.custom_icons.is_empty() {
+        write_custom_icons(writer, &meta.custom_icons)?;
+    }
+    write_bool_tag(writer, "RecycleBinEnabled", meta.recycle_bin_enabled)?;
+    write_string_tag(
+        writer,
+        "RecycleBinUUID",
+        encode_uuid(meta.recycle_bin_uuid.unwrap_or_else(Uuid::nil)),
+    )?;
+    write_string_tag(
+        writer,
+        "RecycleBinChanged",
+        format_datetime(meta.recycle_bin_changed, major_version),
+    )?;
+    write_limit_tag(writer, "HistoryMaxItems", meta.history_max_items)?;
+    write_limit_tag(writer, "HistoryMaxSize", meta.history_max_size)?;
+    if let Some(pool) = meta_binaries.filter(|pool| !pool.is_empty()) {
+        write_meta_binaries(writer, pool, protected_values)?;
+    }
     writer.write(XmlEvent::end_element())?;
     Ok(())
 }
 
-fn write_times<W: Write>(writer: &mut XmlWriter<W>, times: &Times) -> Result<()> {
+fn write_times<W: Write>(
+    writer: &mut XmlWriter<W>,
+    major_version: u16,
+    times: &Times,
+) -> Result<()> {
     writer.write(XmlEvent::start_element("Times"))?;
     write_string_tag(
         writer,
         "LastModificationTime",
-        encode_datetime(times.last_modification_time),
+        format_datetime(times.last_modification_time, major_version),
+    )?;
+    write_string_tag(
+        writer,
+        "CreationTime",
+        format_datetime(times.creation_time, major_version),
     )?;
-    write_string_tag(writer, "CreationTime", encode_datetime(times.creation_time))?;
     write_string_tag(
         writer,
         "LastAccessTime",
-        encode_datetime(times.last_access_time),
+        format_datetime(times.last_access_time, major_version),
     )?;
     write_string_tag(
         writer,
         "LocationChanged",
-        encode_datetime(times.location_changed),
+        format_datetime(times.location_changed, major_version),
+    )?;
+    write_string_tag(
+        writer,
+        "ExpiryTime",
+        format_datetime(times.expiry_time, major_version),
     )?;
-    write_string_tag(writer, "ExpiryTime", encode_datetime(times.expiry_time))?;
     write_string_tag(writer, "UsageCount", times.usage_count.to_string())?;
     write_bool_tag(writer, "Expires", times.expires)?;
     writer.write(XmlEvent::end_element())?;
     Ok(())
 }
 
+fn write_attachment<W: Write>(
+    writer: &mut XmlWriter<W>,
+    attachment: &Attachment,
+    pool: &BinaryPool,
+) -> Result<()> {
+    // Every attachment in the database is added to the pool before writing
+    let id = pool.id(attachment).unwrap_or_default();
+    writer.write(XmlEvent::start_element("Binary"))?;
+    write_string_tag(writer, "Key", &attachment.name)?;
+    writer.write(XmlEvent::start_element("Value").attr("Ref", &id.to_string()))?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
 fn write_entry<W: Write, S: StreamCipher + ?Sized>(
     writer: &mut XmlWriter<W>,
+    major_version: u16,
     entry: &Entry,
-    stream_cipher: &mut S,
+    pool: &BinaryPool,
+    protected_values: &mut ProtectedOutput<'_, S>,
 ) -> Result<()> {
     writer.write(XmlEvent::start_element("Entry"))?;
-    write_string_tag(writer, "UUID", &encode_uuid(entry.uuid()))?;
-    write_times(writer, &entry.times)?;
+    write_unknown_xml(writer, &entry.unknown_xml)?;
+    write_string_tag(writer, "UUID", encode_uuid(entry.uuid()))?;
+    if let Some(icon) = entry.custom_icon_uuid() {
+        write_string_tag(writer, "CustomIconUUID", encode_uuid(icon))?;
+    }
+    write_times(writer, major_version, &entry.times)?;
     for field in entry.fields() {
-        write_field(writer, "String", field, stream_cipher)?;
+        write_field(writer, "String", field, protected_values)?;
+    }
+    for attachment in entry.attachments() {
+        write_attachment(writer, attachment, pool)?;
+    }
+    if !entry.custom_data.is_empty() {
+        write_custom_data(writer, major_version, &entry.custom_data, protected_values)?;
     }
     if !entry.history.is_empty() {
         writer.write(XmlEvent::start_element("History"))?;
         for old_entry in entry.history.entries() {
-            write_entry(writer, old_entry, stream_cipher)?;
+            write_entry(writer, major_version, old_entry, pool, protected_values)?;
         }
         writer.write(XmlEvent::end_element())?;
     }
@@ -149,18 +451,40 @@ fn write_entry<W: Write, S: StreamCipher + ?Sized>(
 
 fn write_group<W: Write, S: StreamCipher + ?Sized>(
     writer: &mut XmlWriter<W>,
+    major_version: u16,
     group: &Group,
-    stream_cipher: &mut S,
+    pool: &BinaryPool,
+    protected_values: &mut ProtectedOutput<'_, S>,
 ) -> Result<()> {
     writer.write(XmlEvent::start_element("Group"))?;
+    write_unknown_xml(writer, &group.unknown_xml)?;
     write_string_tag(writer, "UUID", encode_uuid(group.uuid()))?;
     write_string_tag(writer, "Name", group.name())?;
-    write_times(writer, &group.times)?;
+    if let Some(icon) = group.custom_icon_uuid() {
+        write_string_tag(writer, "CustomIconUUID", encode_uuid(icon))?;
+    }
+    write_times(writer, major_version, &group.times)?;
+    write_bool_tag(writer, "IsExpanded", group.is_expanded)?;
+    write_string_tag(
+        writer,
+        "DefaultAutoTypeSequence",
+        &group.default_auto_type_sequence,
+    )?;
+    write_optional_bool_tag(writer, "EnableAutoType", group.enable_auto_type)?;
+    write_optional_bool_tag(writer, "EnableSearching", group.enable_searching)?;
+    write_string_tag(
+        writer,
+        "LastTopVisibleEntry",
+        encode_uuid(group.last_top_visible_entry.unwrap_or_else(Uuid::nil)),
+    )?;
+    if !group.custom_data.is_empty() {
+        write_custom_data(writer, major_version, &group.custom_data, protected_values)?;
+    }
     for entry in group.entries() {
-        write_entry(writer, entry, stream_cipher)?;
+        write_entry(writer, major_version, entry, pool, protected_values)?;
     }
     for group in group.groups() {
-        write_group(writer, group, stream_cipher)?;
+        write_group(writer, major_version, group, pool, protected_values)?;
     }
     writer.write(XmlEvent::end_element())?;
     Ok(())
@@ -172,20 +496,88 @@ fn write_group<W: Write, S: StreamCipher + ?Sized>(
 /// [`InnerStreamCipherAlgorithm::stream_cipher`][crate::binary::InnerStreamCipherAlgorithm#stream_cipher]
 /// if the XML contains encrypted data, or [`utils::NullStreamCipher`][crate::utils::NullStreamCipher]
 /// if it does not (such as an export from the official client).
+///
+/// Attachment data is written to the meta section, as in KDBX 3.1 files.
 pub fn write_xml<W: Write, S: StreamCipher + ?Sized>(
     output: W,
     database: &Database,
     stream_cipher: &mut S,
+) -> Result<()> {
+    let pool = BinaryPool::new(database);
+    write_xml_with_binaries(output, database, stream_cipher, &pool, true, 4)
+}
+
+/// Write the decrypted XML for a database without encrypting protected values
+///
+/// **This writes passwords and other secrets in the clear.** Protected values
+/// are written as plain text marked with `ProtectInMemory="True"`, as in an
+/// XML export from KeePass.
+///
+/// Attachment data is written to the meta section and datetimes as ISO 8601,
+/// as in KDBX 3.1 files and KeePass XML exports.
+pub fn write_plain_xml<W: Write>(output: W, database: &Database) -> Result<()> {
+    let pool = BinaryPool::new(database);
+    write_document(
+        output,
+        database,
+        &mut ProtectedOutput::<NullStreamCipher>::Plain,
+        &pool,
+        true,
+        3,
+    )
+}
+
+/// Write the decrypted XML for a database, numbering attachments from `pool`
+///
+/// The binaries are only written to the meta section if `meta_binaries` is
+/// set, otherwise they are expected to be stored elsewhere, such as the KDBX 4 inner header.
+/// Datetimes are written in the format used by the `major_version` of the KDBX format.
+pub(crate) fn write_xml_with_binaries<W: Write, S: StreamCipher + ?Sized>(
+    output: W,
+    database: &Database,
+    stream_cipher: &mut S,
+    pool: &BinaryPool,
+    meta_binaries: bool,
+    major_version: u16,
+) -> Result<()> {
+    write_document(
+        output,
+        database,
+        &mut ProtectedOutput::Encrypted(stream_cipher),
+        pool,
+        meta_binaries,
+        major_version,
+    )
+}
+
+fn write_document<W: Write, S: StreamCipher + ?Sized>(
+    output: W,
+    database: &Database,
+    protected_values: &mut ProtectedOutput<'_, S>,
+    pool: &BinaryPool,
+    meta_binaries: bool,
+    major_version: u16,
 ) -> Result<()> {
     let config = xml::EmitterConfig::default()
         .perform_indent(true)
         .indent_string("\t");
     let mut writer = xml::EventWriter::new_with_config(output, config);
     writer.write(XmlEvent::start_element("KeePassFile"))?;
-    write_meta(&mut writer, &database.meta, stream_cipher)?;
+    write_unknown_xml(&mut writer, &database.unknown_xml)?;
+    write_meta(
+        &mut writer,
+        major_version,
+        &database.meta,
+        &database.meta_unknown_xml,
+        Some(pool).filter(|_| meta_binaries),
+        protected_values,
+    )?;
     writer.write(XmlEvent::start_element("Root"))?;
     for group in &database.groups {
-        write_group(&mut writer, group, stream_cipher)?;
+        write_group(&mut writer, major_version, group, pool, protected_values)?;
+    }
+    if !database.deleted_objects.is_empty() {
+        write_deleted_objects(&mut writer, major_version, &database.deleted_objects)?;
     }
     writer.write(XmlEvent::end_element())?;
     writer.write(XmlEvent::end_element())?;
@@ -0,0 +1,93 @@
+use kdbx_rs::database::{Database, Entry, Field, Group};
+use kdbx_rs::errors::ImportError;
+use kdbx_rs::export::{self, CsvOptions};
+use kdbx_rs::import;
+
+fn sample_db() -> Database {
+    let mut db = Database::default();
+    let mut entry = Entry::default();
+    entry.set_title("Bank, Personal");
+    entry.set_username("me");
+    entry.set_password("pass,\"word\"");
+    entry.set_url("https://bank.example.com");
+    entry.add_field(Field::new("Notes", "line one\nline two"));
+    db.add_entry(entry);
+
+    let mut group = Group::new("Work");
+    let mut entry = Entry::default();
+    entry.set_title("Mail");
+    entry.set_password("hunter2");
+    group.add_entry(entry);
+    db.add_group(group);
+    db
+}
+
+#[test]
+fn csv_export_escapes_values() {
+    let mut csv = Vec::new();
+    export::to_csv(&sample_db(), &mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\"\n\
+         \"Root\",\"Bank, Personal\",\"me\",\"pass,\"\"word\"\"\",\"https://bank.example.com\",\"line one\nline two\"\n\
+         \"Root/Work\",\"Mail\",\"\",\"hunter2\",\"\",\"\"\n"
+    );
+}
+
+#[test]
+fn csv_export_redacts_protected_values() {
+    let mut csv = Vec::new();
+    let options = CsvOptions {
+        redact_protected: true,
+    };
+    export::to_csv_with_options(&sample_db(), &mut csv, &options).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(!csv.contains("hunter2"));
+    assert!(csv.contains("\"Root/Work\",\"Mail\",\"\",\"\",\"\",\"\"\n"));
+}
+
+#[test]
+fn csv_import_reads_exported_entries() {
+    let original = sample_db();
+    let mut csv = Vec::new();
+    export::to_csv(&original, &mut csv).unwrap();
+
+    let imported = import::from_csv(csv.as_slice()).unwrap();
+    let entry = imported
+        .find_entry(|e| e.title() == Some("Bank, Personal"))
+        .unwrap();
+    assert_eq!(entry.username(), Some("me"));
+    assert_eq!(entry.password(), Some("pass,\"word\""));
+    assert_eq!(entry.url(), Some("https://bank.example.com"));
+    assert_eq!(entry.value("Notes"), Some("line one\nline two"));
+    assert_ne!(
+        entry.uuid(),
+        original.root().entries().next().unwrap().uuid()
+    );
+
+    let work = imported.find_group_by_path(&["Root", "Work"]).unwrap();
+    assert_eq!(work.entries().next().unwrap().password(), Some("hunter2"));
+    assert_eq!(imported.root().recursive_entries().count(), 2);
+}
+
+#[test]
+fn csv_import_with_delimiter_and_reordered_columns() {
+    let csv = "Password;Title;Group;Extra\r\nsecret;\"Semi;colon\";Vault/Sub/Deeper;x\r\n\r\n";
+    let options = import::CsvOptions { delimiter: ';' };
+    let db = import::from_csv_with_options(csv.as_bytes(), &options).unwrap();
+    assert_eq!(db.root().name(), "Vault");
+    let group = db.find_group_by_path(&["Vault", "Sub", "Deeper"]).unwrap();
+    let entry = group.entries().next().unwrap();
+    assert_eq!(entry.title(), Some("Semi;colon"));
+    assert_eq!(entry.password(), Some("secret"));
+    assert_eq!(entry.find("Extra"), None);
+}
+
+#[test]
+fn csv_import_rejects_unterminated_quote() {
+    let csv = "Title,Notes\n\"Entry\",\"never\nclosed\n";
+    assert!(matches!(
+        import::from_csv(csv.as_bytes()),
+        Err(ImportError::UnterminatedQuote(2))
+    ));
+}
@@ -0,0 +1,41 @@
+use kdbx_rs::database::{Entry, Group};
+use kdbx_rs::utils::NullStreamCipher;
+use kdbx_rs::xml::{parse_xml, write_xml};
+use kdbx_rs::Database;
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really an image";
+
+#[test]
+fn set_custom_icon_png() {
+    let mut db = Database::default();
+    let mut entry = Entry::default();
+    entry.set_custom_icon_png(&mut db, PNG);
+    let mut group = Group::new("Icons");
+    group.set_custom_icon_png(&mut db, PNG);
+
+    assert_eq!(db.meta().custom_icons.len(), 1);
+    let icon = &db.meta().custom_icons[0];
+    assert_eq!(icon.data, PNG);
+    assert_eq!(entry.custom_icon_uuid(), Some(icon.uuid));
+    assert_eq!(group.custom_icon_uuid(), Some(icon.uuid));
+}
+
+#[test]
+fn custom_icons_round_trip() {
+    let mut db = Database::default();
+    let mut entry = Entry::default();
+    entry.set_custom_icon_png(&mut db, PNG);
+    let icon_uuid = entry.custom_icon_uuid();
+    db.add_entry(entry);
+    let group_icon = db.meta_mut().add_custom_icon_png(b"another icon");
+    db.root_mut().set_custom_icon_uuid(Some(group_icon));
+
+    let mut output = Vec::new();
+    write_xml(&mut output, &db, &mut NullStreamCipher).unwrap();
+    let reparsed = parse_xml(&*output, &mut NullStreamCipher).unwrap();
+
+    assert_eq!(reparsed.meta().custom_icons, db.meta().custom_icons);
+    let entry = reparsed.root().entries().next().unwrap();
+    assert_eq!(entry.custom_icon_uuid(), icon_uuid);
+    assert_eq!(reparsed.root().custom_icon_uuid(), Some(group_icon));
+}
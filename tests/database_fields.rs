@@ -0,0 +1,363 @@
+use kdbx_rs::database::{Entry, Field};
+
+#[test]
+fn toggling_protection_keeps_value() {
+    let mut field = Field::new_protected("Secret", "hunter2");
+    field.set_protected(false);
+    assert!(!field.protected());
+    assert_eq!(field.value(), Some("hunter2"));
+
+    field.set_protected(true);
+    assert!(field.protected());
+    assert_eq!(field.value(), Some("hunter2"));
+}
+
+#[test]
+fn cloned_protected_field_survives_drop() {
+    let mut entry = Entry::default();
+    entry.set_password("hunter2");
+    let copy = entry.clone();
+    drop(entry);
+    assert_eq!(copy.password(), Some("hunter2"));
+}
+
+#[test]
+fn custom_field_histogram_counts_custom_keys() {
+    let mut db = kdbx_rs::Database::default();
+    for i in 0..3 {
+        let mut entry = Entry::default();
+        entry.set_title(format!("Entry {}", i));
+        entry.set_password("hunter2");
+        entry.add_field(Field::new("PIN", "1234"));
+        if i == 0 {
+            entry.add_field(Field::new("Recovery", "codes"));
+        }
+        db.add_entry(entry);
+    }
+
+    let histogram = db.custom_field_histogram();
+    assert_eq!(histogram.len(), 2);
+    assert_eq!(histogram["PIN"], 3);
+    assert_eq!(histogram["Recovery"], 1);
+}
+
+#[test]
+fn standard_fields_are_configurable() {
+    let mut db = kdbx_rs::Database::default();
+    let mut entry = Entry::default();
+    entry.set_title("Bank");
+    entry.add_field(Field::new("otp", "otpauth://totp/Bank?secret=JBSWY3DP"));
+    entry.add_field(Field::new("TOTP", "123456"));
+    entry.add_field(Field::new("PIN", "1234"));
+    let uuid = entry.uuid();
+    db.add_entry(entry);
+
+    let custom_keys = |db: &kdbx_rs::Database| -> Vec<String> {
+        let entry = db.entry_by_uuid(uuid).unwrap();
+        entry
+            .custom_fields(db)
+            .map(|f| f.key().to_string())
+            .collect()
+    };
+    assert_eq!(custom_keys(&db), vec!["TOTP", "PIN"]);
+
+    db.standard_fields_mut().add("TOTP");
+    assert_eq!(custom_keys(&db), vec!["PIN"]);
+    assert_eq!(db.custom_field_histogram().len(), 1);
+
+    assert!(db.standard_fields_mut().remove("otp"));
+    assert_eq!(custom_keys(&db), vec!["otp", "PIN"]);
+}
+
+#[test]
+fn prune_history_keeps_newest() {
+    let mut entry = Entry::default();
+    for version in 0..10 {
+        entry.set_title(format!("Version {}", version));
+        entry.new_version();
+    }
+    entry.prune_history(Some(3), None);
+    let titles: Vec<_> = entry
+        .history()
+        .entries()
+        .map(|e| e.title().unwrap())
+        .collect();
+    assert_eq!(titles, vec!["Version 7", "Version 8", "Version 9"]);
+
+    // Each version is "Title" plus "Version N", 14 bytes
+    entry.prune_history(None, Some(30));
+    assert_eq!(entry.history().len(), 2);
+    entry.prune_history(None, Some(0));
+    assert!(entry.history().is_empty());
+}
+
+#[test]
+fn prune_all_history_uses_meta_limits() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    db.meta_mut().history_max_items = Some(1);
+    db.root_mut().walk_entries_mut(|entry| {
+        entry.new_version();
+        entry.new_version();
+    });
+    db.prune_all_history();
+    assert!(db
+        .root()
+        .recursive_entries()
+        .all(|entry| entry.history().len() == 1));
+}
+
+#[test]
+fn history_tracking_snapshots_once() {
+    let mut entry = Entry::default().with_history_tracking();
+    entry.set_title("First");
+    entry.set_title("Second");
+    entry.set_password("hunter2");
+    assert_eq!(entry.history().len(), 1);
+    let snapshot = entry.history().get(0).unwrap();
+    assert_eq!(snapshot.title(), None);
+    assert!(snapshot.history().is_empty());
+
+    let mut untracked = Entry::default();
+    untracked.set_title("First");
+    assert!(untracked.history().is_empty());
+}
+
+#[test]
+fn touch_updates_access_time_and_usage() {
+    let mut entry = Entry::default();
+    let long_ago = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    entry.times_mut().last_access_time = long_ago;
+    let modified = entry.times().last_modification_time;
+
+    entry.touch();
+    entry.touch();
+    assert_eq!(entry.times().usage_count, 2);
+    assert!(entry.times().last_access_time > long_ago);
+    assert_eq!(entry.times().last_modification_time, modified);
+}
+
+#[test]
+fn expiry_helpers() {
+    use kdbx_rs::database::Group;
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut entry = Entry::default();
+    entry.times_mut().expiry_time = now - chrono::Duration::days(1);
+    assert!(!entry.is_expired());
+
+    entry
+        .times_mut()
+        .set_expiry(now - chrono::Duration::days(1));
+    assert!(entry.times().expires);
+    assert!(entry.is_expired());
+
+    let mut group = Group::new("Expiring");
+    group
+        .times_mut()
+        .set_expiry(now + chrono::Duration::days(1));
+    assert!(!group.is_expired());
+}
+
+#[test]
+fn duplicate_entry_gets_new_uuid() {
+    let mut entry = Entry::default();
+    entry.set_title("Original");
+    entry.times_mut().creation_time = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    entry.new_version();
+    entry.set_password("secret");
+
+    let copy = entry.duplicate();
+    assert_ne!(copy.uuid(), entry.uuid());
+    assert_eq!(copy.title(), Some("Original"));
+    assert_eq!(copy.password(), Some("secret"));
+    assert_eq!(copy.history().len(), 1);
+    assert_eq!(copy.history().get(0).unwrap().uuid(), copy.uuid());
+    assert!(copy.times().creation_time > entry.times().creation_time);
+}
+
+#[test]
+fn find_ignore_case_and_value() {
+    let mut entry = Entry::default();
+    entry.set_title("Standard");
+    entry.add_field(Field::new("title", "Lowercase"));
+    entry.add_field(Field::new("Custom Key", "custom"));
+
+    assert_eq!(entry.find("Title").unwrap().value(), Some("Standard"));
+    assert_eq!(entry.find("title").unwrap().value(), Some("Lowercase"));
+    assert!(entry.find("TITLE").is_none());
+
+    assert_eq!(
+        entry.find_ignore_case("Title").unwrap().value(),
+        Some("Standard")
+    );
+    assert_eq!(
+        entry.find_ignore_case("title").unwrap().value(),
+        Some("Lowercase")
+    );
+    assert_eq!(
+        entry.find_ignore_case("TITLE").unwrap().value(),
+        Some("Standard")
+    );
+    assert_eq!(
+        entry.find_ignore_case("custom key").unwrap().key(),
+        "Custom Key"
+    );
+
+    assert_eq!(entry.value("Title"), Some("Standard"));
+    assert_eq!(entry.value("title"), Some("Lowercase"));
+    assert_eq!(entry.value("Missing"), None);
+}
+
+#[test]
+fn resolve_field_references() {
+    let mut db = kdbx_rs::Database::default();
+    let mut target = Entry::default();
+    target.set_title("Shared Login");
+    target.set_username("shared-user");
+    target.set_password("hunter2");
+    let target_uuid = target.uuid();
+    db.add_entry(target);
+
+    let mut by_uuid = Entry::default();
+    by_uuid.set_password(format!(
+        "{{REF:P@I:{}}}",
+        target_uuid.simple().to_string().to_uppercase()
+    ));
+    by_uuid.set_username("{ref:u@t:shared}+suffix");
+    by_uuid.add_field(Field::new("Missing", "{REF:P@T:no such entry}"));
+    by_uuid.add_field(Field::new("Id", "{REF:I@U:shared-user}"));
+
+    assert_eq!(
+        by_uuid.resolved_value("Password", &db).as_deref(),
+        Some("hunter2")
+    );
+    assert_eq!(
+        by_uuid.resolved_value("UserName", &db).as_deref(),
+        Some("shared-user+suffix")
+    );
+    assert_eq!(
+        by_uuid.resolved_value("Missing", &db).as_deref(),
+        Some("{REF:P@T:no such entry}")
+    );
+    assert_eq!(
+        by_uuid.resolved_value("Id", &db),
+        Some(target_uuid.simple().to_string().to_uppercase())
+    );
+    // The stored value is untouched
+    assert!(by_uuid.password().unwrap().starts_with("{REF:"));
+}
+
+#[test]
+fn resolve_reference_cycle_terminates() {
+    let mut db = kdbx_rs::Database::default();
+    let mut first = Entry::default();
+    first.set_title("First");
+    first.set_password("{REF:P@T:Second}");
+    let mut second = Entry::default();
+    second.set_title("Second");
+    second.set_password("{REF:P@T:First}");
+    db.add_entry(first);
+    db.add_entry(second);
+
+    let resolved = db.resolve_references("{REF:P@T:First}");
+    assert!(resolved.starts_with("{REF:P@T:"));
+}
+
+#[test]
+fn expand_placeholders_uses_entry_fields() {
+    let mut entry = Entry::default();
+    entry.set_title("Mail");
+    entry.set_username("alex");
+    entry.add_field(Field::new("Domain", "example.com"));
+
+    assert_eq!(
+        entry.expand_placeholders("{USERNAME}@example.com"),
+        "alex@example.com"
+    );
+    assert_eq!(
+        entry.expand_placeholders("{Title}: {username}@{S:Domain}"),
+        "Mail: alex@example.com"
+    );
+    assert_eq!(
+        entry.expand_placeholders("{URL} {UNKNOWN} {S:Missing} {USERNAME"),
+        "{URL} {UNKNOWN} {S:Missing} {USERNAME"
+    );
+}
+
+#[test]
+fn entries_resolved_expands_references() {
+    let mut db = kdbx_rs::Database::default();
+    let mut target = Entry::default();
+    target.set_title("Shared Login");
+    target.set_password("hunter2");
+    db.add_entry(target);
+
+    let mut referencing = Entry::default();
+    referencing.set_title("Alias");
+    referencing.set_password("{REF:P@T:Shared Login}");
+    db.add_entry(referencing);
+
+    let (_, fields) = db
+        .entries_resolved()
+        .find(|(entry, _)| entry.title() == Some("Alias"))
+        .unwrap();
+    assert_eq!(fields["Title"], "Alias");
+    assert_eq!(fields["Password"], "hunter2");
+}
+
+#[test]
+fn tracked_mut_updates_modification_time() {
+    let long_ago = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let mut entry = Entry::default();
+    entry.times_mut().last_modification_time = long_ago;
+
+    entry.set_title("Untracked");
+    assert_eq!(entry.times().last_modification_time, long_ago);
+
+    assert_eq!(entry.tracked_mut().title(), Some("Untracked"));
+    assert_eq!(entry.times().last_modification_time, long_ago);
+
+    entry.tracked_mut().set_title("Tracked");
+    assert_eq!(entry.title(), Some("Tracked"));
+    assert!(entry.times().last_modification_time > long_ago);
+}
+
+#[test]
+fn debug_output_hides_protected_values() {
+    let mut entry = Entry::default();
+    entry.set_title("Visible title");
+    entry.set_password("hunter2");
+    let output = format!("{:?}", entry);
+    assert!(!output.contains("hunter2"));
+    assert!(output.contains("Protected(\"***\")"));
+    assert!(output.contains("Visible title"));
+
+    let mut db = kdbx_rs::Database::default();
+    db.add_entry(entry);
+    assert!(!format!("{:#?}", db).contains("hunter2"));
+}
+
+#[test]
+fn shared_attachment_counted_once() {
+    let mut db = kdbx_rs::Database::default();
+    for title in ["First", "Second"] {
+        let mut entry = Entry::default();
+        entry.set_title(title);
+        entry.add_attachment("shared.bin", vec![7; 1024]);
+        db.add_entry(entry);
+    }
+
+    assert_eq!(db.total_attachment_bytes(), 1024);
+    for entry in db.root().entries() {
+        assert_eq!(entry.attachment_bytes(&db), 1024);
+    }
+}
@@ -1,6 +1,6 @@
 use kdbx_rs::binary::InnerStreamCipherAlgorithm;
 use kdbx_rs::database::{Entry, Times};
-use kdbx_rs::xml::write_xml;
+use kdbx_rs::xml::{write_plain_xml, write_xml};
 
 use chrono::NaiveDate;
 use std::fs::read_to_string;
@@ -32,19 +32,27 @@ fn set_sample_times(times: &mut Times) {
     times.usage_count = 1;
 }
 
-#[test]
-fn generate_xml() -> Result<(), kdbx_rs::Error> {
+fn expected_output(name: &str) -> String {
     let mut expected_path = PathBuf::new();
     expected_path.push(env!("CARGO_MANIFEST_DIR"));
     expected_path.push("res");
     expected_path.push("test_output");
-    expected_path.push("generate_xml.xml");
+    expected_path.push(name);
 
-    let expected_xml_string = read_to_string(expected_path).unwrap().replace("\r\n", "\n");
+    read_to_string(expected_path).unwrap().replace("\r\n", "\n")
+}
 
+fn sample_db() -> kdbx_rs::Database {
     let mut db = kdbx_rs::Database::default();
     db.set_name("BarName");
     db.set_description("BazDesc");
+    let changed = NaiveDate::from_ymd_opt(2020, 4, 1)
+        .unwrap()
+        .and_hms_opt(1, 2, 3)
+        .unwrap();
+    db.meta_mut().recycle_bin_changed = changed;
+    db.meta_mut().database_name_changed = Some(changed);
+    db.meta_mut().database_description_changed = Some(changed);
     let group = db.root_mut();
     group.set_name("FooGroup");
     group.set_uuid(Uuid::from_u128(0x12345678));
@@ -55,6 +63,13 @@ fn generate_xml() -> Result<(), kdbx_rs::Error> {
     entry.set_uuid(Uuid::from_u128(0x654321));
     set_sample_times(entry.times_mut());
     group.add_entry(entry);
+    db
+}
+
+#[test]
+fn generate_xml() -> Result<(), kdbx_rs::Error> {
+    let expected_xml_string = expected_output("generate_xml.xml");
+    let db = sample_db();
 
     let mut output_buffer = Vec::new();
 
@@ -67,3 +82,15 @@ fn generate_xml() -> Result<(), kdbx_rs::Error> {
     assert_eq!(expected_xml_string, xml_string);
     Ok(())
 }
+
+#[test]
+fn generate_plain_xml() -> Result<(), kdbx_rs::Error> {
+    let expected_xml_string = expected_output("plain_xml.xml");
+    let db = sample_db();
+
+    let mut output_buffer = Vec::new();
+    write_plain_xml(&mut output_buffer, &db)?;
+    let xml_string = String::from_utf8(output_buffer).unwrap();
+    assert_eq!(expected_xml_string, xml_string);
+    Ok(())
+}
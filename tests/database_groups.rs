@@ -0,0 +1,507 @@
+use kdbx_rs::database::{Entry, Group};
+use kdbx_rs::Database;
+
+#[test]
+fn groups_exceeding_flags_full_groups() {
+    let mut db = Database::default();
+    let mut full = Group::new("Full");
+    for _ in 0..3 {
+        full.add_entry(Entry::default());
+    }
+    let full_uuid = full.uuid();
+    let mut sparse = Group::new("Sparse");
+    sparse.add_entry(Entry::default());
+    db.add_group(full);
+    db.add_group(sparse);
+
+    assert_eq!(db.groups_exceeding(2), vec![full_uuid]);
+    assert!(db.groups_exceeding(3).is_empty());
+}
+
+#[test]
+fn groups_exceeding_includes_root() {
+    let mut db = Database::default();
+    db.add_entry(Entry::default());
+    db.add_entry(Entry::default());
+
+    assert_eq!(db.groups_exceeding(1), vec![db.root().uuid()]);
+}
+
+#[test]
+fn entry_health_flags_reuse_expiry_and_age() {
+    let now = chrono::Utc::now().naive_utc();
+    let mut db = Database::default();
+
+    let mut first = Entry::default();
+    first.set_password("shared-secret");
+    first.times_mut().last_modification_time = now - chrono::Duration::days(10);
+    let first_uuid = first.uuid();
+
+    let mut second = Entry::default();
+    second.set_password("shared-secret");
+    second.times_mut().last_modification_time = now - chrono::Duration::days(3);
+    second.times_mut().expires = true;
+    second.times_mut().expiry_time = now - chrono::Duration::days(1);
+    let second_uuid = second.uuid();
+
+    let mut unique = Entry::default();
+    unique.set_password("Correct Horse Battery Staple 1!");
+    let unique_uuid = unique.uuid();
+
+    let mut group = Group::new("Sub");
+    group.add_entry(second);
+    db.add_group(group);
+    db.add_entry(first);
+    db.add_entry(unique);
+
+    let health = db.entry_health();
+    let report = |uuid| health.iter().find(|h| h.uuid == uuid).unwrap();
+
+    assert!(report(first_uuid).reused);
+    assert!(report(second_uuid).reused);
+    assert!(!report(unique_uuid).reused);
+    assert_eq!(report(first_uuid).age_days, 10);
+    assert_eq!(report(second_uuid).age_days, 3);
+    assert!(report(second_uuid).expired);
+    assert!(!report(first_uuid).expired);
+    for entry in db.root().recursive_entries() {
+        assert_eq!(report(entry.uuid()).expired, entry.is_expired());
+    }
+    assert!(report(unique_uuid).entropy > report(first_uuid).entropy);
+}
+
+#[test]
+fn entries_missing_required_fields() {
+    let mut db = Database::default();
+
+    let mut password_only = Entry::default();
+    password_only.set_password("hunter2");
+    let password_only_uuid = password_only.uuid();
+
+    let mut complete = Entry::default();
+    complete.set_username("user");
+    complete.set_password("hunter2");
+
+    let mut empty_username = Entry::default();
+    empty_username.set_username("");
+    let empty_username_uuid = empty_username.uuid();
+
+    db.add_entry(password_only);
+    db.add_entry(complete);
+    let mut group = Group::new("Sub");
+    group.add_entry(empty_username);
+    db.add_group(group);
+
+    let mut missing: Vec<_> = db
+        .entries_missing(&["UserName"])
+        .iter()
+        .map(|e| e.uuid())
+        .collect();
+    missing.sort();
+    let mut expected = vec![password_only_uuid, empty_username_uuid];
+    expected.sort();
+    assert_eq!(missing, expected);
+    assert!(db.entries_missing(&[]).is_empty());
+}
+
+#[test]
+fn recursive_groups_visits_each_group_once() {
+    let mut grandchild = Group::default();
+    grandchild.set_name("Grandchild");
+    let mut child_a = Group::default();
+    child_a.set_name("Child A");
+    child_a.add_group(grandchild);
+    let mut child_b = Group::default();
+    child_b.set_name("Child B");
+    let mut root = Group::default();
+    root.add_group(child_a);
+    root.add_group(child_b);
+
+    let names: Vec<_> = root.recursive_groups().map(|g| g.name()).collect();
+    assert_eq!(names, vec!["Child A", "Grandchild", "Child B"]);
+    let mut uuids: Vec<_> = root.recursive_groups().map(|g| g.uuid()).collect();
+    uuids.sort();
+    uuids.dedup();
+    assert_eq!(uuids.len(), 3);
+}
+
+#[test]
+fn remove_nested_entry_and_group() {
+    let mut entry = Entry::default();
+    entry.set_title("Nested");
+    let entry_uuid = entry.uuid();
+    let mut grandchild = Group::new("Grandchild");
+    grandchild.add_entry(entry);
+    let grandchild_uuid = grandchild.uuid();
+    let mut child = Group::new("Child");
+    child.add_group(grandchild);
+    let mut db = Database::default();
+    db.add_group(child);
+
+    let removed = db.remove_entry(entry_uuid).unwrap();
+    assert_eq!(removed.title(), Some("Nested"));
+    assert!(db.find_entry(|e| e.uuid() == entry_uuid).is_none());
+    assert!(db.remove_entry(entry_uuid).is_none());
+
+    let removed = db.remove_group(grandchild_uuid).unwrap();
+    assert_eq!(removed.name(), "Grandchild");
+    assert_eq!(db.root().recursive_groups().count(), 1);
+    let root_uuid = db.root().uuid();
+    assert!(db.remove_group(root_uuid).is_none());
+    let tombstones: Vec<_> = db.deleted_objects().iter().map(|d| d.uuid).collect();
+    assert_eq!(tombstones, vec![entry_uuid, grandchild_uuid]);
+}
+
+#[test]
+fn move_entries_and_groups() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    let foo_uuid = db.find_entry(|e| e.title() == Some("Foo")).unwrap().uuid();
+    let child_uuid = db.find_group(|g| g.name() == "Child Group").unwrap().uuid();
+    let root_uuid = db.root().uuid();
+
+    assert!(db.move_entry(foo_uuid, child_uuid));
+    let child = db.group_by_uuid(child_uuid).unwrap();
+    assert!(child.entries().any(|e| e.uuid() == foo_uuid));
+    assert!(!db.move_entry(foo_uuid, uuid::Uuid::nil()));
+    assert!(db.entry_by_uuid(foo_uuid).is_some());
+
+    let mut grandchild = Group::new("Grandchild");
+    let grandchild_uuid = grandchild.uuid();
+    grandchild.add_entry(Entry::default());
+    db.group_by_uuid_mut(child_uuid)
+        .unwrap()
+        .add_group(grandchild);
+    assert!(!db.move_group(child_uuid, grandchild_uuid));
+    assert!(!db.move_group(child_uuid, child_uuid));
+    assert!(!db.move_group(root_uuid, child_uuid));
+    assert!(db.move_group(grandchild_uuid, root_uuid));
+    assert!(db.root().groups().any(|g| g.uuid() == grandchild_uuid));
+    assert!(db.deleted_objects().is_empty());
+}
+
+#[test]
+fn iter_entries_with_paths() {
+    let db = kdbx_rs::database::doc_sample_db();
+    let paths: Vec<_> = db
+        .iter_entries()
+        .map(|(path, entry)| (path, entry.title().unwrap()))
+        .collect();
+    assert_eq!(
+        paths,
+        vec![(vec!["Root"], "Foo"), (vec!["Root", "Child Group"], "Bar")]
+    );
+}
+
+#[test]
+fn find_group_by_path() {
+    let db = kdbx_rs::database::doc_sample_db();
+    let group = db.find_group_by_path(&["Root", "Child Group"]).unwrap();
+    assert_eq!(group.name(), "Child Group");
+    assert_eq!(db.find_group_by_path(&["Root"]).unwrap().name(), "Root");
+    assert!(db.find_group_by_path(&["Root", "Missing"]).is_none());
+    assert!(db.find_group_by_path(&["Child Group"]).is_none());
+    assert!(db.find_group_by_path(&[]).is_none());
+}
+
+#[test]
+fn lookup_by_uuid() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    let entry_uuid = db.find_entry(|e| e.title() == Some("Bar")).unwrap().uuid();
+    let group_uuid = db.find_group(|g| g.name() == "Child Group").unwrap().uuid();
+    let root_uuid = db.root().uuid();
+
+    assert_eq!(db.entry_by_uuid(entry_uuid).unwrap().title(), Some("Bar"));
+    db.entry_by_uuid_mut(entry_uuid)
+        .unwrap()
+        .set_title("Renamed");
+    assert_eq!(
+        db.entry_by_uuid(entry_uuid).unwrap().title(),
+        Some("Renamed")
+    );
+    assert_eq!(db.group_by_uuid(group_uuid).unwrap().name(), "Child Group");
+    assert_eq!(db.group_by_uuid(root_uuid).unwrap().name(), "Root");
+    db.group_by_uuid_mut(group_uuid).unwrap().set_name("Moved");
+    assert_eq!(db.group_by_uuid(group_uuid).unwrap().name(), "Moved");
+    assert!(db.entry_by_uuid(group_uuid).is_none());
+}
+
+#[test]
+fn find_entries_across_nested_groups() {
+    let mut db = Database::default();
+    let mut top = Entry::default();
+    top.set_title("Mail work");
+    db.add_entry(top);
+    let mut other = Entry::default();
+    other.set_title("Bank");
+    let mut nested = Entry::default();
+    nested.set_title("Mail personal");
+    let mut grandchild = Group::new("Grandchild");
+    grandchild.add_entry(nested);
+    let mut child = Group::new("Child");
+    child.add_entry(other);
+    child.add_group(grandchild);
+    db.add_group(child);
+
+    let is_mail = |e: &Entry| e.title().unwrap_or_default().contains("Mail");
+    let titles: Vec<_> = db
+        .find_entries(is_mail)
+        .iter()
+        .map(|e| e.title().unwrap())
+        .collect();
+    assert_eq!(titles, vec!["Mail work", "Mail personal"]);
+    assert!(db.find_entries(|e| e.title() == Some("Missing")).is_empty());
+
+    for entry in db.find_entries_mut(is_mail) {
+        entry.set_username("me");
+    }
+    assert_eq!(db.find_entries(|e| e.username() == Some("me")).len(), 2);
+    assert_eq!(
+        db.find_entry(|e| e.title() == Some("Bank"))
+            .unwrap()
+            .username(),
+        None
+    );
+}
+
+#[test]
+fn recycle_moves_entry_into_bin() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    let uuid = db.find_entry(|e| e.title() == Some("Bar")).unwrap().uuid();
+    assert!(db.recycle_bin().is_none());
+
+    assert!(db.recycle(uuid));
+    let bin = db.recycle_bin().unwrap();
+    assert_eq!(bin.name(), "Recycle Bin");
+    assert_eq!(Some(bin.uuid()), db.meta().recycle_bin_uuid);
+    assert_eq!(bin.entries().next().unwrap().uuid(), uuid);
+    let child = db.find_group(|g| g.name() == "Child Group").unwrap();
+    assert_eq!(child.entries().count(), 0);
+
+    assert!(db.recycle(uuid));
+    assert!(db.entry_by_uuid(uuid).is_none());
+    assert!(!db.recycle(uuid));
+}
+
+#[test]
+fn recycle_with_bin_disabled_deletes() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    db.meta_mut().recycle_bin_enabled = false;
+    let uuid = db.find_entry(|e| e.title() == Some("Foo")).unwrap().uuid();
+
+    assert!(db.recycle(uuid));
+    assert!(db.entry_by_uuid(uuid).is_none());
+    assert!(db.recycle_bin().is_none());
+    assert_eq!(db.deleted_objects().len(), 1);
+    assert_eq!(db.deleted_objects()[0].uuid, uuid);
+}
+
+#[test]
+fn walk_entries_and_groups_mut() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    db.root_mut().walk_entries_mut(|entry| {
+        let title = format!("Old {}", entry.title().unwrap_or_default());
+        entry.set_title(title);
+    });
+    let mut titles: Vec<_> = db
+        .root()
+        .recursive_entries()
+        .map(|e| e.title().unwrap())
+        .collect();
+    titles.sort();
+    assert_eq!(titles, vec!["Old Bar", "Old Foo"]);
+
+    db.add_group(Group::new("Second"));
+    let mut visited = 0;
+    db.root_mut().walk_groups_mut(|group| {
+        visited += 1;
+        let name = format!("{}!", group.name());
+        group.set_name(name);
+    });
+    assert_eq!(visited, 2);
+    assert!(db.find_group(|g| g.name() == "Child Group!").is_some());
+    assert!(db.find_group(|g| g.name() == "Second!").is_some());
+    assert_eq!(db.root().name(), "Root");
+}
+
+#[test]
+fn add_entries_to_subgroup() {
+    let mut db = Database::default();
+    let mut parent = Group::new("Parent");
+    let child = Group::new("Imported");
+    let child_uuid = child.uuid();
+    parent.add_group(child);
+    db.add_group(parent);
+
+    let entries = (0..100).map(|i| {
+        let mut entry = Entry::default();
+        entry.set_title(format!("Entry {}", i));
+        entry
+    });
+    db.add_entries_to(child_uuid, entries).unwrap();
+
+    let child = db.group_by_uuid(child_uuid).unwrap();
+    assert_eq!(child.entries().count(), 100);
+    assert_eq!(child.entries().last().unwrap().title(), Some("Entry 99"));
+    assert_eq!(db.root().entries().count(), 0);
+}
+
+#[test]
+fn add_entries_to_missing_group_fails() {
+    use kdbx_rs::errors::AddError;
+
+    let mut db = Database::default();
+    let missing = uuid::Uuid::new_v4();
+    assert_eq!(
+        db.add_entries_to(missing, vec![Entry::default()]),
+        Err(AddError::GroupNotFound(missing))
+    );
+    assert_eq!(db.find_entries(|_| true).len(), 0);
+}
+
+#[test]
+fn search_matches_notes_and_skips_protected() {
+    let mut db = Database::default();
+    let mut group = Group::new("Nested");
+    let mut noted = Entry::default();
+    noted.set_title("Router");
+    noted.add_field(kdbx_rs::database::Field::new(
+        "Notes",
+        "Admin panel is on the Basement switch",
+    ));
+    let noted_uuid = noted.uuid();
+    group.add_entry(noted);
+    db.add_group(group);
+    let mut other = Entry::default();
+    other.set_title("Email");
+    other.set_password("basement-password");
+    let other_uuid = other.uuid();
+    db.add_entry(other);
+
+    let found = db.search("BASEMENT SWITCH", false);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].uuid(), noted_uuid);
+
+    let found: Vec<_> = db
+        .search("basement", false)
+        .iter()
+        .map(|e| e.uuid())
+        .collect();
+    assert_eq!(found, vec![noted_uuid]);
+    let found: Vec<_> = db
+        .search("basement", true)
+        .iter()
+        .map(|e| e.uuid())
+        .collect();
+    assert_eq!(found.len(), 2);
+    assert!(found.contains(&other_uuid));
+}
+
+#[test]
+fn last_modified_is_latest_item_time() {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    let base = chrono::NaiveDate::from_ymd_opt(2021, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    db.root_mut().walk_entries_mut(|entry| {
+        entry.times_mut().last_modification_time = base;
+    });
+    db.root_mut().walk_groups_mut(|group| {
+        group.times_mut().last_modification_time = base;
+    });
+    db.root_mut().times_mut().last_modification_time = base;
+    assert_eq!(db.last_modified(), base);
+
+    let latest = base + chrono::Duration::days(3);
+    db.find_entry_mut(|e| e.title() == Some("Bar"))
+        .unwrap()
+        .times_mut()
+        .last_modification_time = latest;
+    db.find_group_mut(|g| g.name() == "Child Group")
+        .unwrap()
+        .times_mut()
+        .last_modification_time = base + chrono::Duration::days(2);
+    assert_eq!(db.last_modified(), latest);
+}
+
+#[test]
+fn compact_removes_bloat() {
+    use kdbx_rs::database::{CompactOptions, CompactReport};
+
+    let mut db = Database::default();
+    db.meta_mut().history_max_items = Some(10);
+    let mut entry = Entry::default();
+    for version in 0..15 {
+        entry.add_attachment("scan.png", vec![version; 16]);
+        entry.new_version();
+    }
+    db.add_entry(entry);
+    let mut empty = Group::new("Empty");
+    empty.add_group(Group::new("Also empty"));
+    db.add_group(empty);
+    let mut kept = Group::new("Kept");
+    kept.add_entry(Entry::default());
+    db.add_group(kept);
+    let bin = Group::new("Recycle Bin");
+    db.meta_mut().recycle_bin_uuid = Some(bin.uuid());
+    db.add_group(bin);
+
+    let report = db.compact(&CompactOptions::default());
+    assert_eq!(
+        report,
+        CompactReport {
+            history_removed: 5,
+            binaries_removed: 5,
+            empty_groups_removed: 2,
+            undecryptable_removed: 0,
+        }
+    );
+    let names: Vec<_> = db.root().groups().map(|g| g.name()).collect();
+    assert_eq!(names, vec!["Kept", "Recycle Bin"]);
+
+    // Already compact
+    assert_eq!(
+        db.compact(&CompactOptions::default()),
+        CompactReport::default()
+    );
+}
+
+#[test]
+fn to_text_ignores_insertion_order() {
+    use kdbx_rs::database::{Field, TextOptions};
+
+    let mut first = Entry::default();
+    first.set_title("First");
+    first.set_username("user");
+    first.set_password("hunter2");
+    first.add_field(Field::new("Custom", "multi\nline"));
+    let mut second = Entry::default();
+    second.set_title("Second");
+    second.add_attachment("notes.txt", b"attached".to_vec());
+    let group = Group::new("Work");
+
+    let mut db_a = Database::default();
+    let mut group_a = group.clone();
+    group_a.add_entry(second.clone());
+    db_a.add_group(group_a);
+    db_a.add_entry(first.clone());
+
+    let mut db_b = Database::default();
+    db_b.add_entry(first);
+    let mut group_b = group;
+    group_b.add_entry(second);
+    db_b.add_group(group_b);
+
+    // The root groups were created separately, so may differ by a second
+    db_b.root_mut().times_mut().last_modification_time = db_a.root().times().last_modification_time;
+
+    let text = db_a.to_text();
+    assert_eq!(text, db_b.to_text());
+    assert!(text.contains("\"Custom\" = \"multi\\nline\""));
+    assert!(text.contains("Attachment \"notes.txt\""));
+    assert!(!text.contains("hunter2"));
+    let revealed = db_a.to_text_with_options(&TextOptions {
+        reveal_protected: true,
+    });
+    assert!(revealed.contains("\"Password\" = \"hunter2\""));
+}
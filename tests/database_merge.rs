@@ -0,0 +1,237 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use kdbx_rs::database::{CustomDataItem, Entry, Field, Group, MergeReport};
+use kdbx_rs::Database;
+
+fn time(day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2021, 1, day)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+fn base_db() -> Database {
+    let mut db = kdbx_rs::database::doc_sample_db();
+    db.root_mut().walk_entries_mut(|entry| {
+        entry.times_mut().last_modification_time = time(1);
+    });
+    db.root_mut().walk_groups_mut(|group| {
+        group.times_mut().last_modification_time = time(1);
+    });
+    db.root_mut().times_mut().last_modification_time = time(1);
+    db
+}
+
+fn edit_entry(db: &mut Database, title: &str, new_title: &str, day: u32) {
+    let entry = db.find_entry_mut(|e| e.title() == Some(title)).unwrap();
+    entry.new_version();
+    entry.set_title(new_title);
+    entry.times_mut().last_modification_time = time(day);
+}
+
+#[test]
+fn merge_newest_wins() {
+    let mut local = base_db();
+    let mut remote = local.clone();
+
+    edit_entry(&mut remote, "Foo", "Foo remote", 3);
+    edit_entry(&mut local, "Bar", "Bar local", 4);
+    edit_entry(&mut remote, "Bar", "Bar remote", 2);
+
+    let report = local.merge(&remote);
+    assert_eq!(report.updated, 1);
+    assert_eq!(report.added, 0);
+
+    let foo = local
+        .find_entry(|e| e.title() == Some("Foo remote"))
+        .unwrap();
+    assert_eq!(foo.history().len(), 1);
+    let bar = local
+        .find_entry(|e| e.title() == Some("Bar local"))
+        .unwrap();
+    let history: Vec<_> = bar
+        .history()
+        .entries()
+        .map(|e| e.title().unwrap())
+        .collect();
+    assert_eq!(history, vec!["Bar", "Bar remote"]);
+}
+
+#[test]
+fn merge_adds_new_items() {
+    let mut local = base_db();
+    let mut remote = local.clone();
+
+    let mut extra = Group::new("Extra");
+    let mut deep = Entry::default();
+    deep.set_title("Deep");
+    extra.add_entry(deep);
+    remote.add_group(extra);
+    let mut new_entry = Entry::default();
+    new_entry.set_title("New");
+    remote
+        .find_group_mut(|g| g.name() == "Child Group")
+        .unwrap()
+        .add_entry(new_entry);
+
+    let report = local.merge(&remote);
+    assert_eq!(report.added, 3);
+    assert_eq!(report.updated, 0);
+    let extra = local.find_group(|g| g.name() == "Extra").unwrap();
+    assert_eq!(extra.entries().next().unwrap().title(), Some("Deep"));
+    let child = local.find_group(|g| g.name() == "Child Group").unwrap();
+    assert_eq!(child.entries().count(), 2);
+
+    // Merging again changes nothing
+    let report = local.merge(&remote);
+    assert_eq!(report.added, 0);
+    assert_eq!(report.updated, 0);
+}
+
+#[test]
+fn merge_honours_tombstones() {
+    let mut local = base_db();
+    let mut remote = local.clone();
+
+    let foo_uuid = local
+        .find_entry(|e| e.title() == Some("Foo"))
+        .unwrap()
+        .uuid();
+    remote.remove_entry(foo_uuid);
+    assert_eq!(remote.deleted_objects().last().unwrap().uuid, foo_uuid);
+    remote
+        .deleted_objects_mut()
+        .last_mut()
+        .unwrap()
+        .deletion_time = time(2);
+    // Edited locally after the remote deletion, so it survives
+    let bar_uuid = local
+        .find_entry(|e| e.title() == Some("Bar"))
+        .unwrap()
+        .uuid();
+    edit_entry(&mut local, "Bar", "Bar local", 5);
+    remote.remove_entry(bar_uuid);
+    remote
+        .deleted_objects_mut()
+        .last_mut()
+        .unwrap()
+        .deletion_time = time(3);
+
+    let report = local.merge(&remote);
+    assert_eq!(
+        report,
+        MergeReport {
+            deleted: 1,
+            skipped: 2,
+            ..Default::default()
+        }
+    );
+    assert!(local.entry_by_uuid(foo_uuid).is_none());
+    assert!(local.entry_by_uuid(bar_uuid).is_some());
+    assert_eq!(local.deleted_objects().len(), 2);
+
+    // A tombstoned entry is not brought back by an older copy
+    let report = local.merge(&base_db_with(foo_uuid));
+    assert_eq!(report.added, 0);
+    assert!(local.entry_by_uuid(foo_uuid).is_none());
+}
+
+fn base_db_with(uuid: uuid::Uuid) -> Database {
+    let mut db = Database::default();
+    let mut entry = Entry::default();
+    entry.set_uuid(uuid);
+    entry.times_mut().last_modification_time = time(1);
+    db.add_entry(entry);
+    db
+}
+
+#[test]
+fn merge_keeps_newest_name_and_description() {
+    let mut local = base_db();
+    let mut remote = local.clone();
+    local.meta_mut().database_name_changed = Some(time(1));
+    local.meta_mut().database_description_changed = Some(time(1));
+
+    remote.set_name("Renamed");
+    assert!(remote.meta().database_name_changed > Some(time(1)));
+    remote.meta_mut().database_description = "Stale".to_string();
+
+    local.merge(&remote);
+    assert_eq!(local.name(), "Renamed");
+    assert_eq!(
+        local.meta().database_name_changed,
+        remote.meta().database_name_changed
+    );
+    assert_eq!(local.description(), "");
+}
+
+#[test]
+fn merge_newer_group_properties() {
+    let mut local = base_db();
+    let mut remote = local.clone();
+
+    let entry_uuid = remote.root().entries().next().unwrap().uuid();
+    let group = remote.root_mut().groups_mut().next().unwrap();
+    group.set_name("Renamed");
+    group.set_expanded(false);
+    group.set_default_auto_type_sequence("{PASSWORD}{ENTER}");
+    group.set_enable_auto_type(Some(false));
+    group.set_enable_searching(Some(false));
+    group.set_last_top_visible_entry(Some(entry_uuid));
+    group
+        .custom_data_mut()
+        .push(CustomDataItem::from(Field::new("Plugin", "remote")));
+    group.times_mut().last_modification_time = time(2);
+
+    let local_group = local.root().groups().next().unwrap();
+    let local_children = (local_group.entries().count(), local_group.groups().count());
+    let report = local.merge(&remote);
+    assert_eq!(report.updated, 1);
+    let merged = local.root().groups().next().unwrap();
+    assert_eq!(merged.name(), "Renamed");
+    assert!(!merged.is_expanded());
+    assert_eq!(merged.default_auto_type_sequence(), "{PASSWORD}{ENTER}");
+    assert_eq!(merged.enable_auto_type(), Some(false));
+    assert_eq!(merged.enable_searching(), Some(false));
+    assert_eq!(merged.last_top_visible_entry(), Some(entry_uuid));
+    assert_eq!(merged.custom_data().len(), 1);
+    assert_eq!(merged.custom_data()[0].field.value(), Some("remote"));
+    // Children are merged separately, not replaced with the remote group's
+    let children = |g: &Group| (g.entries().count(), g.groups().count());
+    assert_eq!(children(merged), local_children);
+}
+
+#[test]
+fn removed_items_stay_removed_after_merge() {
+    let mut local = base_db();
+    let old_copy = local.clone();
+
+    let foo_uuid = local
+        .find_entry(|e| e.title() == Some("Foo"))
+        .unwrap()
+        .uuid();
+    let child = local.find_group(|g| g.name() == "Child Group").unwrap();
+    let child_uuid = child.uuid();
+    let child_entries: Vec<_> = child.recursive_entries().map(|e| e.uuid()).collect();
+    assert!(!child_entries.is_empty());
+
+    local.remove_entry(foo_uuid).unwrap();
+    local.remove_group(child_uuid).unwrap();
+    let tombstones: Vec<_> = local.deleted_objects().iter().map(|d| d.uuid).collect();
+    assert!(tombstones.contains(&foo_uuid));
+    assert!(tombstones.contains(&child_uuid));
+    assert!(child_entries.iter().all(|uuid| tombstones.contains(uuid)));
+
+    let report = local.merge(&old_copy);
+    assert_eq!(report.added, 0);
+    assert!(local.entry_by_uuid(foo_uuid).is_none());
+    assert!(local.group_by_uuid(child_uuid).is_none());
+    assert!(child_entries
+        .iter()
+        .all(|uuid| local.entry_by_uuid(*uuid).is_none()));
+
+    // The old copy picks up the deletions too
+    let mut old_copy = old_copy;
+    old_copy.merge(&local);
+    assert!(old_copy.entry_by_uuid(foo_uuid).is_none());
+    assert!(old_copy.group_by_uuid(child_uuid).is_none());
+}
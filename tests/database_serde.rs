@@ -0,0 +1,40 @@
+#![cfg(feature = "serde")]
+
+use kdbx_rs::database::doc_sample_db;
+use kdbx_rs::Database;
+
+#[test]
+fn json_round_trip() {
+    let db = doc_sample_db();
+    let json = serde_json::to_string(&db).unwrap();
+    let parsed: Database = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, db);
+}
+
+#[test]
+fn pretty_json_round_trip() {
+    let db = doc_sample_db();
+    let json = db.to_json_pretty().unwrap();
+    assert!(json.contains("\n  "));
+    assert!(json.contains("password1"));
+    assert_eq!(Database::from_json(&json).unwrap(), db);
+}
+
+#[test]
+fn values_are_tagged_and_times_are_iso_8601() {
+    let db = doc_sample_db();
+    let json = serde_json::to_value(&db).unwrap();
+    let entry = &json["groups"][0]["entries"][0];
+    let password = entry["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["key"] == "Password")
+        .unwrap();
+    assert_eq!(password["value"]["kind"], "protected");
+    assert_eq!(password["value"]["value"], "password1");
+
+    let created = entry["times"]["creation_time"].as_str().unwrap();
+    let expected = db.root().entries().next().unwrap().times().creation_time;
+    assert_eq!(created, expected.format("%Y-%m-%dT%H:%M:%S").to_string());
+}
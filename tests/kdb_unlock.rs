@@ -0,0 +1,461 @@
+use aes::Aes256;
+use chrono::{NaiveDate, NaiveDateTime};
+use cipher::block_padding::Pkcs7;
+use cipher::generic_array::GenericArray;
+use cipher::{BlockCipher, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit};
+use kdbx_rs::binary::Kdb;
+use kdbx_rs::errors::UnlockError;
+use kdbx_rs::CompositeKey;
+use sha2::{Digest, Sha256};
+use twofish::Twofish;
+use uuid::Uuid;
+
+const PASSWORD: &str = "kdbxrs";
+const FLAG_RIJNDAEL: u32 = 2;
+const FLAG_TWOFISH: u32 = 8;
+const KEY_ROUNDS: u32 = 100;
+
+struct TestGroup {
+    id: u32,
+    level: u16,
+    name: &'static str,
+}
+
+struct TestEntry {
+    uuid: Uuid,
+    group_id: u32,
+    title: &'static str,
+    username: &'static str,
+    password: &'static str,
+    url: &'static str,
+    notes: &'static str,
+    binary_desc: &'static str,
+    binary_data: &'static [u8],
+    expires: bool,
+}
+
+fn modified_time() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2020, 5, 17)
+        .unwrap()
+        .and_hms_opt(13, 45, 30)
+        .unwrap()
+}
+
+fn expiry_time() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2031, 1, 2)
+        .unwrap()
+        .and_hms_opt(3, 4, 5)
+        .unwrap()
+}
+
+fn never_expires() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2999, 12, 28)
+        .unwrap()
+        .and_hms_opt(23, 59, 59)
+        .unwrap()
+}
+
+fn pack_time(time: NaiveDateTime) -> [u8; 5] {
+    use chrono::{Datelike, Timelike};
+    let (year, month, day) = (time.year() as u32, time.month(), time.day());
+    let (hour, minute, second) = (time.hour(), time.minute(), time.second());
+    [
+        (year >> 6) as u8,
+        (((year & 0x3F) << 2) | (month >> 2)) as u8,
+        (((month & 0x03) << 6) | (day << 1) | (hour >> 4)) as u8,
+        (((hour & 0x0F) << 4) | (minute >> 2)) as u8,
+        (((minute & 0x03) << 6) | second) as u8,
+    ]
+}
+
+fn push_field(data: &mut Vec<u8>, ty: u16, value: &[u8]) {
+    data.extend_from_slice(&ty.to_le_bytes());
+    data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    data.extend_from_slice(value);
+}
+
+fn push_string(data: &mut Vec<u8>, ty: u16, value: &str) {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    push_field(data, ty, &bytes);
+}
+
+fn records(groups: &[TestGroup], entries: &[TestEntry]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for group in groups {
+        push_field(&mut data, 0x0001, &group.id.to_le_bytes());
+        push_string(&mut data, 0x0002, group.name);
+        push_field(&mut data, 0x0004, &pack_time(modified_time()));
+        push_field(&mut data, 0x0006, &pack_time(never_expires()));
+        push_field(&mut data, 0x0008, &group.level.to_le_bytes());
+        push_field(&mut data, 0xFFFF, &[]);
+    }
+    for entry in entries {
+        push_field(&mut data, 0x0001, entry.uuid.as_bytes());
+        push_field(&mut data, 0x0002, &entry.group_id.to_le_bytes());
+        push_string(&mut data, 0x0004, entry.title);
+        push_string(&mut data, 0x0005, entry.url);
+        push_string(&mut data, 0x0006, entry.username);
+        push_string(&mut data, 0x0007, entry.password);
+        push_string(&mut data, 0x0008, entry.notes);
+        push_field(&mut data, 0x000A, &pack_time(modified_time()));
+        let expiry = if entry.expires {
+            expiry_time()
+        } else {
+            never_expires()
+        };
+        push_field(&mut data, 0x000C, &pack_time(expiry));
+        if !entry.binary_desc.is_empty() {
+            push_string(&mut data, 0x000D, entry.binary_desc);
+            push_field(&mut data, 0x000E, entry.binary_data);
+        }
+        push_field(&mut data, 0xFFFF, &[]);
+    }
+    data
+}
+
+fn cipher_key(password: &str, master_seed: &[u8], transform_seed: &[u8]) -> Vec<u8> {
+    let composed = Sha256::digest(password.as_bytes());
+    let transform = Aes256::new_from_slice(transform_seed).unwrap();
+    let mut blocks = [
+        *GenericArray::from_slice(&composed[..16]),
+        *GenericArray::from_slice(&composed[16..]),
+    ];
+    for _ in 0..KEY_ROUNDS {
+        transform.encrypt_blocks(&mut blocks);
+    }
+    let mut transformed = Sha256::new();
+    transformed.update(blocks[0]);
+    transformed.update(blocks[1]);
+    let transformed = transformed.finalize();
+
+    let mut key = Sha256::new();
+    key.update(master_seed);
+    key.update(transformed);
+    key.finalize().to_vec()
+}
+
+fn build_kdb<C>(flags: u32, groups: &[TestGroup], entries: &[TestEntry]) -> Vec<u8>
+where
+    C: BlockCipher + BlockEncryptMut + KeyInit,
+{
+    let master_seed = [0x11u8; 16];
+    let encryption_iv = [0x22u8; 16];
+    let transform_seed = [0x33u8; 32];
+    let plaintext = records(groups, entries);
+
+    let key = cipher_key(PASSWORD, &master_seed, &transform_seed);
+    let mut buffer = plaintext.clone();
+    buffer.resize(plaintext.len() + 16, 0);
+    let encrypted = cbc::Encryptor::<C>::new_from_slices(&key, &encryption_iv)
+        .unwrap()
+        .encrypt_padded_mut::<Pkcs7>(&mut buffer, plaintext.len())
+        .unwrap()
+        .to_vec();
+
+    let mut file = Vec::new();
+    file.extend_from_slice(&0x9AA2_D903u32.to_le_bytes());
+    file.extend_from_slice(&0xB54B_FB65u32.to_le_bytes());
+    file.extend_from_slice(&(flags | 1).to_le_bytes());
+    file.extend_from_slice(&0x0003_0004u32.to_le_bytes());
+    file.extend_from_slice(&master_seed);
+    file.extend_from_slice(&encryption_iv);
+    file.extend_from_slice(&(groups.len() as u32).to_le_bytes());
+    file.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    file.extend_from_slice(&Sha256::digest(&plaintext));
+    file.extend_from_slice(&transform_seed);
+    file.extend_from_slice(&KEY_ROUNDS.to_le_bytes());
+    file.extend_from_slice(&encrypted);
+    file
+}
+
+fn sample_kdb<C>(flags: u32) -> Vec<u8>
+where
+    C: BlockCipher + BlockEncryptMut + KeyInit,
+{
+    let groups = [
+        TestGroup {
+            id: 1,
+            level: 0,
+            name: "General",
+        },
+        TestGroup {
+            id: 2,
+            level: 1,
+            name: "Email",
+        },
+        TestGroup {
+            id: 3,
+            level: 2,
+            name: "Work",
+        },
+        TestGroup {
+            id: 4,
+            level: 1,
+            name: "Banking",
+        },
+        TestGroup {
+            id: 5,
+            level: 0,
+            name: "Backup",
+        },
+    ];
+    let entries = [
+        TestEntry {
+            uuid: Uuid::from_u128(1),
+            group_id: 1,
+            title: "Bank",
+            username: "user",
+            password: "hunter2",
+            url: "https://bank.example",
+            notes: "Some notes",
+            binary_desc: "",
+            binary_data: b"",
+            expires: true,
+        },
+        TestEntry {
+            uuid: Uuid::from_u128(2),
+            group_id: 2,
+            title: "Mail",
+            username: "me@example.com",
+            password: "letmein",
+            url: "https://mail.example",
+            notes: "",
+            binary_desc: "scan.txt",
+            binary_data: b"scanned letter",
+            expires: false,
+        },
+        TestEntry {
+            uuid: Uuid::from_u128(3),
+            group_id: 1,
+            title: "Meta-Info",
+            username: "SYSTEM",
+            password: "",
+            url: "$",
+            notes: "KPX_GROUP_TREE_STATE",
+            binary_desc: "bin-stream",
+            binary_data: &[0, 1, 2, 3],
+            expires: false,
+        },
+    ];
+    build_kdb::<C>(flags, &groups, &entries)
+}
+
+#[test]
+fn kdb_aes_unlock() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))?;
+    assert_eq!(kdb.header().group_count, 5);
+    assert_eq!(kdb.header().key_rounds, KEY_ROUNDS);
+
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+    let general = db.find_group(|g| g.name() == "General").unwrap();
+    let bank = general.find_entry(|e| e.title() == Some("Bank")).unwrap();
+    assert_eq!(bank.uuid(), Uuid::from_u128(1));
+    assert_eq!(bank.username(), Some("user"));
+    assert_eq!(bank.password(), Some("hunter2"));
+    assert_eq!(bank.url(), Some("https://bank.example"));
+    assert_eq!(
+        bank.find("Notes").and_then(|f| f.value()),
+        Some("Some notes")
+    );
+
+    let email = db.find_group(|g| g.name() == "Email").unwrap();
+    assert_eq!(email.entry_count(), 1);
+    assert_eq!(
+        email.entries().next().unwrap().username(),
+        Some("me@example.com")
+    );
+    Ok(())
+}
+
+#[test]
+fn kdb_skips_meta_stream_entries() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))?;
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+    assert!(db.find_entry(|e| e.title() == Some("Meta-Info")).is_none());
+    Ok(())
+}
+
+#[test]
+fn kdb_wrong_password() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))?;
+    let result = kdb.unlock(&CompositeKey::from_password("wrong"));
+    assert!(matches!(result, Err(UnlockError::ContentHashInvalid)));
+    Ok(())
+}
+
+#[test]
+fn kdb_tampered_counts() -> Result<(), kdbx_rs::Error> {
+    let mut file = sample_kdb::<Aes256>(FLAG_RIJNDAEL);
+    file[48..56].copy_from_slice(&[0xFF; 8]);
+    let kdb = Kdb::from_reader(&*file)?;
+    assert_eq!(kdb.header().group_count, u32::MAX);
+    let result = kdb.unlock(&CompositeKey::from_password(PASSWORD));
+    assert!(matches!(result, Err(UnlockError::InvalidKdbRecord(_))));
+    Ok(())
+}
+
+#[test]
+fn kdb_is_not_kdbx() {
+    let result = kdbx_rs::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL));
+    assert!(matches!(
+        result,
+        Err(kdbx_rs::errors::OpenError::UnsupportedFileFormat)
+    ));
+}
+
+#[test]
+fn kdb_twofish_unlock() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Twofish>(FLAG_TWOFISH))?;
+    assert_eq!(
+        kdb.header().cipher(),
+        Some(kdbx_rs::binary::Cipher::TwoFish)
+    );
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+    let bank = db.find_entry(|e| e.title() == Some("Bank")).unwrap();
+    assert_eq!(bank.password(), Some("hunter2"));
+    Ok(())
+}
+
+#[test]
+fn kdb_group_levels_build_tree() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))?;
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+    let top_level: Vec<&str> = db.root().groups().map(|g| g.name()).collect();
+    assert_eq!(top_level, vec!["General", "Backup"]);
+
+    let general = db.root().groups().next().unwrap();
+    let children: Vec<&str> = general.groups().map(|g| g.name()).collect();
+    assert_eq!(children, vec!["Email", "Banking"]);
+
+    let email = general.groups().next().unwrap();
+    let grandchildren: Vec<&str> = email.groups().map(|g| g.name()).collect();
+    assert_eq!(grandchildren, vec!["Work"]);
+    Ok(())
+}
+
+#[test]
+fn kdb_attachments() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))?;
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+
+    let mail = db.find_entry(|e| e.title() == Some("Mail")).unwrap();
+    let attachment = mail.attachment("scan.txt").unwrap();
+    assert_eq!(attachment.data(), b"scanned letter");
+    assert_eq!(mail.attachments().count(), 1);
+
+    let bank = db.find_entry(|e| e.title() == Some("Bank")).unwrap();
+    assert_eq!(bank.attachments().count(), 0);
+    Ok(())
+}
+
+#[test]
+fn kdb_times() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))?;
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+
+    let general = db.find_group(|g| g.name() == "General").unwrap();
+    assert_eq!(general.times().last_modification_time, modified_time());
+    assert!(!general.times().expires);
+
+    let bank = db.find_entry(|e| e.title() == Some("Bank")).unwrap();
+    assert_eq!(bank.times().last_modification_time, modified_time());
+    assert!(bank.times().expires);
+    assert_eq!(bank.times().expiry_time, expiry_time());
+
+    let mail = db.find_entry(|e| e.title() == Some("Mail")).unwrap();
+    assert!(!mail.times().expires);
+    Ok(())
+}
+
+#[test]
+fn kdb_detected_by_any_from_reader() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::AnyDatabase;
+
+    match kdbx_rs::binary::any_from_reader(&*sample_kdb::<Aes256>(FLAG_RIJNDAEL))? {
+        AnyDatabase::Kdb(kdb) => {
+            kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+        }
+        AnyDatabase::Kdbx(_) => panic!("KDB file detected as KDBX"),
+    }
+    Ok(())
+}
+
+#[test]
+fn kdb_format_detected() {
+    let data = sample_kdb::<Aes256>(FLAG_RIJNDAEL);
+    let (format, _) = kdbx_rs::binary::detect_format_slice(&data).unwrap();
+    assert_eq!(format, kdbx_rs::binary::Format::Kdb);
+}
+
+fn sample_file(name: &str) -> std::path::PathBuf {
+    let mut file_path = std::path::PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push(name);
+    file_path
+}
+
+fn assert_sample_file_contents(db: &kdbx_rs::Database) {
+    let top_level: Vec<&str> = db.root().groups().map(|g| g.name()).collect();
+    assert_eq!(top_level, vec!["General", "Backup"]);
+    let general = db.root().groups().next().unwrap();
+    let children: Vec<&str> = general.groups().map(|g| g.name()).collect();
+    assert_eq!(children, vec!["Email"]);
+
+    let sample = general.entries().next().unwrap();
+    assert_eq!(sample.title(), Some("Sample Entry"));
+    assert_eq!(sample.username(), Some("User Name"));
+    assert_eq!(sample.password(), Some("kdbxrs"));
+    assert_eq!(sample.url(), Some("https://keepass.info/"));
+    assert_eq!(
+        sample.find("Notes").and_then(|f| f.value()),
+        Some("Saved in the KeePass 1 format")
+    );
+    assert_eq!(
+        sample.uuid(),
+        Uuid::parse_str("b38bc414-9736-9549-8fc4-98f7a8c4710e").unwrap()
+    );
+    let modified = NaiveDate::from_ymd_opt(2023, 3, 4)
+        .unwrap()
+        .and_hms_opt(10, 15, 52)
+        .unwrap();
+    assert_eq!(sample.times().last_modification_time, modified);
+    assert!(!sample.times().expires);
+    assert_eq!(sample.attachments().count(), 0);
+
+    let mail = db.find_entry(|e| e.title() == Some("Mail")).unwrap();
+    assert_eq!(mail.password(), Some("letmein"));
+    assert!(mail.times().expires);
+    assert_eq!(mail.times().expiry_time, expiry_time());
+    let scan = mail.attachment("scan.txt").unwrap();
+    assert_eq!(scan.data(), b"scanned letter\n");
+
+    assert!(db.find_entry(|e| e.title() == Some("Meta-Info")).is_none());
+}
+
+#[test]
+fn kdb_aes_sample_file() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::open(sample_file("kdb-aes128.kdb"))?;
+    assert_eq!(kdb.header().cipher(), Some(kdbx_rs::binary::Cipher::Aes256));
+    assert_eq!(kdb.header().key_rounds, 6000);
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+    assert_sample_file_contents(&db);
+
+    let result = kdb.unlock(&CompositeKey::from_password("wrong"));
+    assert!(matches!(result, Err(UnlockError::ContentHashInvalid)));
+    Ok(())
+}
+
+#[test]
+fn kdb_twofish_sample_file() -> Result<(), kdbx_rs::Error> {
+    let kdb = Kdb::open(sample_file("kdb-twofish.kdb"))?;
+    assert_eq!(
+        kdb.header().cipher(),
+        Some(kdbx_rs::binary::Cipher::TwoFish)
+    );
+    let db = kdb.unlock(&CompositeKey::from_password(PASSWORD))?;
+    assert_sample_file_contents(&db);
+    Ok(())
+}
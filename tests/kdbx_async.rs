@@ -0,0 +1,28 @@
+#![cfg(feature = "tokio")]
+
+use kdbx_rs::CompositeKey;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+#[tokio::test]
+async fn async_read_and_write() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+    let data = std::fs::read(file_path).unwrap();
+
+    let kdbx = kdbx_rs::from_async_reader(Cursor::new(data)).await?;
+    let key = CompositeKey::from_password("kdbxrs");
+    let unlocked = kdbx.unlock(&key)?;
+
+    let mut output = Cursor::new(Vec::new());
+    unlocked.write_async(&mut output).await?;
+    let reread = kdbx_rs::from_async_reader(Cursor::new(output.into_inner()))
+        .await?
+        .unlock(&key)?;
+    assert_eq!(reread.name(), unlocked.name());
+    assert_eq!(reread.root(), unlocked.root());
+    Ok(())
+}
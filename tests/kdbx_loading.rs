@@ -133,3 +133,134 @@ fn load_kdbx31_aes256() {
         }
     );
 }
+
+#[test]
+fn open_any_detects_kdbx() {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    match kdbx_rs::open_any(file_path).unwrap() {
+        kdbx_rs::binary::AnyDatabase::Kdbx(db) => assert_eq!(db.major_version(), 4),
+        kdbx_rs::binary::AnyDatabase::Kdb(_) => panic!("KDBX file detected as KDB"),
+    }
+}
+
+#[test]
+fn open_any_rejects_other_files() {
+    let result = kdbx_rs::binary::any_from_reader(&b"not a keepass file"[..]);
+    assert!(matches!(
+        result,
+        Err(kdbx_rs::errors::OpenError::NonKeepassFormat)
+    ));
+}
+
+#[test]
+fn detect_kdbx_formats() {
+    use kdbx_rs::binary::Format;
+
+    let cases = [
+        ("kdbx4-argon2d.kdbx", Format::Kdbx4 { minor: 0 }),
+        ("kdbx31-aes256.kdbx", Format::Kdbx3),
+    ];
+    for (file_name, expected) in cases.iter() {
+        let mut file_path = PathBuf::new();
+        file_path.push(env!("CARGO_MANIFEST_DIR"));
+        file_path.push("res");
+        file_path.push("test_input");
+        file_path.push(file_name);
+
+        let mut file = fs::File::open(&file_path).unwrap();
+        assert_eq!(kdbx_rs::detect_format(&mut file).unwrap(), *expected);
+
+        let data = fs::read(&file_path).unwrap();
+        let (format, data) = kdbx_rs::binary::detect_format_slice(&data).unwrap();
+        assert_eq!(format, *expected);
+        kdbx_rs::from_reader(data).unwrap();
+    }
+}
+
+#[cfg(all(unix, feature = "mmap"))]
+#[test]
+fn open_mmap_matches_from_reader() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    // SAFETY: test inputs are never modified while the tests run
+    let mapped = unsafe { kdbx_rs::open_mmap(&file_path)? };
+    let read = kdbx_rs::open(&file_path)?;
+
+    assert_eq!(mapped.major_version(), 4);
+    assert_eq!(mapped.header(), read.header());
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    let unlocked = mapped.unlock(&key)?;
+    assert_eq!(unlocked.root(), read.unlock(&key)?.root());
+    Ok(())
+}
+
+#[test]
+fn kdbx4_with_kdbx3_field_widths() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::OpenWarning;
+    use sha2::{Digest, Sha256};
+
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+    let original = fs::read(&file_path).unwrap();
+
+    // Re-encode the outer header with 2 byte field lengths
+    let mut header = original[0..12].to_vec();
+    let mut idx = 12;
+    loop {
+        let ty = original[idx];
+        let mut len_buf = [0u8; 4];
+        len_buf.copy_from_slice(&original[idx + 1..idx + 5]);
+        let len = u32::from_le_bytes(len_buf) as usize;
+        header.push(ty);
+        header.extend_from_slice(&(len as u16).to_le_bytes());
+        header.extend_from_slice(&original[idx + 5..idx + 5 + len]);
+        idx += 5 + len;
+        if ty == 0 {
+            break;
+        }
+    }
+    let mut mismatched = header.clone();
+    mismatched.extend_from_slice(&Sha256::digest(&header));
+    mismatched.extend_from_slice(&original[idx + 32..]);
+
+    let db = kdbx_rs::from_reader(&*mismatched)?;
+    assert_eq!(
+        db.warnings(),
+        &[OpenWarning::HeaderFieldWidth {
+            declared: 4,
+            used: 3
+        }]
+    );
+    assert_eq!(db.header(), kdbx_rs::open(&file_path)?.header());
+    Ok(())
+}
+
+#[test]
+fn truncated_header_fails() {
+    use kdbx_rs::errors::{HeaderError, OpenError};
+
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+    let original = fs::read(&file_path).unwrap();
+
+    let result = kdbx_rs::from_reader(&original[0..40]);
+    assert!(matches!(
+        result,
+        Err(OpenError::InvalidHeader(HeaderError::FieldOverrun))
+    ));
+}
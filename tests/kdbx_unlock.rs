@@ -30,3 +30,282 @@ fn kdbx4_argon2id() -> Result<(), kdbx_rs::Error> {
     let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
     Ok(db.unlock(&key).map(|_| ())?)
 }
+
+#[test]
+fn kdbx4_decrypted_len() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let file = fs::File::open(file_path).unwrap();
+
+    let db = kdbx_rs::from_reader(file).unwrap();
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    let len = db.decrypted_len(&key)?;
+    let unlocked = db.unlock(&key)?;
+    assert_eq!(unlocked.raw_xml().unwrap().len(), len);
+    Ok(())
+}
+
+#[test]
+fn kdbx31_decrypted_len() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx31-aes256.kdbx");
+
+    let file = fs::File::open(file_path).unwrap();
+
+    let db = kdbx_rs::from_reader(file).unwrap();
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    let len = db.decrypted_len(&key)?;
+    let unlocked = db.unlock(&key)?;
+    assert_eq!(unlocked.raw_xml().unwrap().len(), len);
+    Ok(())
+}
+
+#[test]
+fn kdbx4_unlock_with_password() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let db = kdbx_rs::open(&file_path)?;
+    let failed = db.unlock_with_password("wrong").unwrap_err();
+    Ok(failed.0.unlock_with_password("kdbxrs").map(|_| ())?)
+}
+
+#[test]
+fn kdbx4_kdf_memory_limit() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::{KdfParams, UnlockOptions};
+    use kdbx_rs::errors::{KeyGenerationError, UnlockError};
+
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let mut db = kdbx_rs::open(&file_path)?;
+    if let KdfParams::Argon2 { memory_bytes, .. } = &mut db.header_mut().kdf_params {
+        *memory_bytes = 8 * 1024 * 1024 * 1024;
+    }
+    let options = UnlockOptions {
+        kdf_memory_limit: Some(256 * 1024 * 1024),
+        ..Default::default()
+    };
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    let failed = db.unlock_with_options(&key, &options).unwrap_err();
+    assert!(matches!(
+        failed.1,
+        UnlockError::KeyGen(KeyGenerationError::MemoryLimitExceeded {
+            required: 8589934592,
+            limit: 268435456,
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn kdbx4_within_kdf_memory_limit() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let db = kdbx_rs::open(&file_path)?;
+    let options = kdbx_rs::binary::UnlockOptions {
+        kdf_memory_limit: Some(256 * 1024 * 1024),
+        ..Default::default()
+    };
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    Ok(db.unlock_with_options(&key, &options).map(|_| ())?)
+}
+
+#[test]
+fn kdbx4_within_decompressed_limit() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let db = kdbx_rs::open(&file_path)?;
+    let options = kdbx_rs::binary::UnlockOptions {
+        max_decompressed: Some(64 * 1024),
+        ..Default::default()
+    };
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    Ok(db.unlock_with_options(&key, &options).map(|_| ())?)
+}
+
+#[test]
+fn kdbx4_decompressed_limit_exceeded() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::{CompressionType, KdfParams, UnlockOptions};
+    use kdbx_rs::database::Entry;
+    use kdbx_rs::errors::UnlockError;
+
+    let mut db = kdbx_rs::Database::default();
+    for _ in 0..1000 {
+        let mut entry = Entry::default();
+        entry.set_title("Highly compressible entry title");
+        db.add_entry(entry);
+    }
+    let mut kdbx = kdbx_rs::Kdbx::from_database(db);
+    kdbx.header_mut().compression_type = CompressionType::Gzip;
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    kdbx.set_key(kdbx_rs::CompositeKey::from_password("kdbxrs"))?;
+    let mut output = Vec::new();
+    kdbx.write(&mut output)?;
+
+    let options = UnlockOptions {
+        max_decompressed: Some(output.len()),
+        ..Default::default()
+    };
+    let failed = kdbx_rs::from_reader(&*output)?
+        .unlock_with_options(&key, &options)
+        .unwrap_err();
+    assert!(matches!(failed.1, UnlockError::DecompressedTooLarge(_)));
+    Ok(())
+}
+
+#[test]
+fn kdbx4_records_unlock_duration() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let db = kdbx_rs::open(&file_path)?;
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    let unlocked = db.unlock(&key)?;
+    let duration = unlocked.last_unlock_duration().unwrap();
+    assert!(duration > std::time::Duration::ZERO);
+    assert!(kdbx_rs::Kdbx::from_database(kdbx_rs::Database::default())
+        .last_unlock_duration()
+        .is_none());
+    Ok(())
+}
+
+fn assert_lazy_matches_eager(file_name: &str) -> Result<(), kdbx_rs::Error> {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push(file_name);
+
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    let eager = kdbx_rs::open(&file_path)?.unlock(&key)?;
+    let options = kdbx_rs::binary::UnlockOptions {
+        parse_options: kdbx_rs::xml::ParseOptions {
+            lazy_protected: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let lazy = kdbx_rs::open(&file_path)?.unlock_with_options(&key, &options)?;
+
+    let eager_entries: Vec<_> = eager.iter_entries().collect();
+    let lazy_entries: Vec<_> = lazy.iter_entries().collect();
+    assert_eq!(eager_entries.len(), lazy_entries.len());
+    for ((_, eager_entry), (_, lazy_entry)) in eager_entries.iter().zip(lazy_entries.iter()) {
+        for (eager_field, lazy_field) in eager_entry.fields().zip(lazy_entry.fields()) {
+            assert_eq!(eager_field.key(), lazy_field.key());
+            assert_eq!(eager_field.protected(), lazy_field.protected());
+            assert_eq!(eager_field.value(), lazy_field.reveal().as_deref());
+        }
+    }
+
+    let (_, entry) = &lazy_entries[0];
+    assert_eq!(entry.password(), None);
+    let password = entry.fields().find(|f| f.key() == "Password").unwrap();
+    assert_eq!(password.reveal().as_deref(), eager_entries[0].1.password());
+    Ok(())
+}
+
+#[test]
+fn lazy_protected_values_match_eager() -> Result<(), kdbx_rs::Error> {
+    assert_lazy_matches_eager("kdbx4-argon2d.kdbx")
+}
+
+#[test]
+fn lazy_protected_values_match_eager_salsa20() -> Result<(), kdbx_rs::Error> {
+    assert_lazy_matches_eager("kdbx31-aes256.kdbx")
+}
+
+#[test]
+fn unlock_into_reuses_scratch_buffer() -> Result<(), kdbx_rs::Error> {
+    let mut scratch = Vec::new();
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    for file_name in &["kdbx4-argon2d.kdbx", "kdbx31-aes256.kdbx"] {
+        let mut file_path = PathBuf::new();
+        file_path.push(env!("CARGO_MANIFEST_DIR"));
+        file_path.push("res");
+        file_path.push("test_input");
+        file_path.push(file_name);
+
+        let expected = kdbx_rs::open(&file_path)?.unlock(&key)?;
+        let unlocked = kdbx_rs::open(&file_path)?.unlock_into(&key, &mut scratch)?;
+        assert_eq!(unlocked.root(), expected.root());
+        assert_eq!(Some(scratch.as_slice()), expected.raw_xml());
+        assert!(unlocked.raw_xml().is_none());
+    }
+    Ok(())
+}
+
+#[test]
+fn lazy_protected_values_in_reports() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::database::{Entry, Otp};
+
+    let mut db = kdbx_rs::Database::default();
+    for title in &["First", "Second"] {
+        let mut entry = Entry::default();
+        entry.set_title(*title);
+        entry.set_password("shared-secret");
+        entry.set_otp(Otp::new("JBSWY3DPEHPK3PXP", 30, 6));
+        db.add_entry(entry);
+    }
+    let mut kdbx = kdbx_rs::Kdbx::from_database(db);
+    kdbx.set_kdf_params(kdbx_rs::binary::KdfParams::aes(1000));
+    let key = kdbx_rs::CompositeKey::from_password("kdbxrs");
+    kdbx.set_key(kdbx_rs::CompositeKey::from_password("kdbxrs"))?;
+    let bytes = kdbx.to_bytes()?;
+
+    let options = kdbx_rs::binary::UnlockOptions {
+        parse_options: kdbx_rs::xml::ParseOptions {
+            lazy_protected: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let lazy = kdbx_rs::from_bytes(&bytes)?.unlock_with_options(&key, &options)?;
+    let entry = lazy.root().entries().next().unwrap();
+    assert_eq!(entry.password(), None);
+    assert_eq!(entry.reveal("Password").as_deref(), Some("shared-secret"));
+    assert_eq!(entry.otp().unwrap().secret(), Some("JBSWY3DPEHPK3PXP"));
+
+    assert!(lazy.entries_missing(&["Password", "otp"]).is_empty());
+    let health = lazy.entry_health();
+    assert_eq!(health.len(), 2);
+    for report in &health {
+        assert!(report.reused);
+        assert!(report.entropy > 0.0);
+    }
+    Ok(())
+}
@@ -16,3 +16,228 @@ fn kdbx4_argon2d_key_file() -> Result<(), kdbx_rs::Error> {
     let key = kdbx_rs::CompositeKey::new(Some(String::from("kdbxrs")), key_file);
     Ok(db.unlock(&key).map(|_| ())?)
 }
+
+#[test]
+fn xml_key_file_uses_key_data() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::KdfParams;
+    use kdbx_rs::{CompositeKey, Database, Kdbx};
+
+    let v1_key_file = br#"<?xml version="1.0" encoding="utf-8"?>
+<KeyFile>
+    <Meta><Version>1.00</Version></Meta>
+    <Key><Data>ChssPU5fYHGCk6S1xtfo+QESIzRFVmd4iZqrvM3e7/A=</Data></Key>
+</KeyFile>"#;
+    let v2_key_file = br#"<?xml version="1.0" encoding="utf-8"?>
+<KeyFile>
+    <Meta><Version>2.0</Version></Meta>
+    <Key>
+        <Data>
+            0A1B2C3D 4E5F6071 8293A4B5 C6D7E8F9
+            01122334 45566778 899AABBC CDDEEFF0
+        </Data>
+    </Key>
+</KeyFile>"#;
+
+    let mut kdbx = Kdbx::from_database(Database::default());
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    kdbx.set_key(CompositeKey::new(None, Some(v2_key_file.to_vec())))?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?;
+    let key = CompositeKey::new(None, Some(v1_key_file.to_vec()));
+    Ok(reparsed.unlock(&key).map(|_| ())?)
+}
+
+#[test]
+fn generated_key_file_only() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::KdfParams;
+    use kdbx_rs::{CompositeKey, Database, Kdbx};
+    use rand::{rngs::OsRng, RngCore};
+
+    let mut key_file = vec![0u8; 32];
+    OsRng.fill_bytes(&mut key_file);
+
+    let mut db = Database::default();
+    db.set_name("Key file only");
+    let mut kdbx = Kdbx::from_database(db);
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    kdbx.set_key(CompositeKey::new(None, Some(key_file.clone())))?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+
+    let with_password = CompositeKey::new(Some(String::new()), Some(key_file.clone()));
+    assert!(kdbx_rs::from_reader(&*output_buf)?
+        .unlock(&with_password)
+        .is_err());
+
+    let key = CompositeKey::new(None, Some(key_file));
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key)?;
+    assert_eq!(unlocked.name(), "Key file only");
+    Ok(())
+}
+
+#[test]
+fn precomputed_challenge_response() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::KdfParams;
+    use kdbx_rs::errors::{KeyGenerationError, UnlockError};
+    use kdbx_rs::{CompositeKey, Database, Kdbx};
+
+    let response = [0x5a; 20];
+    let mut kdbx = Kdbx::from_database(Database::default());
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    // KDBX 4 databases challenge the token with the KDF salt
+    let seed = match &kdbx.header().kdf_params {
+        KdfParams::Argon2 { salt, .. } => salt.clone(),
+        _ => unreachable!(),
+    };
+    kdbx.set_key(
+        CompositeKey::from_password("kdbxrs").with_precomputed_response(&seed, &response),
+    )?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+
+    let key = CompositeKey::from_password("kdbxrs").with_precomputed_response(&seed, &response);
+    kdbx_rs::from_reader(&*output_buf)?.unlock(&key)?;
+
+    let without_response = CompositeKey::from_password("kdbxrs");
+    assert!(kdbx_rs::from_reader(&*output_buf)?
+        .unlock(&without_response)
+        .is_err());
+
+    let wrong_seed =
+        CompositeKey::from_password("kdbxrs").with_precomputed_response(&[0; 32], &response);
+    let failed = kdbx_rs::from_reader(&*output_buf)?
+        .unlock(&wrong_seed)
+        .unwrap_err();
+    assert!(matches!(
+        failed.1,
+        UnlockError::KeyGen(KeyGenerationError::ChallengeSeedMismatch)
+    ));
+    Ok(())
+}
+
+#[test]
+fn unlock_with_keyfile_path() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::errors::UnlockError;
+
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d-key-file.kdbx");
+    let key_file_path =
+        std::env::temp_dir().join(format!("kdbx-rs-key-file-{}", std::process::id()));
+    fs::write(&key_file_path, b"key-file").unwrap();
+
+    let unlocked = kdbx_rs::open(&file_path)?.unlock_with_keyfile(Some("kdbxrs"), &key_file_path);
+    fs::remove_file(&key_file_path).unwrap();
+    unlocked?;
+
+    let failed = kdbx_rs::open(&file_path)?
+        .unlock_with_keyfile(Some("kdbxrs"), &key_file_path)
+        .unwrap_err();
+    assert!(matches!(failed.1, UnlockError::KeyFile(_)));
+    Ok(())
+}
+
+#[test]
+fn xml_key_file_hash_length() {
+    use kdbx_rs::errors::KeyGenerationError;
+    use kdbx_rs::{CompositeKey, Database, Kdbx};
+
+    let key_file_with_hash = |hash: &str| {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<KeyFile>
+    <Meta><Version>2.0</Version></Meta>
+    <Key>
+        <Data Hash="{}">
+            0A1B2C3D 4E5F6071 8293A4B5 C6D7E8F9
+            01122334 45566778 899AABBC CDDEEFF0
+        </Data>
+    </Key>
+</KeyFile>"#,
+            hash
+        )
+    };
+    let mut kdbx = Kdbx::from_database(Database::default());
+    for hash in ["", &"00".repeat(33)] {
+        let key = CompositeKey::new(None, Some(key_file_with_hash(hash).into_bytes()));
+        let result = kdbx.set_key(key);
+        assert!(
+            matches!(result, Err(KeyGenerationError::InvalidKeyFile(_))),
+            "hash {:?} accepted",
+            hash
+        );
+    }
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn challenge_response_sample(file_name: &str, seed: &str) -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::CompositeKey;
+
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push(file_name);
+
+    let seed = from_hex(seed);
+    let response = from_hex("3b7e2a9c41d05f86e1a4c7290b5d8e3f6a1c9b72");
+    let key = CompositeKey::from_password("kdbxrs").with_precomputed_response(&seed, &response);
+    let db = kdbx_rs::open(&file_path)?.unlock(&key)?;
+    let entry = db.find_entry(|e| e.title() == Some("Token Entry")).unwrap();
+    assert_eq!(entry.password(), Some("letmein"));
+
+    assert!(kdbx_rs::open(&file_path)?
+        .unlock(&CompositeKey::from_password("kdbxrs"))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn kdbx31_challenge_response_uses_master_seed() -> Result<(), kdbx_rs::Error> {
+    challenge_response_sample(
+        "kdbx31-challenge-response.kdbx",
+        "1f2e3d4c5b6a79880f1e2d3c4b5a69781f2e3d4c5b6a79880f1e2d3c4b5a6978",
+    )
+}
+
+#[test]
+fn kdbx4_challenge_response_uses_kdf_seed() -> Result<(), kdbx_rs::Error> {
+    challenge_response_sample(
+        "kdbx4-challenge-response.kdbx",
+        "8f7e6d5c4b3a29180716f5e4d3c2b1a08f7e6d5c4b3a29180716f5e4d3c2b1a0",
+    )
+}
@@ -21,3 +21,37 @@ fn keepassxc_otp_read_secret() -> Result<(), kdbx_rs::Error> {
 
     Ok(())
 }
+
+#[test]
+fn bare_base32_secret() {
+    let mut entry = kdbx_rs::database::Entry::default();
+    entry.add_field(kdbx_rs::database::Field::new_protected(
+        "otp",
+        "JBSWY3DPEHPK3PXP",
+    ));
+    let otp = entry.otp().unwrap();
+    assert_eq!(otp.secret(), Some("JBSWY3DPEHPK3PXP"));
+    assert_eq!(otp.period(), Some(30));
+    assert_eq!(otp.digits(), Some(6));
+}
+
+#[test]
+fn bare_base32_secret_with_spaces() {
+    let mut entry = kdbx_rs::database::Entry::default();
+    entry.add_field(kdbx_rs::database::Field::new_protected(
+        "otp",
+        "JBSW Y3DP EHPK 3PXP",
+    ));
+    let otp = entry.otp().unwrap();
+    assert_eq!(otp.secret(), Some("JBSW Y3DP EHPK 3PXP"));
+    assert_eq!(otp.period(), Some(30));
+    assert_eq!(otp.digits(), Some(6));
+}
+
+#[test]
+fn otpauth_url_params() {
+    let otp = kdbx_rs::database::Otp::new("JBSWY3DPEHPK3PXP", 60, 8);
+    assert_eq!(otp.secret(), Some("JBSWY3DPEHPK3PXP"));
+    assert_eq!(otp.period(), Some(60));
+    assert_eq!(otp.digits(), Some(8));
+}
@@ -1,4 +1,4 @@
-use kdbx_rs::binary::KdfParams;
+use kdbx_rs::binary::{Cipher, CompressionType, KdfParams};
 use kdbx_rs::database::{Entry, Field, Group};
 use kdbx_rs::{self, CompositeKey, Kdbx};
 
@@ -91,3 +91,1102 @@ fn round_trip_argon2id() -> Result<(), kdbx_rs::Error> {
 
     Ok(())
 }
+
+#[test]
+fn argon2id_kdf_uuid_written() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::KdfAlgorithm;
+
+    let argon2id_uuid = uuid::Uuid::parse_str("9e298b19-56db-4773-b23d-fc3ec6f0a1e6").unwrap();
+    let aes_uuid = uuid::Uuid::parse_str("c9d9f39a-628a-4460-bf74-0d08c18a4fea").unwrap();
+    let mut kdbx = Kdbx::from_database(kdbx_rs::Database::default());
+    kdbx.set_kdf_params(KdfParams::argon2(1, 1, 1, argon2::Variant::Argon2id));
+    kdbx.set_key(key())?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+
+    let contains = |needle: &[u8]| output_buf.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(argon2id_uuid.as_bytes()));
+    assert!(!contains(aes_uuid.as_bytes()));
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?;
+    assert_eq!(
+        reparsed.header().kdf_params.algorithm(),
+        KdfAlgorithm::Argon2id
+    );
+    Ok(())
+}
+
+#[test]
+fn round_trip_custom_kdf_params() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = Kdbx::from_database(kdbx_rs::Database::default());
+    kdbx.set_kdf_params(KdfParams::argon2(19, 3, 1, argon2::Variant::Argon2id));
+
+    let mut output_buf = Vec::new();
+    assert!(kdbx.write(&mut output_buf).is_err());
+    kdbx.set_key(key())?;
+    kdbx.write(&mut output_buf)?;
+
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?;
+    match &reparsed.header().kdf_params {
+        KdfParams::Argon2 {
+            memory_bytes,
+            iterations,
+            lanes,
+            variant,
+            ..
+        } => {
+            assert_eq!(*memory_bytes, 19 * 1024 * 1024);
+            assert_eq!(*iterations, 3);
+            assert_eq!(*lanes, 1);
+            assert_eq!(*variant, argon2::Variant::Argon2id);
+        }
+        other => panic!("Unexpected KDF params {:?}", other),
+    }
+    reparsed.unlock(&key())?;
+    Ok(())
+}
+
+fn assert_benchmarked(variant: argon2::Variant) {
+    let target = std::time::Duration::from_millis(50);
+    match KdfParams::benchmark_argon2(1, 1, target, variant) {
+        KdfParams::Argon2 {
+            memory_bytes,
+            iterations,
+            lanes,
+            variant: benchmarked_variant,
+            ..
+        } => {
+            assert_eq!(memory_bytes, 1024 * 1024);
+            assert_eq!(lanes, 1);
+            assert_eq!(benchmarked_variant, variant);
+            assert!(iterations >= 1);
+            assert!(iterations <= KdfParams::MAX_BENCHMARK_ITERATIONS);
+        }
+        other => panic!("Unexpected KDF params {:?}", other),
+    }
+}
+
+#[test]
+fn benchmark_argon2d() {
+    assert_benchmarked(argon2::Variant::Argon2d);
+}
+
+#[test]
+fn benchmark_argon2id() {
+    assert_benchmarked(argon2::Variant::Argon2id);
+}
+
+#[test]
+fn benchmark_zero_target_uses_one_iteration() {
+    let params = KdfParams::benchmark_argon2(
+        1,
+        1,
+        std::time::Duration::from_secs(0),
+        argon2::Variant::Argon2id,
+    );
+    assert!(matches!(params, KdfParams::Argon2 { iterations: 1, .. }));
+}
+
+#[test]
+fn change_key_regenerates_seeds() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = std::path::PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let mut kdbx = kdbx_rs::open(&file_path)?.unlock_with_password("kdbxrs")?;
+    let old_seed = kdbx.header().master_seed.clone();
+    let old_iv = kdbx.header().encryption_iv.clone();
+    kdbx.change_key(CompositeKey::from_password("changed"))?;
+    assert_ne!(kdbx.header().master_seed, old_seed);
+    assert_ne!(kdbx.header().encryption_iv, old_iv);
+    assert!(kdbx.meta().master_key_changed.is_some());
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?;
+    let failed = reparsed.unlock_with_password("kdbxrs").unwrap_err();
+    let unlocked = failed.0.unlock_with_password("changed")?;
+    assert_eq!(unlocked.root(), kdbx.root());
+    Ok(())
+}
+
+#[test]
+fn change_key_failure_keeps_old_key() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let old_seed = kdbx.header().master_seed.clone();
+    let old_kdf = kdbx.header().kdf_params.clone();
+    let invalid_key_file = br#"<KeyFile><Meta><Version>2.0</Version></Meta>
+<Key><Data Hash="">0A1B2C3D</Data></Key></KeyFile>"#;
+    let result = kdbx.change_key(CompositeKey::new(None, Some(invalid_key_file.to_vec())));
+    assert!(result.is_err());
+    assert_eq!(kdbx.header().master_seed, old_seed);
+    assert_eq!(kdbx.header().kdf_params, old_kdf);
+    assert_eq!(kdbx.meta().master_key_changed, None);
+
+    let reparsed = kdbx_rs::from_bytes(&kdbx.to_bytes()?)?;
+    let unlocked = reparsed.unlock(&key())?;
+    assert_eq!(unlocked.root(), kdbx.root());
+    Ok(())
+}
+
+#[test]
+fn change_key_to_precomputed_response() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let seed = [7u8; 32];
+    let response = [9u8; 20];
+    let token_key =
+        || CompositeKey::from_password("kdbxrs").with_precomputed_response(&seed, &response);
+    kdbx.change_key(token_key())?;
+    assert!(matches!(&kdbx.header().kdf_params, KdfParams::Argon2 { salt, .. } if salt == &seed));
+    assert!(kdbx.meta().master_key_changed.is_some());
+
+    let reparsed = kdbx_rs::from_bytes(&kdbx.to_bytes()?)?;
+    let unlocked = reparsed.unlock(&token_key())?;
+    assert_eq!(unlocked.root(), kdbx.root());
+    Ok(())
+}
+
+#[test]
+fn round_trip_gzip() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = Kdbx::from_database(kdbx_rs::Database::default());
+    kdbx.header_mut().compression_type = kdbx_rs::binary::CompressionType::Gzip;
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    kdbx.set_key(key())?;
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.root(), kdbx.root());
+    Ok(())
+}
+
+#[test]
+fn history_keeps_version_times() -> Result<(), kdbx_rs::Error> {
+    use chrono::NaiveDate;
+
+    let mut file_path = std::path::PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+
+    let kdbx = kdbx_rs::open(&file_path)?.unlock_with_password("kdbxrs")?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?.unlock_with_password("kdbxrs")?;
+
+    let entry = reparsed.root().entries().next().unwrap();
+    let version_modified = NaiveDate::from_ymd_opt(2020, 5, 16)
+        .unwrap()
+        .and_hms_opt(18, 26, 42)
+        .unwrap();
+    let current_modified = NaiveDate::from_ymd_opt(2020, 5, 18)
+        .unwrap()
+        .and_hms_opt(23, 6, 4)
+        .unwrap();
+    assert_eq!(
+        entry.history()[0].times().last_modification_time,
+        version_modified
+    );
+    assert_eq!(entry.times().last_modification_time, current_modified);
+    Ok(())
+}
+
+#[test]
+fn new_version_keeps_old_modification_time() {
+    use chrono::NaiveDate;
+
+    let old_modified = NaiveDate::from_ymd_opt(2020, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let mut entry = Entry::default();
+    entry.set_password("old");
+    entry.times_mut().last_modification_time = old_modified;
+    entry.new_version();
+    entry.set_password("new");
+
+    assert_eq!(
+        entry.history()[0].times().last_modification_time,
+        old_modified
+    );
+    assert_eq!(entry.history()[0].password(), Some("old"));
+    assert!(entry.times().last_modification_time > old_modified);
+}
+
+fn fast_kdbx() -> Result<Kdbx<kdbx_rs::binary::Unlocked>, kdbx_rs::Error> {
+    let mut db = kdbx_rs::Database::default();
+    db.set_name(DATABASE_NAME);
+    let mut kdbx = Kdbx::from_database(db);
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    kdbx.set_key(key())?;
+    Ok(kdbx)
+}
+
+#[test]
+fn save_with_options() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::SaveOptions;
+
+    let kdbx = fast_kdbx()?;
+    let dir = std::env::temp_dir().join(format!("kdbx-rs-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let option_sets = [
+        SaveOptions::default(),
+        SaveOptions {
+            fsync: false,
+            atomic: false,
+        },
+    ];
+    for (idx, options) in option_sets.iter().enumerate() {
+        let path = dir.join(format!("saved-{}.kdbx", idx));
+        kdbx.save_with_options(&path, options)?;
+        // Saving again replaces the existing file
+        kdbx.save_with_options(&path, options)?;
+
+        let reopened = kdbx_rs::open(&path)?.unlock(&key())?;
+        assert_eq!(reopened.name(), DATABASE_NAME);
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), idx + 1);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // New files from atomic saves are only readable by their owner
+        let mode = std::fs::metadata(dir.join("saved-0.kdbx"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    Ok(())
+}
+
+#[test]
+fn round_trip_kdbx3() -> Result<(), kdbx_rs::Error> {
+    let mut db = kdbx_rs::Database::default();
+    db.set_name(DATABASE_NAME.to_string());
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.set_password(ENTRY_PASSWORD);
+    db.add_entry(entry);
+    let mut kdbx = Kdbx::from_database(db);
+    kdbx.set_major_version(3);
+    kdbx.set_kdf_params(KdfParams::aes(1000));
+    kdbx.set_key(key())?;
+
+    for compression in [CompressionType::None, CompressionType::Gzip] {
+        kdbx.header_mut().compression_type = compression;
+        let mut output_buf = Vec::new();
+        kdbx.write(&mut output_buf)?;
+
+        let reparsed = kdbx_rs::from_reader(&*output_buf)?;
+        assert_eq!(reparsed.major_version(), 3);
+        assert_eq!(reparsed.minor_version(), 1);
+        let unlocked = reparsed.unlock(&key())?;
+        assert_eq!(unlocked.meta().database_name, DATABASE_NAME);
+        let entry = unlocked.find_entry(|e| e.title() == Some(ENTRY_NAME));
+        assert_eq!(entry.unwrap().password(), Some(ENTRY_PASSWORD));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn round_trip_kdbx3_datetimes() -> Result<(), kdbx_rs::Error> {
+    let expiry = chrono::NaiveDate::from_ymd_opt(2021, 3, 4)
+        .unwrap()
+        .and_hms_opt(5, 6, 7)
+        .unwrap();
+    let mut db = kdbx_rs::Database::default();
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.times_mut().expires = true;
+    entry.times_mut().expiry_time = expiry;
+    db.add_entry(entry);
+    let mut kdbx = Kdbx::from_database(db);
+    kdbx.set_major_version(3);
+    kdbx.set_kdf_params(KdfParams::aes(1000));
+    kdbx.set_key(key())?;
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let xml = String::from_utf8(unlocked.raw_xml().unwrap().to_vec()).unwrap();
+    assert!(xml.contains("<ExpiryTime>2021-03-04T05:06:07Z</ExpiryTime>"));
+    let entry = unlocked
+        .find_entry(|e| e.title() == Some(ENTRY_NAME))
+        .unwrap();
+    assert_eq!(entry.times().expiry_time, expiry);
+    Ok(())
+}
+
+#[test]
+fn kdbx3_protected_attachments() -> Result<(), kdbx_rs::Error> {
+    let mut db = kdbx_rs::Database::default();
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.set_password(ENTRY_PASSWORD);
+    entry.add_attachment("secret.txt", b"protected contents".to_vec());
+    entry
+        .attachment_mut("secret.txt")
+        .unwrap()
+        .set_protected(true);
+    entry.add_attachment("plain.txt", b"plain contents".to_vec());
+    db.add_entry(entry);
+    let mut kdbx = Kdbx::from_database(db);
+    kdbx.set_major_version(3);
+    kdbx.set_kdf_params(KdfParams::aes(1000));
+    kdbx.set_key(key())?;
+
+    let unlocked = kdbx_rs::from_bytes(&kdbx.to_bytes()?)?.unlock(&key())?;
+    let xml = String::from_utf8(unlocked.raw_xml().unwrap().to_vec()).unwrap();
+    assert!(xml.contains(r#"Protected="True""#));
+    assert!(!xml.contains("cHJvdGVjdGVkIGNvbnRlbnRz"));
+    let entry = unlocked
+        .find_entry(|e| e.title() == Some(ENTRY_NAME))
+        .unwrap();
+    let attachments: Vec<_> = entry
+        .attachments()
+        .map(|a| (a.name(), a.data(), a.protected()))
+        .collect();
+    assert_eq!(
+        attachments,
+        vec![
+            ("secret.txt", &b"protected contents"[..], true),
+            ("plain.txt", &b"plain contents"[..], false),
+        ]
+    );
+    assert_eq!(entry.password(), Some(ENTRY_PASSWORD));
+    Ok(())
+}
+
+#[test]
+fn kdbx3_incompatible_settings() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::KdfAlgorithm;
+    use kdbx_rs::errors::WriteError;
+
+    let mut kdbx = fast_kdbx()?;
+    let incompatibility = kdbx.header().is_v3_compatible().unwrap_err();
+    assert_eq!(incompatibility.kdf, Some(KdfAlgorithm::Argon2d));
+    assert_eq!(incompatibility.cipher, None);
+
+    kdbx.header_mut().cipher = Cipher::ChaCha20;
+    kdbx.set_major_version(3);
+    kdbx.set_kdf_params(KdfParams::aes(1000));
+    kdbx.set_key(key())?;
+    let incompatibility = kdbx.header().is_v3_compatible().unwrap_err();
+    assert_eq!(incompatibility.cipher, Some(Cipher::ChaCha20));
+    assert_eq!(incompatibility.kdf, None);
+    let result = kdbx.write(&mut Vec::new());
+    assert!(matches!(result, Err(WriteError::Incompatible(_))));
+
+    kdbx.header_mut().cipher = Cipher::Aes256;
+    assert!(kdbx.header().is_v3_compatible().is_ok());
+    Ok(())
+}
+
+#[test]
+fn roundtrip_check() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.set_password(ENTRY_PASSWORD);
+    entry.add_attachment("notes.txt", b"attached".to_vec());
+    entry.new_version();
+    let uuid = entry.uuid();
+    kdbx.add_group(Group::new(GROUP_NAME));
+    kdbx.add_entry(entry);
+    kdbx.roundtrip_check()?;
+
+    // Sub-second times are not stored in the file
+    let times = kdbx.entry_by_uuid_mut(uuid).unwrap().times_mut();
+    times.last_access_time += chrono::Duration::milliseconds(500);
+    match kdbx.roundtrip_check() {
+        Err(kdbx_rs::Error::RoundTripMismatch(difference)) => {
+            assert_eq!(difference, format!("entry {} differs", uuid))
+        }
+        other => panic!("Expected mismatch, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn rewrite_kdbx31() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = std::path::PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx31-aes256.kdbx");
+    let key = CompositeKey::from_password("kdbxrs");
+
+    let locked = kdbx_rs::open(&file_path)?;
+    let mut locked_buf = Vec::new();
+    locked.write(&mut locked_buf)?;
+
+    let unlocked = kdbx_rs::from_reader(&*locked_buf)?.unlock(&key)?;
+    let mut output_buf = Vec::new();
+    unlocked.write(&mut output_buf)?;
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?.unlock(&key)?;
+    assert_eq!(reparsed.root(), unlocked.root());
+
+    Ok(())
+}
+
+#[test]
+fn rewrap_edited_xml() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = std::path::PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+    let original = kdbx_rs::open(&file_path)?.unlock_with_password("kdbxrs")?;
+
+    let edited = String::from_utf8(original.raw_xml().unwrap().to_vec())
+        .unwrap()
+        .replace("<Name>Root</Name>", "<Name>Edited</Name>");
+    assert!(edited.contains("<Name>Edited</Name>"));
+
+    let mut kdbx = original.rewrap_raw_xml(edited.as_bytes())?;
+    kdbx.set_kdf_params(KdfParams::aes(1000));
+    kdbx.set_key(key())?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let reloaded = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(reloaded.root().name(), "Edited");
+    let entry = original.root().entries().next().unwrap();
+    let reloaded_entry = reloaded.entry_by_uuid(entry.uuid()).unwrap();
+    assert_eq!(reloaded_entry.password(), entry.password());
+    Ok(())
+}
+
+#[test]
+fn from_decrypted_xml() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::utils::NullStreamCipher;
+
+    let db = kdbx_rs::database::doc_sample_db();
+    let mut xml = Vec::new();
+    kdbx_rs::xml::write_xml(&mut xml, &db, &mut NullStreamCipher)?;
+    let mut kdbx = Kdbx::from_xml_bytes(&xml)?;
+    kdbx.set_kdf_params(KdfParams::aes(1000));
+    kdbx.set_key(key())?;
+    let reloaded = kdbx_rs::from_bytes(&kdbx.to_bytes()?)?.unlock(&key())?;
+    assert_eq!(reloaded.root(), db.root());
+    Ok(())
+}
+
+fn assert_cipher_round_trip(cipher: Cipher, iv_len: usize) -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.set_password(ENTRY_PASSWORD);
+    kdbx.add_entry(entry);
+    kdbx.set_cipher(cipher);
+    assert_eq!(kdbx.header().encryption_iv.len(), iv_len);
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+
+    let reparsed = kdbx_rs::from_reader(&*output_buf)?;
+    assert_eq!(reparsed.header().cipher, cipher);
+    let unlocked = reparsed.unlock(&key())?;
+    assert_eq!(unlocked.root(), kdbx.root());
+    Ok(())
+}
+
+#[test]
+fn round_trip_chacha20() -> Result<(), kdbx_rs::Error> {
+    assert_cipher_round_trip(Cipher::ChaCha20, 12)
+}
+
+#[test]
+fn round_trip_twofish() -> Result<(), kdbx_rs::Error> {
+    assert_cipher_round_trip(Cipher::TwoFish, 16)
+}
+
+#[test]
+fn chacha20_wrong_iv_len_fails() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    kdbx.header_mut().cipher = Cipher::ChaCha20;
+    kdbx.header_mut().encryption_iv = vec![0u8; 16];
+    let mut output_buf = Vec::new();
+    let result = kdbx.write(&mut output_buf);
+    assert!(matches!(
+        result,
+        Err(kdbx_rs::errors::WriteError::Cipher(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn round_trip_recycle_bin() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    let uuid = entry.uuid();
+    kdbx.add_entry(entry);
+    kdbx.recycle(uuid);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(
+        unlocked.meta().recycle_bin_uuid,
+        kdbx.meta().recycle_bin_uuid
+    );
+    assert_eq!(
+        unlocked.meta().recycle_bin_changed,
+        kdbx.meta().recycle_bin_changed
+    );
+    let bin = unlocked.recycle_bin().unwrap();
+    assert_eq!(bin.entries().next().unwrap().uuid(), uuid);
+    Ok(())
+}
+
+#[test]
+fn round_trip_meta_custom_data_times() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::database::CustomDataItem;
+
+    let mut kdbx = fast_kdbx()?;
+    let modified = chrono::NaiveDate::from_ymd_opt(2021, 3, 4)
+        .unwrap()
+        .and_hms_opt(5, 6, 7)
+        .unwrap();
+    kdbx.meta_mut().custom_data.push(CustomDataItem {
+        field: Field::new("timed", "value"),
+        last_modification_time: Some(modified),
+    });
+    kdbx.meta_mut()
+        .custom_data
+        .push(Field::new("untimed", "value").into());
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.meta().custom_data, kdbx.meta().custom_data);
+    Ok(())
+}
+
+#[test]
+fn round_trip_meta_change_times() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let old = chrono::NaiveDate::from_ymd_opt(2019, 1, 2)
+        .unwrap()
+        .and_hms_opt(3, 4, 5)
+        .unwrap();
+    let meta = kdbx.meta_mut();
+    meta.database_name_changed = Some(old);
+    meta.default_user_name_changed = Some(old);
+    meta.master_key_changed = Some(old);
+    meta.settings_changed = Some(old);
+    kdbx.set_name(DATABASE_NAME);
+    assert!(kdbx.meta().database_name_changed.unwrap() > old);
+    assert_eq!(kdbx.meta().database_description_changed, None);
+    kdbx.set_description(DATABASE_DESC);
+    assert!(kdbx.meta().database_description_changed.is_some());
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let meta = unlocked.meta();
+    assert_eq!(
+        meta.database_name_changed,
+        kdbx.meta().database_name_changed
+    );
+    assert_eq!(
+        meta.database_description_changed,
+        kdbx.meta().database_description_changed
+    );
+    assert_eq!(meta.default_user_name_changed, Some(old));
+    assert_eq!(meta.master_key_changed, Some(old));
+    assert_eq!(meta.settings_changed, Some(old));
+    Ok(())
+}
+
+#[test]
+fn round_trip_default_username() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    assert_eq!(kdbx.new_entry().username(), None);
+    kdbx.meta_mut().set_default_username("alice");
+    assert!(kdbx.meta().default_user_name_changed.is_some());
+    assert_eq!(kdbx.new_entry().username(), Some("alice"));
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.meta().default_username, "alice");
+    Ok(())
+}
+
+#[test]
+fn round_trip_entry_and_group_custom_data() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::database::CustomDataItem;
+
+    let mut kdbx = fast_kdbx()?;
+    let modified = chrono::NaiveDate::from_ymd_opt(2022, 1, 2)
+        .unwrap()
+        .and_hms_opt(3, 4, 5)
+        .unwrap();
+    let plugin_item = CustomDataItem {
+        field: Field::new("KPXC_BROWSER_SETTINGS", "{}"),
+        last_modification_time: Some(modified),
+    };
+    let mut entry = Entry::default();
+    entry.custom_data_mut().push(plugin_item.clone());
+    entry
+        .custom_data_mut()
+        .push(Field::new("untimed", "value").into());
+    let entry_uuid = entry.uuid();
+    let mut group = Group::new(GROUP_NAME);
+    group.custom_data_mut().push(plugin_item.clone());
+    group.add_entry(entry);
+    kdbx.add_group(group);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let entry = unlocked.entry_by_uuid(entry_uuid).unwrap();
+    assert_eq!(entry.custom_data()[0], plugin_item);
+    assert_eq!(entry.custom_data()[1].last_modification_time, None);
+    let group = unlocked.find_group(|g| g.name() == GROUP_NAME).unwrap();
+    assert_eq!(group.custom_data(), &[plugin_item]);
+    assert!(unlocked.meta().custom_data.is_empty());
+    Ok(())
+}
+
+#[test]
+fn round_trip_generator() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.generator(), "kdbx-rs");
+
+    kdbx.set_generator("KeePassXC");
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let mut unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.generator(), "KeePassXC");
+
+    unlocked.set_name("Renamed");
+    let mut output_buf = Vec::new();
+    unlocked.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.generator(), "KeePassXC");
+    Ok(())
+}
+
+#[test]
+fn round_trip_group_flags() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    let entry_uuid = entry.uuid();
+    entry.set_title(ENTRY_NAME);
+    let mut group = Group::new(GROUP_NAME);
+    assert!(group.is_expanded());
+    group.set_expanded(false);
+    group.set_default_auto_type_sequence("{PASSWORD}{ENTER}");
+    group.set_enable_auto_type(Some(false));
+    group.set_last_top_visible_entry(Some(entry_uuid));
+    group.add_entry(entry);
+    kdbx.add_group(group);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let group = unlocked.find_group(|g| g.name() == GROUP_NAME).unwrap();
+    assert!(!group.is_expanded());
+    assert_eq!(group.default_auto_type_sequence(), "{PASSWORD}{ENTER}");
+    assert_eq!(group.enable_auto_type(), Some(false));
+    assert_eq!(group.enable_searching(), None);
+    assert_eq!(group.last_top_visible_entry(), Some(entry_uuid));
+    assert!(unlocked.root().is_expanded());
+    Ok(())
+}
+
+#[test]
+fn round_trip_deleted_objects() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::database::DeletedObject;
+
+    let mut kdbx = fast_kdbx()?;
+    let deletion_time = chrono::NaiveDate::from_ymd_opt(2022, 1, 2)
+        .unwrap()
+        .and_hms_opt(3, 4, 5)
+        .unwrap();
+    kdbx.deleted_objects_mut().push(DeletedObject {
+        uuid: uuid::Uuid::new_v4(),
+        deletion_time,
+    });
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.deleted_objects(), kdbx.deleted_objects());
+    assert_eq!(unlocked.root(), kdbx.root());
+    Ok(())
+}
+
+#[test]
+fn resave_without_changes_is_stable() -> Result<(), kdbx_rs::Error> {
+    let mut file_path = std::path::PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("kdbx4-argon2d.kdbx");
+    let key = CompositeKey::from_password("kdbxrs");
+
+    let unlocked = kdbx_rs::open(&file_path)?.unlock(&key)?;
+    let mut first_save = Vec::new();
+    unlocked.write(&mut first_save)?;
+
+    let mut reopened = kdbx_rs::from_reader(&*first_save)?.unlock(&key)?;
+    let mut second_save = Vec::new();
+    reopened.write(&mut second_save)?;
+    assert_eq!(first_save, second_save);
+
+    // A changed inner header is written out fresh
+    reopened.inner_header_mut().inner_stream_key = vec![0x42; 64];
+    let mut changed_save = Vec::new();
+    reopened.write(&mut changed_save)?;
+    assert_ne!(changed_save, second_save);
+    let reparsed = kdbx_rs::from_reader(&*changed_save)?.unlock(&key)?;
+    assert_eq!(reparsed.inner_header().inner_stream_key, vec![0x42; 64]);
+    assert_eq!(reparsed.root(), unlocked.root());
+    Ok(())
+}
+
+#[test]
+fn auto_history_snapshots_once_per_save() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    let uuid = entry.uuid();
+    kdbx.set_auto_history(true);
+    kdbx.add_entry(entry);
+
+    let entry = kdbx.entry_by_uuid_mut(uuid).unwrap();
+    entry.set_password("one");
+    entry.set_password("two");
+    assert_eq!(entry.history().len(), 1);
+
+    let dir = std::env::temp_dir().join(format!("kdbx-rs-save-history-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    kdbx.save(dir.join("history.kdbx"))?;
+    std::fs::remove_dir_all(&dir).unwrap();
+    kdbx.mark_saved();
+    let entry = kdbx.entry_by_uuid_mut(uuid).unwrap();
+    entry.set_password("three");
+    entry.set_username("user");
+    assert_eq!(entry.history().len(), 2);
+    assert_eq!(entry.history().get(1).unwrap().password(), Some("two"));
+    Ok(())
+}
+
+#[test]
+fn auto_history_only_reset_by_mark_saved() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    let uuid = entry.uuid();
+    kdbx.set_auto_history(true);
+    kdbx.add_entry(entry);
+    kdbx.entry_by_uuid_mut(uuid).unwrap().set_password("one");
+
+    let dir = std::env::temp_dir().join(format!("kdbx-rs-write-history-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    kdbx.save(dir.join("history.kdbx"))?;
+    std::fs::remove_dir_all(&dir).unwrap();
+    kdbx.to_bytes()?;
+    kdbx.roundtrip_check()?;
+    kdbx.entry_by_uuid_mut(uuid).unwrap().set_password("two");
+    assert_eq!(kdbx.entry_by_uuid(uuid).unwrap().history().len(), 1);
+
+    let copy = kdbx.entry_by_uuid(uuid).unwrap().clone();
+    kdbx.mark_saved();
+    assert_ne!(kdbx.entry_by_uuid(uuid).unwrap(), &copy);
+    kdbx.entry_by_uuid_mut(uuid).unwrap().set_password("three");
+    assert_eq!(kdbx.entry_by_uuid(uuid).unwrap().history().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn public_custom_data_round_trip() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::{VariantDict, VariantDictValue};
+
+    let mut kdbx = fast_kdbx()?;
+    let mut custom_data = VariantDict::new();
+    custom_data.insert(
+        "Plugin.Setting".to_string(),
+        VariantDictValue::Boolean(true),
+    );
+    kdbx.header_mut().set_public_custom_data(&custom_data);
+
+    let locked = kdbx_rs::from_bytes(&kdbx.to_bytes()?)?;
+    assert_eq!(locked.header().public_custom_data(), Some(custom_data));
+    locked.unlock(&key())?;
+    Ok(())
+}
+
+#[test]
+fn max_field_length_truncates_on_write() -> Result<(), kdbx_rs::Error> {
+    use kdbx_rs::binary::FieldLengthPolicy;
+    use kdbx_rs::errors::WriteError;
+
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.add_field(Field::new("Notes", &"é".repeat(5 * 1024)));
+    let uuid = entry.uuid();
+    kdbx.add_entry(entry);
+    kdbx.set_max_field_length(Some(1024));
+
+    let over_limit = kdbx.fields_over_limit();
+    assert_eq!(over_limit.len(), 1);
+    assert_eq!(over_limit[0].entry_uuid, uuid);
+    assert_eq!(over_limit[0].key, "Notes");
+    assert_eq!(over_limit[0].len, 10 * 1024);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let notes = unlocked
+        .entry_by_uuid(uuid)
+        .unwrap()
+        .find("Notes")
+        .and_then(|f| f.value())
+        .unwrap();
+    assert_eq!(notes, "é".repeat(512));
+    assert_eq!(
+        unlocked.entry_by_uuid(uuid).unwrap().title(),
+        Some(ENTRY_NAME)
+    );
+    // The database in memory keeps the full value
+    assert_eq!(kdbx.fields_over_limit().len(), 1);
+
+    kdbx.set_field_length_policy(FieldLengthPolicy::Error);
+    let err = kdbx.write(&mut Vec::new()).unwrap_err();
+    assert!(matches!(err, WriteError::FieldTooLong { len, max_len: 1024, .. } if len == 10 * 1024));
+    Ok(())
+}
+
+#[test]
+fn round_trip_attachments() -> Result<(), kdbx_rs::Error> {
+    let attachment = b"Small attached file\n".to_vec();
+    let mut kdbx = fast_kdbx()?;
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.add_attachment("notes.txt", attachment.clone());
+    entry.new_version();
+    entry.add_attachment("other.bin", vec![0, 1, 2, 3]);
+    let uuid = entry.uuid();
+    kdbx.add_entry(entry);
+
+    let binaries = kdbx.binaries();
+    assert_eq!(binaries.len(), 2);
+    assert_eq!(binaries[0].data, attachment);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let entry = unlocked.entry_by_uuid(uuid).unwrap();
+    let names: Vec<_> = entry.attachments().map(|a| a.name()).collect();
+    assert_eq!(names, vec!["notes.txt", "other.bin"]);
+    assert_eq!(
+        entry.attachment("notes.txt").unwrap().data(),
+        &attachment[..]
+    );
+    let old_version = entry.history().get(0).unwrap();
+    assert_eq!(old_version.attachments().count(), 1);
+    assert!(unlocked.parse_warnings().is_empty());
+    // Binaries are moved out of the inner header's unknown headers
+    assert!(unlocked.inner_header().other_headers.is_empty());
+    assert_eq!(unlocked.root(), kdbx.root());
+
+    let mut unlocked = unlocked;
+    assert!(unlocked
+        .entry_by_uuid_mut(uuid)
+        .unwrap()
+        .remove_attachment("other.bin"));
+    // Still used by history, so only the removed attachment's binary goes
+    assert_eq!(unlocked.binaries().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn attachments_share_identical_binaries() -> Result<(), kdbx_rs::Error> {
+    let attachment = b"Shared attachment".to_vec();
+    let mut kdbx = fast_kdbx()?;
+    let mut uuids = Vec::new();
+    for name in ["first.txt", "second.txt"] {
+        let mut entry = Entry::default();
+        entry.add_attachment(name, attachment.clone());
+        uuids.push(entry.uuid());
+        kdbx.add_entry(entry);
+    }
+    assert_eq!(kdbx.binaries().len(), 1);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    assert_eq!(unlocked.binaries().len(), 1);
+    for uuid in uuids {
+        let entry = unlocked.entry_by_uuid(uuid).unwrap();
+        assert_eq!(entry.attachments().next().unwrap().data(), &attachment[..]);
+    }
+    Ok(())
+}
+
+#[test]
+fn inner_header_binaries() {
+    use kdbx_rs::binary::{InnerBinary, KdbxInnerHeader};
+
+    let mut header = KdbxInnerHeader::from_os_random();
+    assert!(header.binaries().is_empty());
+    let binaries = vec![
+        InnerBinary {
+            protected: true,
+            data: b"secret".to_vec(),
+        },
+        InnerBinary {
+            protected: false,
+            data: Vec::new(),
+        },
+    ];
+    header.set_binaries(binaries.clone());
+    assert_eq!(header.other_headers.len(), 2);
+    assert_eq!(header.binaries(), binaries);
+
+    header.set_binaries(binaries[1..].to_vec());
+    assert_eq!(header.binaries(), &binaries[1..]);
+}
+
+#[test]
+fn round_trip_attachments_kdbx3() -> Result<(), kdbx_rs::Error> {
+    let mut kdbx = fast_kdbx()?;
+    kdbx.set_major_version(3);
+    kdbx.set_key(key())?;
+    let mut entry = Entry::default();
+    entry.add_attachment("notes.txt", b"KDBX 3 attachment".to_vec());
+    let uuid = entry.uuid();
+    kdbx.add_entry(entry);
+
+    let mut output_buf = Vec::new();
+    kdbx.write(&mut output_buf)?;
+    let unlocked = kdbx_rs::from_reader(&*output_buf)?.unlock(&key())?;
+    let entry = unlocked.entry_by_uuid(uuid).unwrap();
+    assert_eq!(
+        entry.attachment("notes.txt").unwrap().data(),
+        b"KDBX 3 attachment"
+    );
+    Ok(())
+}
+
+#[test]
+fn save_and_save_atomic() -> Result<(), kdbx_rs::Error> {
+    let kdbx = fast_kdbx()?;
+    let dir = std::env::temp_dir().join(format!("kdbx-rs-save-plain-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("saved.kdbx");
+    kdbx.save(&path)?;
+    let reopened = kdbx_rs::open(&path)?.unlock(&key())?;
+    assert_eq!(reopened.name(), DATABASE_NAME);
+
+    let atomic_path = dir.join("atomic.kdbx");
+    kdbx.save_atomic(&atomic_path)?;
+    kdbx.save_atomic(&atomic_path)?;
+    let reopened = kdbx_rs::open(&atomic_path)?.unlock(&key())?;
+    assert_eq!(reopened.name(), DATABASE_NAME);
+    // No temporary files are left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn save_atomic_keeps_permissions() -> Result<(), kdbx_rs::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let kdbx = fast_kdbx()?;
+    let dir = std::env::temp_dir().join(format!("kdbx-rs-save-mode-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("private.kdbx");
+    std::fs::write(&path, b"").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    kdbx.save_atomic(&path)?;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    Ok(())
+}
+
+#[test]
+fn round_trip_bytes() -> Result<(), kdbx_rs::Error> {
+    let kdbx = fast_kdbx()?;
+    let bytes = kdbx.to_bytes()?;
+    let reopened = kdbx_rs::from_bytes(&bytes)?;
+    assert_eq!(reopened.to_bytes()?, bytes);
+    let unlocked = reopened.unlock(&key())?;
+    assert_eq!(unlocked.name(), DATABASE_NAME);
+    Ok(())
+}
+
+#[test]
+fn set_seeds_reproducible() -> Result<(), kdbx_rs::Error> {
+    let mut db = kdbx_rs::Database::default();
+    db.set_name(DATABASE_NAME);
+    let mut entry = Entry::default();
+    entry.set_title(ENTRY_NAME);
+    entry.set_password(ENTRY_PASSWORD);
+    db.root_mut().add_entry(entry);
+
+    let write_seeded = |db: kdbx_rs::Database| -> Result<Vec<u8>, kdbx_rs::Error> {
+        let mut kdbx = Kdbx::from_database(db);
+        if let KdfParams::Argon2 {
+            memory_bytes,
+            iterations,
+            ..
+        } = &mut kdbx.header_mut().kdf_params
+        {
+            *memory_bytes = 1024 * 1024;
+            *iterations = 1;
+        }
+        let iv = vec![2; kdbx.header().encryption_iv.len()];
+        let stream_key = vec![4; kdbx.inner_header().inner_stream_key.len()];
+        kdbx.set_seeds(&[1; 32], &iv, &[3; 32], &stream_key);
+        kdbx.set_key(key())?;
+        Ok(kdbx.to_bytes()?)
+    };
+    let first = write_seeded(db.clone())?;
+    let second = write_seeded(db.clone())?;
+    assert_eq!(first, second);
+
+    let unlocked = kdbx_rs::from_bytes(&first)?.unlock(&key())?;
+    assert_eq!(unlocked.header().master_seed, vec![1; 32]);
+    assert_eq!(unlocked.root(), db.root());
+    Ok(())
+}
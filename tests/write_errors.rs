@@ -0,0 +1,59 @@
+use kdbx_rs::binary::KdfParams;
+use kdbx_rs::errors::WriteError;
+use kdbx_rs::{CompositeKey, Database, Kdbx};
+use std::io::{self, Write};
+
+/// Writer that accepts a fixed number of bytes then fails
+struct FailingWriter {
+    remaining: usize,
+}
+
+impl Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > self.remaining {
+            return Err(io::Error::new(io::ErrorKind::Other, "disk full"));
+        }
+        self.remaining -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn kdbx() -> Result<Kdbx<kdbx_rs::binary::Unlocked>, kdbx_rs::Error> {
+    let mut kdbx = Kdbx::from_database(Database::default());
+    if let KdfParams::Argon2 {
+        memory_bytes,
+        iterations,
+        ..
+    } = &mut kdbx.header_mut().kdf_params
+    {
+        *memory_bytes = 1024 * 1024;
+        *iterations = 1;
+    }
+    kdbx.set_key(CompositeKey::from_password("kdbxrs"))?;
+    Ok(kdbx)
+}
+
+#[test]
+fn failing_header_write_is_io_error() -> Result<(), kdbx_rs::Error> {
+    let result = kdbx()?.write(FailingWriter { remaining: 0 });
+    assert!(matches!(result, Err(WriteError::Io(_))));
+    Ok(())
+}
+
+#[test]
+fn failing_data_write_is_hmac_error() -> Result<(), kdbx_rs::Error> {
+    let kdbx = kdbx()?;
+    let mut output = Vec::new();
+    kdbx.write(&mut output)?;
+
+    // Enough space for the header, but not the encrypted data blocks
+    let result = kdbx.write(FailingWriter {
+        remaining: output.len() - 64,
+    });
+    assert!(matches!(result, Err(WriteError::Hmac(_))));
+    Ok(())
+}
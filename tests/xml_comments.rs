@@ -0,0 +1,57 @@
+use kdbx_rs::utils::NullStreamCipher;
+use kdbx_rs::xml::{parse_xml, parse_xml_with_options, write_xml, ParseOptions};
+
+const XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<!-- Edited by hand -->
+	<Meta>
+		<!-- Meta comment -->
+		<DatabaseName>Commented</DatabaseName>
+	</Meta>
+	<Root>
+		<Group>
+			<!-- Group comment -->
+			<Name>Root</Name>
+			<Entry>
+				<!-- Entry comment -->
+				<String><Key>Title</Key><Value>Bar</Value></String>
+			</Entry>
+		</Group>
+	</Root>
+</KeePassFile>"#;
+
+fn round_trip(options: &ParseOptions) -> String {
+    let db = parse_xml_with_options(XML.as_bytes(), &mut NullStreamCipher, options).unwrap();
+    let mut output = Vec::new();
+    write_xml(&mut output, &db, &mut NullStreamCipher).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn comments_preserved_when_requested() {
+    let options = ParseOptions {
+        preserve_comments: true,
+        ..Default::default()
+    };
+    let output = round_trip(&options);
+    let meta_comment = output.find("<!-- Edited by hand -->").unwrap();
+    assert!(meta_comment < output.find("<Meta>").unwrap());
+    assert!(output.contains("<!-- Meta comment -->"));
+    assert!(output.contains("<!-- Group comment -->"));
+    assert!(output.contains("<!-- Entry comment -->"));
+
+    let reparsed =
+        parse_xml_with_options(output.as_bytes(), &mut NullStreamCipher, &options).unwrap();
+    let mut rewritten = Vec::new();
+    write_xml(&mut rewritten, &reparsed, &mut NullStreamCipher).unwrap();
+    assert_eq!(String::from_utf8(rewritten).unwrap(), output);
+}
+
+#[test]
+fn comments_dropped_by_default() {
+    let output = round_trip(&ParseOptions::default());
+    assert!(!output.contains("<!--"));
+
+    let db = parse_xml(XML.as_bytes(), &mut NullStreamCipher).unwrap();
+    assert_eq!(db.name(), "Commented");
+}
@@ -0,0 +1,98 @@
+use kdbx_rs::utils::NullStreamCipher;
+use kdbx_rs::xml::{parse_xml, ParseWarning};
+
+#[test]
+fn empty_root_gets_default_group() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta><DatabaseName>Empty</DatabaseName></Meta>
+	<Root></Root>
+</KeePassFile>"#;
+    let db = parse_xml(xml.as_bytes(), &mut NullStreamCipher).unwrap();
+    assert_eq!(db.parse_warnings(), &[ParseWarning::EmptyRoot]);
+    assert_eq!(db.root().entries().count(), 0);
+    assert_eq!(db.root().groups().count(), 0);
+}
+
+#[test]
+fn first_meta_is_kept() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta><DatabaseName>First</DatabaseName></Meta>
+	<Meta><DatabaseName>Second</DatabaseName></Meta>
+	<Root><Group><Name>Root</Name></Group></Root>
+</KeePassFile>"#;
+    let db = parse_xml(xml.as_bytes(), &mut NullStreamCipher).unwrap();
+    assert_eq!(db.name(), "First");
+    assert_eq!(db.parse_warnings(), &[ParseWarning::DuplicateMeta]);
+}
+
+#[test]
+fn padding_before_xml_is_skipped() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta><DatabaseName>Padded</DatabaseName></Meta>
+	<Root><Group><Name>Root</Name></Group></Root>
+</KeePassFile>"#;
+    let mut padded = vec![0u8, 0, 0, b'\n', 0];
+    padded.extend_from_slice(xml.as_bytes());
+    let db = parse_xml(&*padded, &mut NullStreamCipher).unwrap();
+    assert_eq!(db.name(), "Padded");
+    assert!(db.parse_warnings().is_empty());
+}
+
+#[test]
+fn lowercase_binary_ref_is_resolved() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta>
+		<Binaries><Binary ID="0">YXR0YWNoZWQ=</Binary></Binaries>
+	</Meta>
+	<Root><Group><Name>Root</Name>
+		<Entry>
+			<Binary><Key>named.txt</Key><Value ref="0"/></Binary>
+			<Binary><Value Ref="0"/></Binary>
+		</Entry>
+	</Group></Root>
+</KeePassFile>"#;
+    let db = parse_xml(xml.as_bytes(), &mut NullStreamCipher).unwrap();
+    let entry = db.root().entries().next().unwrap();
+    let names: Vec<_> = entry.attachments().map(|a| a.name()).collect();
+    assert_eq!(names, vec!["named.txt", "0"]);
+    assert_eq!(entry.attachment("named.txt").unwrap().data(), b"attached");
+    assert!(db.parse_warnings().is_empty());
+}
+
+#[test]
+fn missing_memory_protection_uses_keepass_defaults() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta><DatabaseName>Unprotected</DatabaseName></Meta>
+	<Root><Group><Name>Root</Name></Group></Root>
+</KeePassFile>"#;
+    let db = parse_xml(xml.as_bytes(), &mut NullStreamCipher).unwrap();
+    let protection = &db.meta().memory_protection;
+    assert!(protection.protect_password);
+    assert!(!protection.protect_title);
+    assert!(!protection.protect_notes);
+}
+
+#[test]
+fn wrapped_base64_protected_value_is_decoded() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta><DatabaseName>Wrapped</DatabaseName></Meta>
+	<Root><Group><Name>Root</Name>
+		<Entry>
+			<String><Key>Password</Key><Value Protected="True">aHVu
+				dGVy
+				Mj8+</Value></String>
+			<String><Key>UrlSafe</Key><Value Protected="True">aHVudGVyMj8-</Value></String>
+		</Entry>
+	</Group></Root>
+</KeePassFile>"#;
+    let db = parse_xml(xml.as_bytes(), &mut NullStreamCipher).unwrap();
+    let entry = db.root().entries().next().unwrap();
+    assert_eq!(entry.password(), Some("hunter2?>"));
+    assert_eq!(entry.find("UrlSafe").unwrap().value(), Some("hunter2?>"));
+}
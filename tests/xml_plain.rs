@@ -0,0 +1,32 @@
+use kdbx_rs::database::doc_sample_db;
+use kdbx_rs::xml::{read_plain_xml, write_plain_xml};
+use std::fs::File;
+use std::path::PathBuf;
+
+#[test]
+fn read_keepass_xml_export() {
+    let mut file_path = PathBuf::new();
+    file_path.push(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("res");
+    file_path.push("test_input");
+    file_path.push("keepass-export.xml");
+
+    let db = read_plain_xml(File::open(file_path).unwrap()).unwrap();
+    assert_eq!(db.name(), "Exported");
+    assert!(db.meta().memory_protection.protect_password);
+    let entry = db.root().entries().next().unwrap();
+    assert_eq!(entry.title(), Some("Sample Entry"));
+    assert_eq!(entry.username(), Some("User Name"));
+    assert_eq!(entry.password(), Some("kdbxrs"));
+    assert!(entry.find("Password").unwrap().protected());
+    assert!(!entry.find("Title").unwrap().protected());
+}
+
+#[test]
+fn plain_xml_round_trip() {
+    let db = doc_sample_db();
+    let mut xml = Vec::new();
+    write_plain_xml(&mut xml, &db).unwrap();
+    let read = read_plain_xml(xml.as_slice()).unwrap();
+    assert_eq!(read.root(), db.root());
+}
@@ -0,0 +1,77 @@
+use kdbx_rs::utils::NullStreamCipher;
+use kdbx_rs::xml::{parse_xml, write_xml};
+
+const XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta>
+		<DatabaseName>Extended</DatabaseName>
+		<HeaderHash>AAAA</HeaderHash>
+		<Color>#FF0000</Color>
+		<PluginSettings Version="2"><Option Name="sync">on</Option></PluginSettings>
+	</Meta>
+	<Root>
+		<Group>
+			<Name>Root</Name>
+			<Notes>Group notes</Notes>
+			<Entry>
+				<String><Key>Title</Key><Value>Bar</Value></String>
+				<Tags>work;mail</Tags>
+				<AutoType><Enabled>True</Enabled><Association><Window>Login*</Window></Association></AutoType>
+			</Entry>
+		</Group>
+	</Root>
+</KeePassFile>"#;
+
+fn write(db: &kdbx_rs::Database) -> String {
+    let mut output = Vec::new();
+    write_xml(&mut output, db, &mut NullStreamCipher).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn unknown_elements_round_trip() {
+    let db = parse_xml(XML.as_bytes(), &mut NullStreamCipher).unwrap();
+    let entry = db.root().entries().next().unwrap();
+    assert_eq!(entry.title(), Some("Bar"));
+    let output = write(&db);
+
+    assert!(output.contains("<Color>#FF0000</Color>"));
+    assert!(output.contains(r#"<PluginSettings Version="2">"#));
+    assert!(output.contains(r#"<Option Name="sync">on</Option>"#));
+    assert!(output.contains("<Notes>Group notes</Notes>"));
+    assert!(output.contains("<Tags>work;mail</Tags>"));
+    assert!(output.contains("<Window>Login*</Window>"));
+    assert!(!output.contains("HeaderHash"));
+
+    let reparsed = parse_xml(output.as_bytes(), &mut NullStreamCipher).unwrap();
+    assert_eq!(reparsed.root(), db.root());
+    assert_eq!(write(&reparsed), output);
+}
+
+#[test]
+fn plugin_data_keys_reported() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+	<Meta>
+		<CustomData>
+			<Item><Key>KPXC_DECRYPTION_TIME_PREFERENCE</Key><Value>1000</Value></Item>
+		</CustomData>
+	</Meta>
+	<Root><Group><Name>Root</Name>
+		<Entry>
+			<CustomData>
+				<Item><Key>KPXC_BROWSER_SETTINGS</Key><Value>{}</Value></Item>
+			</CustomData>
+		</Entry>
+	</Group></Root>
+</KeePassFile>"#;
+    let db = parse_xml(XML.as_bytes(), &mut NullStreamCipher).unwrap();
+    assert!(!db.has_plugin_data());
+
+    let db = parse_xml(xml.as_bytes(), &mut NullStreamCipher).unwrap();
+    assert!(db.has_plugin_data());
+    assert_eq!(
+        db.plugin_data_keys(),
+        vec!["KPXC_BROWSER_SETTINGS", "KPXC_DECRYPTION_TIME_PREFERENCE"]
+    );
+}